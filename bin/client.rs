@@ -1,11 +1,14 @@
-use std::path::PathBuf;
+use std::{path::PathBuf, time::Duration};
 
 use alloy::primitives::Address;
+use blst::min_pk::SecretKey as BlsSecretKey;
 use clap::Parser;
 use eyre::{bail, eyre};
 use url::Url;
 
-use dato::{Client, FilesystemRegistry, Registry, SmartContractRegistry};
+use dato::{
+    Client, FilesystemRegistry, Registry, SmartContractRegistry, DEFAULT_RECONCILE_INTERVAL,
+};
 
 #[derive(Debug, Parser)]
 struct CliOpts {
@@ -27,6 +30,17 @@ struct CliOpts {
     pub registry_path: Option<PathBuf>,
     #[clap(long, env = "DATO_API_PORT", default_value = "12440")]
     pub api_port: u16,
+    /// How often to reconcile the validator set with the registry, in seconds.
+    #[clap(
+        long,
+        env = "DATO_RECONCILE_INTERVAL",
+        default_value_t = DEFAULT_RECONCILE_INTERVAL.as_secs()
+    )]
+    pub reconcile_interval: u64,
+    /// The hex-encoded BLS secret key to sign subscribe requests with, for validators to
+    /// check against the subscribers allowed for private namespaces. Random if not given.
+    #[clap(long, env = "DATO_SUBSCRIBER_KEY", value_parser = parse_secret_key)]
+    pub subscriber_key: Option<BlsSecretKey>,
 }
 
 impl CliOpts {
@@ -36,6 +50,8 @@ impl CliOpts {
             execution_client_url: None,
             registry_address: None,
             api_port: 0,
+            reconcile_interval: DEFAULT_RECONCILE_INTERVAL.as_secs(),
+            subscriber_key: None,
             registry_path: Some("registry.txt".parse()?),
         })
     }
@@ -46,7 +62,8 @@ async fn main() -> eyre::Result<()> {
     let _ = tracing_subscriber::fmt::try_init();
     let opts = CliOpts::parse();
 
-    let registry: Box<dyn Registry> = if let Some(registry_path) = opts.registry_path {
+    let registry: Box<dyn Registry + Send + Sync> = if let Some(registry_path) = opts.registry_path
+    {
         Box::new(FilesystemRegistry::read_from_file(registry_path)?)
     } else if let Some(registry_addr) = opts.registry_address {
         let el_url = opts.execution_client_url.ok_or(eyre!("Missing Execution client URL"))?;
@@ -56,15 +73,26 @@ async fn main() -> eyre::Result<()> {
     };
 
     let mut client = Client::new();
-
-    // Iterate over the validators and connect to each one
-    for validator in registry.all_validators().await? {
-        client.connect_validator(validator.identity(), validator.socket).await?;
+    if let Some(subscriber_key) = opts.subscriber_key {
+        client = client.with_subscriber_key(subscriber_key);
     }
 
+    // Connect to the validators of the current epoch
+    let epoch = registry.epoch().await?;
+    client.reconcile(epoch, &registry.validators_at(epoch).await?).await;
+
+    // Keep the validator set up to date with the registry
+    client.spawn_reconciler(registry, Duration::from_secs(opts.reconcile_interval));
+
     let handle = client.run_api(opts.api_port).await?;
 
     handle.await?;
 
     Ok(())
 }
+
+/// Parses a hex-encoded BLS secret key.
+fn parse_secret_key(secret_key: &str) -> eyre::Result<BlsSecretKey> {
+    BlsSecretKey::from_bytes(&alloy::hex::decode(secret_key)?)
+        .map_err(|e| eyre::eyre!("Invalid secret key: {:?}", e))
+}
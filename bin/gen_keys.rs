@@ -3,6 +3,7 @@
 //! - Private BLS key hex-encoded
 //! - Public BLS key hex-encoded
 //! - Validator DNS name in the expected Docker network setup
+//! - Proof of possession of the private BLS key hex-encoded
 //!
 //! The goal of using a file-based registry is to quickly simulate a discovery process
 //! to test DATO in a local Docker network.
@@ -10,7 +11,7 @@
 use std::{fs::File, io::Write};
 
 use alloy::hex::encode_prefixed;
-use dato::bls::random_bls_secret;
+use dato::bls::{proof_of_possession, random_bls_secret};
 
 fn main() -> eyre::Result<()> {
     let mut f = File::create("registry.txt").unwrap();
@@ -18,9 +19,10 @@ fn main() -> eyre::Result<()> {
     for i in 0..1000 {
         let privkey = random_bls_secret();
         let pubkey = encode_prefixed(privkey.sk_to_pk().to_bytes());
+        let proof = encode_prefixed(proof_of_possession(&privkey).to_bytes());
         let privkey = encode_prefixed(privkey.to_bytes());
 
-        let line = format!("{i},{privkey},{pubkey},dato-validator-{i}:8222\n");
+        let line = format!("{i},{privkey},{pubkey},dato-validator-{i}:8222,{proof}\n");
 
         f.write_all(line.as_bytes())?;
     }
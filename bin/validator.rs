@@ -1,7 +1,17 @@
-use blst::min_pk::SecretKey as BlsSecretKey;
+use std::{path::PathBuf, time::Duration};
+
+use alloy::{
+    primitives::{Address, U256},
+    signers::local::PrivateKeySigner,
+};
+use blst::min_pk::{PublicKey as BlsPublicKey, SecretKey as BlsSecretKey};
 use clap::{Parser, ValueEnum};
+use url::Url;
 
-use dato::Validator;
+use dato::{
+    AdmissionPolicy, DataStore, FilesystemRegistry, FilesystemStore, InMemoryStore, Namespace,
+    Registry, RegistryOperator, RetentionPolicy, SmartContractRegistry, Validator,
+};
 use tracing::info;
 
 #[derive(Debug, Parser)]
@@ -17,6 +27,11 @@ enum SubCommand {
     /// Register a new validator to the on-chain registry contract.
     /// This command should be run once per individual validator instance.
     Register(RegisterOpts),
+    /// Deposit more stake for a registered validator in the on-chain registry contract.
+    DepositStake(DepositStakeOpts),
+    /// Withdraw all of the stake of a registered validator from the on-chain registry
+    /// contract, which removes it from the validator set.
+    Withdraw(WithdrawOpts),
 }
 
 #[derive(Debug, Parser)]
@@ -27,6 +42,82 @@ struct RunOpts {
     pub secret_key: String,
     #[clap(long, env = "DATO_VAL_BACKEND", default_value = "in-memory")]
     pub backend: BackendType,
+    /// The directory to store the log records in, when using the filesystem backend.
+    #[clap(long, env = "DATO_VAL_DATA_DIR", default_value = "./data")]
+    pub data_dir: PathBuf,
+    /// The retention policy of namespaces without their own policy, as comma-separated limits,
+    /// e.g. `records=4096,bytes=1048576,age=3600` (age in seconds). The in-memory backend keeps
+    /// at most 4096 records per namespace unless a policy sets `records`, and the filesystem
+    /// backend keeps every record by default.
+    #[clap(long, env = "DATO_VAL_DEFAULT_RETENTION", value_parser = parse_retention)]
+    pub default_retention: Option<RetentionPolicy>,
+    /// The retention policy of a namespace, e.g. `telemetry:age=600`. Can be repeated.
+    #[clap(long = "retention", value_parser = parse_namespace_retention)]
+    pub retention: Vec<(Namespace, RetentionPolicy)>,
+    /// How long to accumulate writes for, in milliseconds, to sign them together with a
+    /// single signature. Each write is signed on its own if 0.
+    #[clap(long, env = "DATO_VAL_SIGNING_WINDOW_MS", default_value = "0")]
+    pub signing_window_ms: u64,
+    /// The registry contract to find the other validators to sync missed records with.
+    #[clap(
+        long,
+        env = "DATO_EL_URL",
+        conflicts_with = "registry_path",
+        requires = "registry_address"
+    )]
+    pub execution_client_url: Option<Url>,
+    #[clap(
+        long,
+        env = "DATO_REGISTRY_ADDRESS",
+        conflicts_with = "registry_path",
+        requires = "execution_client_url"
+    )]
+    pub registry_address: Option<Address>,
+    /// The registry file to find the other validators to sync missed records with.
+    #[clap(long, env = "DATO_REGISTRY_PATH", conflicts_with = "registry_address")]
+    pub registry_path: Option<PathBuf>,
+    /// How often to sync missed records with the other validators, in seconds. Syncing is
+    /// disabled if no registry is given.
+    #[clap(long, env = "DATO_VAL_SYNC_INTERVAL", default_value = "30")]
+    pub sync_interval: u64,
+    /// Backfill the records written before the validator joined from the other validators.
+    /// Requires a registry.
+    #[clap(long, env = "DATO_VAL_BACKFILL")]
+    pub backfill: bool,
+    /// The maximum size of a message to write, in bytes.
+    #[clap(long, env = "DATO_VAL_MAX_MESSAGE_BYTES", default_value = "1048576")]
+    pub max_message_bytes: usize,
+    /// The maximum length of a namespace to write to or subscribe to, in bytes.
+    #[clap(long, env = "DATO_VAL_MAX_NAMESPACE_LEN", default_value = "256")]
+    pub max_namespace_len: usize,
+    /// A pattern of the namespaces to accept, where `*` matches anything, e.g. `telemetry/*`.
+    /// Can be repeated. Any namespace is accepted if none is given.
+    #[clap(long = "namespace-pattern", env = "DATO_VAL_NAMESPACE_PATTERNS", value_delimiter = ',')]
+    pub namespace_patterns: Vec<String>,
+    /// The maximum number of namespaces to hold records for or publish to subscribers of.
+    /// Writes and subscriptions to new namespaces are rejected once it is reached.
+    #[clap(long, env = "DATO_VAL_MAX_NAMESPACES", default_value = "65536")]
+    pub max_namespaces: usize,
+    /// A pattern of namespaces that only the given subscribers can subscribe to, as their
+    /// hex-encoded BLS public keys, e.g. `private/*:0xab..,0xcd..`. Can be repeated. Other
+    /// namespaces can be subscribed to by anyone.
+    #[clap(long = "private-namespace", value_parser = parse_private_namespace)]
+    pub private_namespaces: Vec<(String, Vec<BlsPublicKey>)>,
+}
+
+impl RunOpts {
+    /// Returns the registry to find the other validators in, if one is given.
+    fn registry(&self) -> eyre::Result<Option<Box<dyn Registry + Send + Sync>>> {
+        if let Some(registry_path) = &self.registry_path {
+            Ok(Some(Box::new(FilesystemRegistry::read_from_file(registry_path.clone())?)))
+        } else if let (Some(el_url), Some(registry_address)) =
+            (&self.execution_client_url, self.registry_address)
+        {
+            Ok(Some(Box::new(SmartContractRegistry::new(el_url.clone(), registry_address))))
+        } else {
+            Ok(None)
+        }
+    }
 }
 
 #[derive(Debug, Clone, Parser, ValueEnum)]
@@ -37,10 +128,53 @@ pub enum BackendType {
     Filesystem,
 }
 
+/// The options to send transactions to the on-chain registry contract.
+#[derive(Debug, Parser)]
+struct RegistryOpts {
+    #[clap(long, env = "DATO_EL_URL")]
+    pub execution_client_url: Url,
+    #[clap(long, env = "DATO_REGISTRY_ADDRESS")]
+    pub registry_address: Address,
+    /// The ECDSA private key of the account that owns the registration and the stake.
+    #[clap(long, env = "DATO_VAL_ECDSA_KEY")]
+    pub ecdsa_key: PrivateKeySigner,
+}
+
+impl RegistryOpts {
+    fn operator(self) -> RegistryOperator {
+        RegistryOperator::new(self.execution_client_url, self.registry_address, self.ecdsa_key)
+    }
+}
+
 #[derive(Debug, Parser)]
 struct RegisterOpts {
+    #[clap(flatten)]
+    pub registry: RegistryOpts,
+    /// The BLS secret key of the validator, from which the registered public key and its
+    /// proof of possession are derived.
+    #[clap(long, env = "DATO_VAL_SECRET_KEY")]
+    pub secret_key: String,
+    /// The public socket address at which clients can reach the validator.
+    #[clap(long, env = "DATO_VAL_SOCKET")]
+    pub socket: String,
+    /// The stake to deposit, in wei.
     #[clap(long)]
-    pub pubkey: String,
+    pub stake: U256,
+}
+
+#[derive(Debug, Parser)]
+struct DepositStakeOpts {
+    #[clap(flatten)]
+    pub registry: RegistryOpts,
+    /// The amount to deposit, in wei.
+    #[clap(long)]
+    pub amount: U256,
+}
+
+#[derive(Debug, Parser)]
+struct WithdrawOpts {
+    #[clap(flatten)]
+    pub registry: RegistryOpts,
 }
 
 #[tokio::main]
@@ -50,26 +184,160 @@ async fn main() -> eyre::Result<()> {
 
     match opts.cmd {
         SubCommand::Run(run_opts) => {
-            let sk = BlsSecretKey::from_bytes(&alloy::hex::decode(run_opts.secret_key)?)
-                .map_err(|e| eyre::eyre!("Invalid secret key: {:?}", e))?;
+            let sk = parse_secret_key(&run_opts.secret_key)?;
 
             match run_opts.backend {
                 BackendType::InMemory => {
                     info!("Running validator with in-memory backend on port {}", run_opts.port);
-                    Validator::new_in_memory(sk, run_opts.port).await?.await;
+                    let mut store = InMemoryStore::with_capacity(4096);
+                    if let Some(policy) = run_opts.default_retention {
+                        store = store.with_default_retention(policy);
+                    }
+                    for (namespace, policy) in &run_opts.retention {
+                        store = store.with_retention(namespace.clone(), *policy);
+                    }
+
+                    let validator = Validator::new(store, sk, run_opts.port).await?;
+                    run_validator(validator, &run_opts)?.await;
                 }
                 BackendType::Filesystem => {
                     info!("Running validator with filesystem backend on port {}", run_opts.port);
-                    todo!()
+                    let mut store = FilesystemStore::open(&run_opts.data_dir)?;
+                    if let Some(policy) = run_opts.default_retention {
+                        store = store.with_default_retention(policy);
+                    }
+                    for (namespace, policy) in &run_opts.retention {
+                        store = store.with_retention(namespace.clone(), *policy);
+                    }
+
+                    let validator = Validator::new(store, sk, run_opts.port).await?;
+                    run_validator(validator, &run_opts)?.await;
                 }
             }
         }
         SubCommand::Register(register_opts) => {
-            println!("Registering with pubkey: {}", register_opts.pubkey);
+            let secret_key = parse_secret_key(&register_opts.secret_key)?;
+            let pubkey = secret_key.sk_to_pk();
+            let operator = register_opts.registry.operator();
+
+            info!(
+                operator = %operator.address(),
+                pubkey = %alloy::hex::encode_prefixed(pubkey.compress()),
+                socket = %register_opts.socket,
+                stake = %register_opts.stake,
+                "Registering validator"
+            );
+            let receipt =
+                operator.register(&secret_key, register_opts.socket, register_opts.stake).await?;
+            println!("Registered validator in transaction {}", receipt.transaction_hash);
+        }
+        SubCommand::DepositStake(deposit_opts) => {
+            let operator = deposit_opts.registry.operator();
+
+            info!(operator = %operator.address(), amount = %deposit_opts.amount, "Depositing stake");
+            let receipt = operator.deposit_stake(deposit_opts.amount).await?;
+            println!("Deposited stake in transaction {}", receipt.transaction_hash);
+        }
+        SubCommand::Withdraw(withdraw_opts) => {
+            let operator = withdraw_opts.registry.operator();
 
-            // TODO: registration logic
+            info!(operator = %operator.address(), "Withdrawing all stake");
+            let receipt = operator.withdraw().await?;
+            println!("Withdrew stake in transaction {}", receipt.transaction_hash);
         }
     }
 
     Ok(())
 }
+
+/// Configures the validator with the given run options.
+fn run_validator<DS: DataStore + 'static>(
+    validator: Validator<DS>,
+    opts: &RunOpts,
+) -> eyre::Result<Validator<DS>> {
+    let mut admission = AdmissionPolicy::default()
+        .with_max_message_bytes(opts.max_message_bytes)
+        .with_max_namespace_len(opts.max_namespace_len)
+        .with_max_namespaces(opts.max_namespaces);
+    for pattern in &opts.namespace_patterns {
+        admission = admission.with_namespace_pattern(pattern.clone());
+    }
+
+    let mut validator = validator
+        .with_signing_window(Duration::from_millis(opts.signing_window_ms))
+        .with_admission(admission);
+
+    for (pattern, subscribers) in &opts.private_namespaces {
+        info!(pattern, subscribers = subscribers.len(), "Restricting subscriptions");
+        validator = validator.with_private_namespace(pattern.clone(), subscribers.clone());
+    }
+
+    if let Some(registry) = opts.registry()? {
+        info!(interval = opts.sync_interval, "Syncing missed records with other validators");
+        validator = validator.with_sync(registry, Duration::from_secs(opts.sync_interval));
+    } else if opts.backfill {
+        eyre::bail!("Backfilling requires a registry to find the other validators in");
+    }
+
+    if opts.backfill {
+        info!("Backfilling records written before joining");
+        validator = validator.with_backfill();
+    }
+
+    Ok(validator)
+}
+
+/// Parses a retention policy, as comma-separated `records`, `bytes` and `age` (in seconds)
+/// limits, e.g. `records=4096,age=3600`.
+fn parse_retention(policy: &str) -> eyre::Result<RetentionPolicy> {
+    let mut retention = RetentionPolicy::default();
+
+    for limit in policy.split(',') {
+        let (key, value) =
+            limit.split_once('=').ok_or_else(|| eyre::eyre!("Invalid retention limit: {limit}"))?;
+        let value = value.trim().parse::<u64>()?;
+
+        retention = match key.trim() {
+            "records" => retention.with_max_records(value),
+            "bytes" => retention.with_max_bytes(value),
+            "age" => retention.with_max_age(Duration::from_secs(value)),
+            key => eyre::bail!("Unknown retention limit: {key}"),
+        };
+    }
+
+    Ok(retention)
+}
+
+/// Parses the retention policy of a namespace, e.g. `telemetry:age=600`.
+fn parse_namespace_retention(retention: &str) -> eyre::Result<(Namespace, RetentionPolicy)> {
+    let (namespace, policy) = retention
+        .split_once(':')
+        .ok_or_else(|| eyre::eyre!("Missing namespace in retention policy: {retention}"))?;
+
+    Ok((Namespace::copy_from_slice(namespace.as_bytes()), parse_retention(policy)?))
+}
+
+/// Parses a pattern of private namespaces along with the hex-encoded BLS public keys of the
+/// subscribers allowed for them, e.g. `private/*:0xab..,0xcd..`.
+fn parse_private_namespace(private: &str) -> eyre::Result<(String, Vec<BlsPublicKey>)> {
+    // Public keys don't contain a colon, but patterns can
+    let (pattern, subscribers) = private
+        .rsplit_once(':')
+        .ok_or_else(|| eyre::eyre!("Missing subscribers of private namespace: {private}"))?;
+
+    let subscribers = subscribers
+        .split(',')
+        .map(|pubkey| {
+            BlsPublicKey::from_bytes(&alloy::hex::decode(pubkey.trim())?)
+                .map_err(|e| eyre::eyre!("Invalid subscriber public key: {:?}", e))
+        })
+        .collect::<eyre::Result<_>>()?;
+
+    Ok((pattern.to_owned(), subscribers))
+}
+
+/// Parses a hex-encoded BLS secret key.
+fn parse_secret_key(secret_key: &str) -> eyre::Result<BlsSecretKey> {
+    BlsSecretKey::from_bytes(&alloy::hex::decode(secret_key)?)
+        .map_err(|e| eyre::eyre!("Invalid secret key: {:?}", e))
+}
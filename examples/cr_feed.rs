@@ -94,8 +94,11 @@ async fn main() {
                     info!("Subscribed to certified records")
                 }
                 Ok(Event::Message(msg)) => {
-                    let mut record = serde_json::from_str::<CertifiedRecord>(&msg.data).unwrap();
+                    let record = serde_json::from_str::<CertifiedRecord>(&msg.data).unwrap();
                     let median_timestamp = record.certified_timestamp();
+                    let mut signed_timestamps =
+                        record.signers.iter().map(|i| record.timestamps[i]).collect::<Vec<_>>();
+                    signed_timestamps.sort();
                     // Print the first last and median timestamp
 
                     let mut ts = timestamps_clone.lock().await;
@@ -104,10 +107,10 @@ async fn main() {
                     {
                         info!(
                             "First timestamp: {:?}  Median timestamp: {:?} Last timestamp: {:?}",
-                            record.timestamps[record.timestamps.len() - 1]
+                            signed_timestamps[signed_timestamps.len() - 1]
                                 .duration_since(start_time),
                             median_timestamp.duration_since(start_time),
-                            record.timestamps[0].duration_since(start_time),
+                            signed_timestamps[0].duration_since(start_time),
                         );
                         let duration = start_time.elapsed();
                         durations.push(duration);
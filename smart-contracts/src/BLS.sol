@@ -0,0 +1,196 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.13;
+
+/// BLS12-381 signature helpers on top of the EIP-2537 precompiles, for the "minimal public key"
+/// variant used by DATO: public keys are G1 points and signatures are G2 points.
+///
+/// Field elements are encoded as 64-byte big-endian words (48 bytes left-padded with zeros), as
+/// expected by the precompiles. G1 points are 128 bytes (x || y), G2 points are 256 bytes
+/// (x.c0 || x.c1 || y.c0 || y.c1).
+library BLS {
+    address constant MODEXP = address(0x05);
+    address constant BLS12_G2ADD = address(0x0d);
+    address constant BLS12_PAIRING_CHECK = address(0x0f);
+    address constant BLS12_MAP_FP2_TO_G2 = address(0x11);
+
+    // The base field modulus, as 48 bytes and as (high, low) 256-bit limbs
+    bytes constant P =
+        hex"1a0111ea397fe69a4b1ba7b6434bacd764774b84f38512bf6730d2a0f6b0f6241eabfffeb153ffffb9feffffffffaaab";
+    uint256 constant P_HI = 0x1a0111ea397fe69a4b1ba7b6434bacd7;
+    uint256 constant P_LO = 0x64774b84f38512bf6730d2a0f6b0f6241eabfffeb153ffffb9feffffffffaaab;
+
+    // (p - 1) / 2, the largest "lexicographically small" square root
+    uint256 constant HALF_P_HI = 0xd0088f51cbff34d258dd3db21a5d66b;
+    uint256 constant HALF_P_LO = 0xb23ba5c279c2895fb39869507b587b120f55ffff58a9ffffdcff7fffffffd555;
+
+    // (p + 1) / 4, since p = 3 mod 4 the square root of `a` is `a^((p + 1) / 4)`
+    bytes constant SQRT_EXPONENT =
+        hex"0680447a8e5ff9a692c6e9ed90d2eb35d91dd2e13ce144afd9cc34a83dac3d8907aaffffac54ffffee7fbfffffffeaab";
+
+    // The negated generator of G1
+    bytes constant NEG_G1_GENERATOR =
+        hex"0000000000000000000000000000000017f1d3a73197d7942695638c4fa9ac0fc3688c4f9774b905a14e3a3f171bac586c55e83ff97a1aeffb3af00adb22c6bb00000000000000000000000000000000114d1d6855d545a8aa7d76c8cf2e21f267816aef1db507c96655b9d5caac42364e6f38ba0ecb751bad54dcd6b939c2ca";
+
+    /// Verifies an aggregate signature over distinct messages, where `messages[i]` was signed
+    /// with the domain separator `dst` by the owner of `pubKeys[i]`.
+    ///
+    /// Public keys are 48-byte compressed G1 points, and the signature is an uncompressed G2 point.
+    function aggregateVerify(bytes[] memory pubKeys, bytes[] memory messages, bytes memory signature, bytes memory dst)
+        internal
+        view
+        returns (bool)
+    {
+        require(pubKeys.length == messages.length, "Length mismatch");
+        require(signature.length == 256, "Invalid signature length");
+
+        // e(-g1, signature) * e(pk_0, H(m_0)) * ... * e(pk_n, H(m_n)) == 1
+        bytes memory input = abi.encodePacked(NEG_G1_GENERATOR, signature);
+        for (uint256 i = 0; i < pubKeys.length; i++) {
+            input = abi.encodePacked(input, decompressG1(pubKeys[i]), hashToG2(messages[i], dst));
+        }
+
+        (bool success, bytes memory result) = BLS12_PAIRING_CHECK.staticcall(input);
+        return success && result.length == 32 && abi.decode(result, (uint256)) == 1;
+    }
+
+    /// Verifies a signature over `message`, signed with the domain separator `dst` by the owner
+    /// of `pubKey`.
+    function verify(bytes memory pubKey, bytes memory message, bytes memory signature, bytes memory dst)
+        internal
+        view
+        returns (bool)
+    {
+        bytes[] memory pubKeys = new bytes[](1);
+        pubKeys[0] = pubKey;
+        bytes[] memory messages = new bytes[](1);
+        messages[0] = message;
+
+        return aggregateVerify(pubKeys, messages, signature, dst);
+    }
+
+    /// Decompresses a 48-byte compressed G1 point into its 128-byte uncompressed encoding.
+    function decompressG1(bytes memory compressed) internal view returns (bytes memory) {
+        require(compressed.length == 48, "Invalid public key length");
+
+        uint8 flags = uint8(compressed[0]);
+        require(flags & 0x80 != 0, "Public key not compressed");
+        require(flags & 0x40 == 0, "Public key at infinity");
+
+        bytes memory x = bytes.concat(bytes1(flags & 0x1f), _slice(compressed, 1, 47));
+        (uint256 xHi, uint256 xLo) = _toLimbs(x);
+        require(_lessThan(xHi, xLo, P_HI, P_LO), "Invalid public key");
+
+        // y^2 = x^3 + 4
+        (uint256 rhsHi, uint256 rhsLo) = _toLimbs(_modexp(x, hex"03"));
+        (rhsHi, rhsLo) = _addMod(rhsHi, rhsLo, 4);
+        bytes memory rhs = abi.encodePacked(uint128(rhsHi), rhsLo);
+
+        bytes memory y = _modexp(rhs, SQRT_EXPONENT);
+        require(keccak256(_modexp(y, hex"02")) == keccak256(rhs), "Invalid public key");
+
+        // The sign flag is set if y is the larger of the two square roots
+        (uint256 yHi, uint256 yLo) = _toLimbs(y);
+        bool isLarger = _lessThan(HALF_P_HI, HALF_P_LO, yHi, yLo);
+        if (isLarger != (flags & 0x20 != 0)) {
+            (yHi, yLo) = _sub(P_HI, P_LO, yHi, yLo);
+        }
+
+        return abi.encodePacked(xHi, xLo, yHi, yLo);
+    }
+
+    /// Hashes a message to a G2 point with the given domain separator, following the
+    /// `BLS12381G2_XMD:SHA-256_SSWU_RO_` suite of RFC 9380.
+    function hashToG2(bytes memory message, bytes memory dst) internal view returns (bytes memory) {
+        bytes memory uniform = expandMessageXmd(message, dst);
+
+        bytes memory q0 = _mapToG2(bytes.concat(_reduce(uniform, 0), _reduce(uniform, 64)));
+        bytes memory q1 = _mapToG2(bytes.concat(_reduce(uniform, 128), _reduce(uniform, 192)));
+
+        // The precompile clears the cofactor of each point, which commutes with the addition
+        (bool success, bytes memory point) = BLS12_G2ADD.staticcall(bytes.concat(q0, q1));
+        require(success && point.length == 256, "G2 addition failed");
+
+        return point;
+    }
+
+    /// Expands a message into 256 uniformly random bytes with SHA-256, as specified by
+    /// `expand_message_xmd` in RFC 9380.
+    function expandMessageXmd(bytes memory message, bytes memory dst) internal pure returns (bytes memory) {
+        require(dst.length <= 255, "Domain separator too long");
+
+        bytes memory dstPrime = abi.encodePacked(dst, uint8(dst.length));
+        bytes32 b0 = sha256(abi.encodePacked(new bytes(64), message, uint16(256), uint8(0), dstPrime));
+
+        bytes32 bi = sha256(abi.encodePacked(b0, uint8(1), dstPrime));
+        bytes memory uniform = abi.encodePacked(bi);
+        for (uint8 i = 2; i <= 8; i++) {
+            bi = sha256(abi.encodePacked(b0 ^ bi, i, dstPrime));
+            uniform = abi.encodePacked(uniform, bi);
+        }
+
+        return uniform;
+    }
+
+    /// Maps an Fp2 element to a G2 point.
+    function _mapToG2(bytes memory element) private view returns (bytes memory) {
+        (bool success, bytes memory point) = BLS12_MAP_FP2_TO_G2.staticcall(element);
+        require(success && point.length == 256, "Map to G2 failed");
+
+        return point;
+    }
+
+    /// Reduces the 64 bytes at `offset` modulo p, and returns them as a padded field element.
+    function _reduce(bytes memory uniform, uint256 offset) private view returns (bytes memory) {
+        return bytes.concat(bytes16(0), _modexp(_slice(uniform, offset, 64), hex"01"));
+    }
+
+    /// Computes `base^exponent mod p` with the MODEXP precompile, returning 48 bytes.
+    function _modexp(bytes memory base, bytes memory exponent) private view returns (bytes memory) {
+        bytes memory input = abi.encodePacked(base.length, exponent.length, P.length, base, exponent, P);
+        (bool success, bytes memory result) = MODEXP.staticcall(input);
+        require(success && result.length == 48, "Modexp failed");
+
+        return result;
+    }
+
+    /// Splits a 48-byte big-endian number into its high 128 bits and low 256 bits.
+    function _toLimbs(bytes memory value) private pure returns (uint256 hi, uint256 lo) {
+        assembly {
+            hi := shr(128, mload(add(value, 32)))
+            lo := mload(add(value, 48))
+        }
+    }
+
+    function _lessThan(uint256 aHi, uint256 aLo, uint256 bHi, uint256 bLo) private pure returns (bool) {
+        return aHi < bHi || (aHi == bHi && aLo < bLo);
+    }
+
+    /// Computes `a - b`, assuming `a >= b`.
+    function _sub(uint256 aHi, uint256 aLo, uint256 bHi, uint256 bLo) private pure returns (uint256, uint256) {
+        unchecked {
+            uint256 borrow = aLo < bLo ? 1 : 0;
+            return (aHi - bHi - borrow, aLo - bLo);
+        }
+    }
+
+    /// Computes `(a + b) mod p`, assuming `a < p`.
+    function _addMod(uint256 aHi, uint256 aLo, uint256 b) private pure returns (uint256 hi, uint256 lo) {
+        unchecked {
+            lo = aLo + b;
+            hi = aHi + (lo < aLo ? 1 : 0);
+        }
+
+        if (!_lessThan(hi, lo, P_HI, P_LO)) {
+            (hi, lo) = _sub(hi, lo, P_HI, P_LO);
+        }
+    }
+
+    function _slice(bytes memory data, uint256 start, uint256 length) private pure returns (bytes memory) {
+        bytes memory result = new bytes(length);
+        for (uint256 i = 0; i < length; i++) {
+            result[i] = data[start + i];
+        }
+
+        return result;
+    }
+}
@@ -0,0 +1,168 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.13;
+
+import {ValidatorRegistry} from "./Registry.sol";
+import {BLS} from "./BLS.sol";
+
+/// Verifies DATO certificates against the validators in the registry, so that certificates of
+/// availability and unavailability can be used on-chain, e.g. to attribute censorship faults.
+contract DatoVerifier {
+    struct CertifiedRecord {
+        uint8 version;              // Certificate format version
+        uint64 epoch;               // Epoch of the validator set the certificate was produced under
+        bytes namespace;            // Namespace the message was written to
+        uint128[] timestamps;       // Timestamp signed by each validator, indexed by validator index
+        bytes signers;              // Bit `i` is set if validator `i` signed
+        bytes32[][] proofs;         // Inclusion proof in the batch signed by each validator, if any
+        bytes message;              // Message that was certified
+        bytes quorumSignature;      // Aggregate signature, as an uncompressed G2 point
+    }
+
+    struct CertifiedUnavailableMessage {
+        uint8 version;              // Certificate format version
+        uint64 epoch;               // Epoch of the validator set the certificate was produced under
+        bytes namespace;            // Namespace the message was looked up in
+        uint128[] timestamps;       // Time as of which each validator had not seen the message
+        bytes signers;              // Bit `i` is set if validator `i` signed
+        bytes32 msgId;              // ID of the unavailable message
+        bytes quorumSignature;      // Aggregate signature, as an uncompressed G2 point
+    }
+
+    uint8 public constant CERTIFICATE_VERSION = 3;
+
+    /// The prefix of the message signed by a validator for a batch of records, followed by the
+    /// root of the Merkle tree of their digests.
+    bytes public constant BATCH_PREFIX = "DATO_BATCH";
+
+    bytes public constant RECORD_DST = "BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+    bytes public constant UNAVAILABLE_DST = "DATO_UNAVAILABLE_BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+    ValidatorRegistry public registry;
+
+    constructor(ValidatorRegistry _registry) {
+        registry = _registry;
+    }
+
+    /// Reverts unless the given ABI-encoded `CertifiedRecord` is signed by a quorum of the
+    /// validators registered at the epoch of the certificate, which may be any epoch up to the
+    /// current one.
+    function verifyRecord(bytes calldata _certificate) external view {
+        CertifiedRecord memory certificate = abi.decode(_certificate, (CertifiedRecord));
+        require(certificate.version == CERTIFICATE_VERSION, "Unsupported certificate version");
+        require(certificate.epoch <= registry.currentEpoch(), "Unknown epoch");
+
+        uint256[] memory signers = signerIndexes(certificate.signers);
+        bytes[] memory messages = new bytes[](signers.length);
+        for (uint256 i = 0; i < signers.length; i++) {
+            require(signers[i] < certificate.timestamps.length, "Missing timestamp");
+
+            bytes32 digest = keccak256(
+                abi.encodePacked(
+                    certificate.namespace,
+                    toLittleEndian(certificate.timestamps[signers[i]]),
+                    certificate.message
+                )
+            );
+
+            // Validators that signed the record in a batch signed the root of the batch instead
+            if (signers[i] < certificate.proofs.length && certificate.proofs[signers[i]].length > 0) {
+                bytes32 root = processProof(keccak256(abi.encodePacked(digest)), certificate.proofs[signers[i]]);
+                messages[i] = abi.encodePacked(BATCH_PREFIX, root);
+            } else {
+                messages[i] = abi.encodePacked(digest);
+            }
+        }
+
+        verifyQuorumSignature(certificate.epoch, signers, messages, certificate.quorumSignature, RECORD_DST);
+    }
+
+    /// Reverts unless the given ABI-encoded `CertifiedUnavailableMessage` is signed by a quorum
+    /// of the validators registered at the epoch of the certificate.
+    function verifyUnavailable(bytes calldata _certificate) external view {
+        CertifiedUnavailableMessage memory certificate = abi.decode(_certificate, (CertifiedUnavailableMessage));
+        require(certificate.version == CERTIFICATE_VERSION, "Unsupported certificate version");
+        require(certificate.epoch <= registry.currentEpoch(), "Unknown epoch");
+
+        uint256[] memory signers = signerIndexes(certificate.signers);
+        bytes[] memory messages = new bytes[](signers.length);
+        for (uint256 i = 0; i < signers.length; i++) {
+            require(signers[i] < certificate.timestamps.length, "Missing timestamp");
+
+            messages[i] = abi.encodePacked(
+                keccak256(
+                    abi.encodePacked(
+                        certificate.namespace,
+                        certificate.msgId,
+                        toLittleEndian(certificate.timestamps[signers[i]])
+                    )
+                )
+            );
+        }
+
+        verifyQuorumSignature(certificate.epoch, signers, messages, certificate.quorumSignature, UNAVAILABLE_DST);
+    }
+
+    /// Checks that the signers hold at least 2/3 of the total stake of the given epoch, and that
+    /// the signature is the aggregate of each signer's signature over its own message.
+    function verifyQuorumSignature(
+        uint64 _epoch,
+        uint256[] memory _signers,
+        bytes[] memory _messages,
+        bytes memory _signature,
+        bytes memory _dst
+    ) internal view {
+        uint256 signedStake = 0;
+        bytes[] memory pubKeys = new bytes[](_signers.length);
+        for (uint256 i = 0; i < _signers.length; i++) {
+            uint256 stake = registry.stakeAt(_signers[i], _epoch);
+            require(stake > 0, "Unknown signer");
+
+            signedStake += stake;
+            pubKeys[i] = registry.registeredPubKey(_signers[i]);
+        }
+
+        require(signedStake > 0 && signedStake * 3 >= registry.totalStakeAt(_epoch) * 2, "No quorum");
+        require(BLS.aggregateVerify(pubKeys, _messages, _signature, _dst), "Invalid signature");
+    }
+
+    /// Returns the indexes of the set bits in the signer bitfield, in ascending order.
+    function signerIndexes(bytes memory _signers) internal pure returns (uint256[] memory) {
+        uint256 count = 0;
+        for (uint256 i = 0; i < _signers.length * 8; i++) {
+            if (uint8(_signers[i / 8]) & (1 << (i % 8)) != 0) {
+                count++;
+            }
+        }
+
+        uint256[] memory indexes = new uint256[](count);
+        uint256 next = 0;
+        for (uint256 i = 0; i < _signers.length * 8; i++) {
+            if (uint8(_signers[i / 8]) & (1 << (i % 8)) != 0) {
+                indexes[next++] = i;
+            }
+        }
+
+        return indexes;
+    }
+
+    /// Returns the root of the Merkle tree that includes the given leaf, from its inclusion proof.
+    /// Pairs of nodes are hashed in sorted order.
+    function processProof(bytes32 _leaf, bytes32[] memory _proof) internal pure returns (bytes32) {
+        bytes32 node = _leaf;
+        for (uint256 i = 0; i < _proof.length; i++) {
+            bytes32 sibling = _proof[i];
+            node = node <= sibling
+                ? keccak256(abi.encodePacked(node, sibling))
+                : keccak256(abi.encodePacked(sibling, node));
+        }
+
+        return node;
+    }
+
+    /// Returns the 16-byte little-endian encoding of a timestamp, as hashed by the validators.
+    function toLittleEndian(uint128 _value) internal pure returns (bytes16 result) {
+        for (uint256 i = 0; i < 16; i++) {
+            result |= bytes16(bytes1(uint8(_value >> (8 * i)))) >> (8 * i);
+        }
+    }
+}
@@ -1,10 +1,13 @@
 // SPDX-License-Identifier: MIT
 pragma solidity ^0.8.13;
 
+import {BLS} from "./BLS.sol";
+
 contract ValidatorRegistry {
     struct Validator {
         uint256 index;    // Validator index
         bytes blsPubKey;  // BLS Public Key
+        bytes proofOfPossession; // BLS signature over the public key, as an uncompressed G2 point
         uint256 stake;    // Stake deposited
         string socket;    // Socket information
         bool exists;      // To check if the validator exists
@@ -15,43 +18,85 @@ contract ValidatorRegistry {
     mapping(bytes => address) public blsPubKeyToValidator;
     address[] public validatorAddresses;
 
+    /// The domain separator of proofs of possession, as defined by the ciphersuite of the keys.
+    bytes public constant POP_DST = "BLS_POP_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
     uint256 public minimumStake;
     uint256 public validatorCount;
 
-    event ValidatorRegistered(address indexed validator, uint256 indexed index, bytes blsPubKey, uint256 stake, string socket);
+    // Every change to the validator set starts a new epoch, at most one per block. The validator
+    // set of an epoch is the state of the registry at the end of the block in which it started.
+    uint256 public currentEpoch;
+    mapping(uint256 => uint256) public epochStartBlock;
+
+    // The stake of each validator and the total stake, checkpointed at every epoch in which they
+    // changed, so that certificates can be verified against the validator set of their epoch.
+    struct Checkpoint {
+        uint256 epoch;
+        uint256 stake;
+    }
+
+    uint256 public totalStake;
+    mapping(uint256 => Checkpoint[]) internal stakeCheckpoints;
+    Checkpoint[] internal totalStakeCheckpoints;
+    // Unlike `indexToPubKey`, kept after the validator is removed, as indexes are never reused
+    mapping(uint256 => bytes) public registeredPubKey;
+
+    event ValidatorRegistered(address indexed validator, uint256 indexed index, bytes blsPubKey, bytes proofOfPossession, uint256 stake, string socket);
     event StakeDeposited(address indexed validator, uint256 amount);
     event ValidatorRemoved(address indexed validator);
+    event EpochStarted(uint256 indexed epoch, uint256 startBlock);
 
     constructor(uint256 _minimumStake) {
         minimumStake = _minimumStake;
+        epochStartBlock[0] = block.number;
     }
 
-    function registerValidator(bytes memory _blsPubKey, string memory _socket, uint256 _stake) external payable {
+    /// Registers the sender as a validator. The proof of possession must be a signature of the
+    /// compressed BLS public key by its own secret key, so that a rogue key can't be registered to
+    /// forge aggregate signatures.
+    function registerValidator(
+        bytes memory _blsPubKey,
+        bytes memory _proofOfPossession,
+        string memory _socket,
+        uint256 _stake
+    ) external payable {
         require(validators[msg.sender].exists == false, "Validator already registered");
         require(_stake >= minimumStake, "Insufficient stake");
         require(msg.value == _stake, "Stake amount mismatch with the transferred value");
+        require(BLS.verify(_blsPubKey, _blsPubKey, _proofOfPossession, POP_DST), "Invalid proof of possession");
 
         validators[msg.sender] = Validator({
             index: validatorCount,
             blsPubKey: _blsPubKey,
+            proofOfPossession: _proofOfPossession,
             stake: _stake,
             socket: _socket,
             exists: true
         });
 
         indexToPubKey[validatorCount] = _blsPubKey;
+        registeredPubKey[validatorCount] = _blsPubKey;
         blsPubKeyToValidator[_blsPubKey] = msg.sender;
 
         validatorAddresses.push(msg.sender);
         validatorCount++;
+        totalStake += _stake;
+        advanceEpoch();
+        checkpoint(stakeCheckpoints[validatorCount - 1], _stake);
+        checkpoint(totalStakeCheckpoints, totalStake);
 
-        emit ValidatorRegistered(msg.sender, validatorCount - 1, _blsPubKey, _stake, _socket);
+        emit ValidatorRegistered(msg.sender, validatorCount - 1, _blsPubKey, _proofOfPossession, _stake, _socket);
     }
 
     function depositStake() external payable {
         require(validators[msg.sender].exists == true, "Validator not registered");
 
         validators[msg.sender].stake += msg.value;
+        totalStake += msg.value;
+        advanceEpoch();
+        checkpoint(stakeCheckpoints[validators[msg.sender].index], validators[msg.sender].stake);
+        checkpoint(totalStakeCheckpoints, totalStake);
 
         emit StakeDeposited(msg.sender, msg.value);
     }
@@ -101,6 +146,7 @@ contract ValidatorRegistry {
 
         uint256 amount = validators[msg.sender].stake;
         validators[msg.sender].stake = 0;
+        totalStake -= amount;
 
         (bool success, ) = payable(msg.sender).call{value: amount}("");
         require(success, "Withdrawal failed");
@@ -128,6 +174,60 @@ contract ValidatorRegistry {
             }
         }
 
+        advanceEpoch();
+        checkpoint(stakeCheckpoints[index], 0);
+        checkpoint(totalStakeCheckpoints, totalStake);
+
         emit ValidatorRemoved(_validator);
     }
+
+    /// Returns the stake of the validator at the given index in the validator set of the given
+    /// epoch, or zero if it was not part of it.
+    function stakeAt(uint256 _index, uint256 _epoch) external view returns (uint256) {
+        return checkpointAt(stakeCheckpoints[_index], _epoch);
+    }
+
+    /// Returns the total stake of the validator set of the given epoch.
+    function totalStakeAt(uint256 _epoch) external view returns (uint256) {
+        return checkpointAt(totalStakeCheckpoints, _epoch);
+    }
+
+    /// Records the given stake for the current epoch, replacing the one recorded earlier in the
+    /// same epoch, if any. Must be called after `advanceEpoch`.
+    function checkpoint(Checkpoint[] storage _checkpoints, uint256 _stake) internal {
+        uint256 length = _checkpoints.length;
+        if (length > 0 && _checkpoints[length - 1].epoch == currentEpoch) {
+            _checkpoints[length - 1].stake = _stake;
+        } else {
+            _checkpoints.push(Checkpoint({epoch: currentEpoch, stake: _stake}));
+        }
+    }
+
+    /// Returns the stake of the last checkpoint at or before the given epoch, or zero if none.
+    function checkpointAt(Checkpoint[] storage _checkpoints, uint256 _epoch) internal view returns (uint256) {
+        uint256 low = 0;
+        uint256 high = _checkpoints.length;
+        while (low < high) {
+            uint256 mid = (low + high) / 2;
+            if (_checkpoints[mid].epoch > _epoch) {
+                high = mid;
+            } else {
+                low = mid + 1;
+            }
+        }
+
+        return low == 0 ? 0 : _checkpoints[low - 1].stake;
+    }
+
+    function advanceEpoch() internal {
+        // Changes within the same block are part of the same epoch
+        if (epochStartBlock[currentEpoch] == block.number) {
+            return;
+        }
+
+        currentEpoch++;
+        epochStartBlock[currentEpoch] = block.number;
+
+        emit EpochStarted(currentEpoch, block.number);
+    }
 }
\ No newline at end of file
@@ -0,0 +1,179 @@
+// SPDX-License-Identifier: MIT
+pragma solidity ^0.8.13;
+
+import {Test, console} from "forge-std/Test.sol";
+import "../src/Registry.sol";
+import "../src/DatoVerifier.sol";
+
+contract DatoVerifierTest is Test {
+    ValidatorRegistry public registry;
+    DatoVerifier public verifier;
+
+    uint256 public minimumStake = 1 ether;
+
+    // Generated with the Rust client from 3 deterministic BLS keys. All certificates are signed
+    // by validators 0 and 2, in namespace "dato" and epoch 0. In the batched record certificate,
+    // validator 2 signed the record as part of a batch of 3 records.
+    bytes[] public blsPubKeys;
+    bytes[] public proofsOfPossession;
+    bytes public recordCertificate =
+        hex"0000000000000000000000000000000000000000000000000000000000000020000000000000000000000000000000000000000000000000000000000000000300000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000100000000000000000000000000000000000000000000000000000000000000014000000000000000000000000000000000000000000000000000000000000001c0000000000000000000000000000000000000000000000000000000000000020000000000000000000000000000000000000000000000000000000000000002e0000000000000000000000000000000000000000000000000000000000000032000000000000000000000000000000000000000000000000000000000000000046461746f0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000030000000000000000000000000000000000000000000000000000018bcfe5680000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000018bcfe568020000000000000000000000000000000000000000000000000000000000000001050000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000030000000000000000000000000000000000000000000000000000000000000060000000000000000000000000000000000000000000000000000000000000008000000000000000000000000000000000000000000000000000000000000000a0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000b68656c6c6f20776f726c6400000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000001000000000000000000000000000000000007f4702352d5259f39ec294397a8474942e0fb3509c2991756b531c88e704690a43c0988f9fefe2c339c37d44becf066000000000000000000000000000000000bfeff565e06ab6421e39d53b074cf57cdd7356d2821a0091ce873a6747bcd7578196018b4573996bb44697b6627ae970000000000000000000000000000000015e2e68451f7234417dd60af3954a62a8947b7d4eaee1a6cef894205b47536dc29abec4e9e82355d951bbe5ce24338ff0000000000000000000000000000000013c31bb5f63a4803efa4153ecec7f95f8d0025cfe403f2335cd5db8d3eb8181ac19364c6133db3adb7c5fecbe216b15d";
+    bytes public unavailableCertificate =
+        hex"00000000000000000000000000000000000000000000000000000000000000200000000000000000000000000000000000000000000000000000000000000003000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000e0000000000000000000000000000000000000000000000000000000000000012000000000000000000000000000000000000000000000000000000000000001a0abababababababababababababababababababababababababababababababab00000000000000000000000000000000000000000000000000000000000001e000000000000000000000000000000000000000000000000000000000000000046461746f0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000030000000000000000000000000000000000000000000000000000018bcfe5680000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000018bcfe5681400000000000000000000000000000000000000000000000000000000000000010500000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000010000000000000000000000000000000000075e0c6ccc0a45339dd2706093d61c2e733f2f1462b580afcc690c20b828c32966b9c951f30fd4bfe160f5279160d896000000000000000000000000000000000cc9c40f250e7a4159734423580e1ef4b90ac3023743ea96c572381f14edd225e027c96c682369ffea1425e13213597b0000000000000000000000000000000006eb8063d78ebd6d342760da7661364b5be8e59062849f5bc55726ada3857acda8d1e80cca0dda367e070bd62d8a18200000000000000000000000000000000009c6f4422a4e835247baec72f8d0049491e4a737b8f13becdf0e8d9232881b6bba140292bdfe7547257225ed219de851";
+    bytes public batchedRecordCertificate =
+        hex"0000000000000000000000000000000000000000000000000000000000000020000000000000000000000000000000000000000000000000000000000000000300000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000100000000000000000000000000000000000000000000000000000000000000014000000000000000000000000000000000000000000000000000000000000001c000000000000000000000000000000000000000000000000000000000000002000000000000000000000000000000000000000000000000000000000000000320000000000000000000000000000000000000000000000000000000000000036000000000000000000000000000000000000000000000000000000000000000046461746f0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000030000000000000000000000000000000000000000000000000000018bcfe5680000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000018bcfe568020000000000000000000000000000000000000000000000000000000000000001050000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000030000000000000000000000000000000000000000000000000000000000000060000000000000000000000000000000000000000000000000000000000000008000000000000000000000000000000000000000000000000000000000000000a0000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000002cebc8882fecbec7fb80d2cf4b312bec018884c2d66667c67a90508214bd8bafcee4a079f5b14a24465181d45af32a8053c2d446446d7019359e210b82e53b8ba000000000000000000000000000000000000000000000000000000000000000b68656c6c6f20776f726c640000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000000100000000000000000000000000000000000ad84b76f6af91d336676c295a0074ec83a7fc15afde9d51e75dc1717b4838367c387852e6a4e35d8617792bcf1516a2000000000000000000000000000000000832c98d226ecdf6b85ce6e04a6ae9709192500639ce3e98c21059f6825a2b7ca6483d46603002d0642214dee32cc14e000000000000000000000000000000000ed3eb73c44e82a6d8689a333f369772d06ef3c4b8c86eff0e0491c939081ff07e77e2c88f3af1e08d1afd8dea9db7a500000000000000000000000000000000019c05c8cdc075c703dfe6200498fe12640d453bcc6f9e20e7876bf2236caaa8143767b942dc44d2c3344f2c072da3ab";
+
+    function setUp() public {
+        registry = new ValidatorRegistry(minimumStake);
+        verifier = new DatoVerifier(registry);
+
+        blsPubKeys.push(hex"95a254501b7733239ed3cec4d56737977bd09ede881d8a234560e83e5525017add3b1dcc3eabfb85e12a4131b19c253b");
+        blsPubKeys.push(hex"ac80a5e08c712d5f08f0306ad743f7d8c215d982489b84a1d6ba805733d94c006e8938f9089a75db3ffa135af33bc69a");
+        blsPubKeys.push(hex"96df714a5cc9ddd2298546dce3d6d3827762a6d5b1c2a91e5ca93c9c898b1b4319cc105c493212a55b63080732ec2249");
+        proofsOfPossession.push(
+            hex"000000000000000000000000000000000d39e3098f1bae074c062f84f24353210666bd58c0d9be3ff76ba9dd9ce905c5b602a12e78a04350275faacce8b7137d00000000000000000000000000000000046aa12a4402eb67cb92a497e0716db573c817a4163783153f0ddca475f4870200049d8e9ed35087c786059c1f26fc9d000000000000000000000000000000000b5fcbe6ea3a7b58f9549869a0e5c3a2b2ba240c1b5105760b108137848f6888d5be9bd141abc84fb2855d67c34448080000000000000000000000000000000005eb121cfed507131d968047d01478eb11860d4dbfe04d6232e4711e89bf99d44ff1c3e32aab33e38ceba4eae17ea2f4"
+        );
+        proofsOfPossession.push(
+            hex"0000000000000000000000000000000010d7ae595d18940e60894bd0a39eb157c1f646ee0f2079d64bd7f4e3c6cbc297e74ce69f3ae4e0728f915f1aac3cdf9b0000000000000000000000000000000011b22261eeb641b36d4f701f7e5635c5dd0ee53102e7ad8c11594be0d785f0bb5d75bd063ec2caa415e953f85e6e18e1000000000000000000000000000000001345fe188ba70ebb1126bda655014d95773f7ae4fa8c7678b9d3e76b8e7388c354e855f4240b6aa02eb414e4ca07dff500000000000000000000000000000000168c9f164a81e58ad55b1a73506848c6de2ad5316d821c725ddd3a35b7e42eed5ff79f7108dd8d26f9d86178ae7f674b"
+        );
+        proofsOfPossession.push(
+            hex"0000000000000000000000000000000011136e673ec2448619e768f2a978955c3aba6eb2b995c3e1c7851a4945fedc8d75709c4d0a98f6d6c70c5a47e9fdbf2600000000000000000000000000000000158f7ca277b5d44b57008bc90e88d4b8dbc941fd514124c7260176b2199e66e862eaf2e8c6145f4aa95a5362ba10f6a60000000000000000000000000000000019732bd69d4e6a4ddeade5161d5280c573d7bd5f2c9e63faa116b63d2468cd746405f6399067aef8002df1d3d4feac750000000000000000000000000000000007b38be5a1f5a4e26a89f32b990276109d065c6f3ee40eb5dd25ed8d17563225f5ace6e8582f5fb11bae721ae61b7e85"
+        );
+
+        for (uint256 i = 0; i < blsPubKeys.length; i++) {
+            address validator = address(uint160(i + 1));
+            vm.deal(validator, 10 ether);
+            vm.prank(validator);
+            registry.registerValidator{value: minimumStake}(
+                blsPubKeys[i], proofsOfPossession[i], "127.0.0.1:4000", minimumStake
+            );
+        }
+    }
+
+    function testVerifyRecord() public view {
+        verifier.verifyRecord(recordCertificate);
+    }
+
+    function testVerifyUnavailable() public view {
+        verifier.verifyUnavailable(unavailableCertificate);
+    }
+
+    function testVerifyBatchedRecord() public view {
+        verifier.verifyRecord(batchedRecordCertificate);
+    }
+
+    function testVerifyBatchedRecordWithoutProof() public {
+        DatoVerifier.CertifiedRecord memory certificate =
+            abi.decode(batchedRecordCertificate, (DatoVerifier.CertifiedRecord));
+        delete certificate.proofs[2];
+
+        vm.expectRevert("Invalid signature");
+        verifier.verifyRecord(abi.encode(certificate));
+    }
+
+    function testVerifyBatchedRecordWrongProof() public {
+        DatoVerifier.CertifiedRecord memory certificate =
+            abi.decode(batchedRecordCertificate, (DatoVerifier.CertifiedRecord));
+        certificate.proofs[2][0] = bytes32(0);
+
+        vm.expectRevert("Invalid signature");
+        verifier.verifyRecord(abi.encode(certificate));
+    }
+
+    function testVerifyRecordWrongNamespace() public {
+        DatoVerifier.CertifiedRecord memory certificate = decodeRecord();
+        certificate.namespace = "other";
+
+        vm.expectRevert("Invalid signature");
+        verifier.verifyRecord(abi.encode(certificate));
+    }
+
+    function testVerifyRecordUnsupportedVersion() public {
+        DatoVerifier.CertifiedRecord memory certificate = decodeRecord();
+        certificate.version = 4;
+
+        vm.expectRevert("Unsupported certificate version");
+        verifier.verifyRecord(abi.encode(certificate));
+    }
+
+    function testVerifyRecordFutureEpoch() public {
+        DatoVerifier.CertifiedRecord memory certificate = decodeRecord();
+        certificate.epoch = 1;
+
+        vm.expectRevert("Unknown epoch");
+        verifier.verifyRecord(abi.encode(certificate));
+    }
+
+    function testVerifyRecordAfterEpochChange() public {
+        // A deposit in a later block changes the validator set, and starts a new epoch. The
+        // certificate is still verified against the validator set of epoch 0, in which validator
+        // 1 doesn't hold half of the stake.
+        vm.roll(block.number + 1);
+        vm.prank(address(2));
+        registry.depositStake{value: 1 ether}();
+        assertEq(registry.currentEpoch(), 1);
+
+        verifier.verifyRecord(recordCertificate);
+        verifier.verifyUnavailable(unavailableCertificate);
+
+        // The same signers are not a quorum of epoch 1
+        DatoVerifier.CertifiedRecord memory certificate = decodeRecord();
+        certificate.epoch = 1;
+        vm.expectRevert("No quorum");
+        verifier.verifyRecord(abi.encode(certificate));
+    }
+
+    function testVerifyRecordAfterSignerRemoved() public {
+        // Validator 2 withdrawing in a later block doesn't invalidate the certificates it signed
+        vm.roll(block.number + 1);
+        vm.prank(address(3));
+        registry.withdrawAllStake();
+
+        verifier.verifyRecord(recordCertificate);
+
+        DatoVerifier.CertifiedRecord memory certificate = decodeRecord();
+        certificate.epoch = 1;
+        vm.expectRevert("Unknown signer");
+        verifier.verifyRecord(abi.encode(certificate));
+    }
+
+    function testVerifyRecordMissingTimestamp() public {
+        DatoVerifier.CertifiedRecord memory certificate = decodeRecord();
+        certificate.signers = hex"0d"; // validators 0, 2 and 3
+
+        vm.expectRevert("Missing timestamp");
+        verifier.verifyRecord(abi.encode(certificate));
+    }
+
+    function testVerifyRecordSingleSigner() public {
+        DatoVerifier.CertifiedRecord memory certificate = decodeRecord();
+        certificate.signers = hex"01";
+
+        vm.expectRevert("No quorum");
+        verifier.verifyRecord(abi.encode(certificate));
+    }
+
+    function testVerifyRecordStakeWeighted() public {
+        // Validator 1 now holds half of the stake, so validators 0 and 2 are not a quorum. The
+        // deposit is in the same block, so the epoch doesn't change.
+        vm.prank(address(2));
+        registry.depositStake{value: 1 ether}();
+
+        vm.expectRevert("No quorum");
+        verifier.verifyRecord(recordCertificate);
+    }
+
+    function testUnavailableSignatureIsNotARecordSignature() public {
+        DatoVerifier.CertifiedUnavailableMessage memory unavailable =
+            abi.decode(unavailableCertificate, (DatoVerifier.CertifiedUnavailableMessage));
+        DatoVerifier.CertifiedRecord memory certificate = decodeRecord();
+        certificate.quorumSignature = unavailable.quorumSignature;
+
+        vm.expectRevert("Invalid signature");
+        verifier.verifyRecord(abi.encode(certificate));
+    }
+
+    function decodeRecord() internal view returns (DatoVerifier.CertifiedRecord memory) {
+        return abi.decode(recordCertificate, (DatoVerifier.CertifiedRecord));
+    }
+}
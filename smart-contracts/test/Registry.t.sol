@@ -9,8 +9,13 @@ contract ValidatorRegistryTest is Test {
     address public validator1 = address(0x1);
     address public validator2 = address(0x2);
 
-    bytes public blsPubKey1 = hex"00112233445566778899aabbccddeeff";
-    bytes public blsPubKey2 = hex"112233445566778899aabbccddeeff00";
+    // Generated from deterministic BLS keys, the proofs of possession are uncompressed G2 points
+    bytes public blsPubKey1 = hex"95a254501b7733239ed3cec4d56737977bd09ede881d8a234560e83e5525017add3b1dcc3eabfb85e12a4131b19c253b";
+    bytes public blsPubKey2 = hex"ac80a5e08c712d5f08f0306ad743f7d8c215d982489b84a1d6ba805733d94c006e8938f9089a75db3ffa135af33bc69a";
+    bytes public proofOfPossession1 =
+        hex"000000000000000000000000000000000d39e3098f1bae074c062f84f24353210666bd58c0d9be3ff76ba9dd9ce905c5b602a12e78a04350275faacce8b7137d00000000000000000000000000000000046aa12a4402eb67cb92a497e0716db573c817a4163783153f0ddca475f4870200049d8e9ed35087c786059c1f26fc9d000000000000000000000000000000000b5fcbe6ea3a7b58f9549869a0e5c3a2b2ba240c1b5105760b108137848f6888d5be9bd141abc84fb2855d67c34448080000000000000000000000000000000005eb121cfed507131d968047d01478eb11860d4dbfe04d6232e4711e89bf99d44ff1c3e32aab33e38ceba4eae17ea2f4";
+    bytes public proofOfPossession2 =
+        hex"0000000000000000000000000000000010d7ae595d18940e60894bd0a39eb157c1f646ee0f2079d64bd7f4e3c6cbc297e74ce69f3ae4e0728f915f1aac3cdf9b0000000000000000000000000000000011b22261eeb641b36d4f701f7e5635c5dd0ee53102e7ad8c11594be0d785f0bb5d75bd063ec2caa415e953f85e6e18e1000000000000000000000000000000001345fe188ba70ebb1126bda655014d95773f7ae4fa8c7678b9d3e76b8e7388c354e855f4240b6aa02eb414e4ca07dff500000000000000000000000000000000168c9f164a81e58ad55b1a73506848c6de2ad5316d821c725ddd3a35b7e42eed5ff79f7108dd8d26f9d86178ae7f674b";
     string public socket1 = "127.0.0.1:4000";
     string public socket2 = "192.168.1.1:4000";
 
@@ -24,7 +29,7 @@ contract ValidatorRegistryTest is Test {
 
     function testRegisterValidator() public {
         vm.startPrank(validator1);
-        registry.registerValidator{value: minimumStake}(blsPubKey1, socket1, minimumStake);
+        registry.registerValidator{value: minimumStake}(blsPubKey1, proofOfPossession1, socket1, minimumStake);
 
         (uint256 index, bytes memory key, uint256 stake, string memory socket) = registry.getValidator(validator1);
         assertEq(index, 0);
@@ -37,22 +42,30 @@ contract ValidatorRegistryTest is Test {
     function testRegisterValidatorInsufficientStake() public {
         vm.startPrank(validator1);
         vm.expectRevert("Insufficient stake");
-        registry.registerValidator{value: 0.5 ether}(blsPubKey1, socket1, 0.5 ether);
+        registry.registerValidator{value: 0.5 ether}(blsPubKey1, proofOfPossession1, socket1, 0.5 ether);
+        vm.stopPrank();
+    }
+
+    function testRegisterValidatorInvalidProofOfPossession() public {
+        vm.startPrank(validator1);
+        // The proof of possession of another key doesn't prove possession of this one
+        vm.expectRevert("Invalid proof of possession");
+        registry.registerValidator{value: minimumStake}(blsPubKey1, proofOfPossession2, socket1, minimumStake);
         vm.stopPrank();
     }
 
     function testRegisterValidatorAlreadyRegistered() public {
         vm.startPrank(validator1);
-        registry.registerValidator{value: minimumStake}(blsPubKey1, socket1, minimumStake);
+        registry.registerValidator{value: minimumStake}(blsPubKey1, proofOfPossession1, socket1, minimumStake);
 
         vm.expectRevert("Validator already registered");
-        registry.registerValidator{value: minimumStake}(blsPubKey1, socket1, minimumStake);
+        registry.registerValidator{value: minimumStake}(blsPubKey1, proofOfPossession1, socket1, minimumStake);
         vm.stopPrank();
     }
 
     function testDepositStake() public {
         vm.startPrank(validator1);
-        registry.registerValidator{value: minimumStake}(blsPubKey1, socket1, minimumStake);
+        registry.registerValidator{value: minimumStake}(blsPubKey1, proofOfPossession1, socket1, minimumStake);
 
         registry.depositStake{value: 1 ether}();
 
@@ -71,7 +84,7 @@ contract ValidatorRegistryTest is Test {
     function testWithdrawAllStake() public {
         // Register the validator
         vm.startPrank(validator1);
-        registry.registerValidator{value: minimumStake}(blsPubKey1, socket1, minimumStake);
+        registry.registerValidator{value: minimumStake}(blsPubKey1, proofOfPossession1, socket1, minimumStake);
         vm.stopPrank();
 
         // Capture the initial balance of validator1
@@ -102,11 +115,11 @@ contract ValidatorRegistryTest is Test {
 
     function testGetAllValidatorSockets() public {
         vm.startPrank(validator1);
-        registry.registerValidator{value: minimumStake}(blsPubKey1, socket1, minimumStake);
+        registry.registerValidator{value: minimumStake}(blsPubKey1, proofOfPossession1, socket1, minimumStake);
         vm.stopPrank();
 
         vm.startPrank(validator2);
-        registry.registerValidator{value: minimumStake}(blsPubKey2, socket2, minimumStake);
+        registry.registerValidator{value: minimumStake}(blsPubKey2, proofOfPossession2, socket2, minimumStake);
         vm.stopPrank();
 
         string[] memory sockets = registry.getAllValidatorSockets();
@@ -119,18 +132,19 @@ contract ValidatorRegistryTest is Test {
     function testGetValidatorByIndex() public {
         // Register validator 1
         vm.startPrank(validator1);
-        registry.registerValidator{value: minimumStake}(blsPubKey1, socket1, minimumStake);
+        registry.registerValidator{value: minimumStake}(blsPubKey1, proofOfPossession1, socket1, minimumStake);
         vm.stopPrank();
 
         // Register validator 2
         vm.startPrank(validator2);
-        registry.registerValidator{value: minimumStake}(blsPubKey2, socket2, minimumStake);
+        registry.registerValidator{value: minimumStake}(blsPubKey2, proofOfPossession2, socket2, minimumStake);
         vm.stopPrank();
 
         // Retrieve validator 1 by index
         ValidatorRegistry.Validator memory validator = registry.getValidatorByIndex(0);
         assertEq(validator.index, 0);
         assertEq(validator.blsPubKey, blsPubKey1);
+        assertEq(validator.proofOfPossession, proofOfPossession1);
         assertEq(validator.stake, minimumStake);
         assertEq(validator.socket, socket1);
 
@@ -141,4 +155,71 @@ contract ValidatorRegistryTest is Test {
         assertEq(validator.stake, minimumStake);
         assertEq(validator.socket, socket2);
     }
+
+    function testEpochs() public {
+        uint256 deployBlock = block.number;
+        assertEq(registry.currentEpoch(), 0);
+
+        // Changes in the same block are part of the same epoch
+        vm.roll(deployBlock + 1);
+        vm.prank(validator1);
+        registry.registerValidator{value: minimumStake}(blsPubKey1, proofOfPossession1, socket1, minimumStake);
+        vm.prank(validator2);
+        registry.registerValidator{value: minimumStake}(blsPubKey2, proofOfPossession2, socket2, minimumStake);
+        assertEq(registry.currentEpoch(), 1);
+
+        vm.roll(deployBlock + 2);
+        vm.prank(validator1);
+        registry.depositStake{value: 1 ether}();
+        assertEq(registry.currentEpoch(), 2);
+
+        vm.roll(deployBlock + 3);
+        vm.prank(validator2);
+        registry.withdrawAllStake();
+        assertEq(registry.currentEpoch(), 3);
+
+        assertEq(registry.epochStartBlock(0), deployBlock);
+        assertEq(registry.epochStartBlock(1), deployBlock + 1);
+        assertEq(registry.epochStartBlock(2), deployBlock + 2);
+        assertEq(registry.epochStartBlock(3), deployBlock + 3);
+    }
+
+    function testStakeCheckpoints() public {
+        uint256 deployBlock = block.number;
+
+        vm.roll(deployBlock + 1);
+        vm.prank(validator1);
+        registry.registerValidator{value: minimumStake}(blsPubKey1, proofOfPossession1, socket1, minimumStake);
+        vm.prank(validator2);
+        registry.registerValidator{value: minimumStake}(blsPubKey2, proofOfPossession2, socket2, minimumStake);
+        // A deposit in the same block updates the stake of the same epoch
+        vm.prank(validator1);
+        registry.depositStake{value: 1 ether}();
+
+        vm.roll(deployBlock + 2);
+        vm.prank(validator1);
+        registry.depositStake{value: 1 ether}();
+
+        vm.roll(deployBlock + 3);
+        vm.prank(validator2);
+        registry.withdrawAllStake();
+
+        assertEq(registry.stakeAt(0, 0), 0);
+        assertEq(registry.stakeAt(0, 1), 2 ether);
+        assertEq(registry.stakeAt(0, 2), 3 ether);
+        assertEq(registry.stakeAt(0, 3), 3 ether);
+        assertEq(registry.stakeAt(1, 1), minimumStake);
+        assertEq(registry.stakeAt(1, 2), minimumStake);
+        assertEq(registry.stakeAt(1, 3), 0);
+
+        assertEq(registry.totalStakeAt(0), 0);
+        assertEq(registry.totalStakeAt(1), 3 ether);
+        assertEq(registry.totalStakeAt(2), 4 ether);
+        assertEq(registry.totalStakeAt(3), 3 ether);
+        assertEq(registry.totalStake(), 3 ether);
+
+        // The key of a removed validator is kept for the certificates of past epochs
+        assertEq(registry.getPubKeyByIndex(1), "");
+        assertEq(registry.registeredPubKey(1), blsPubKey2);
+    }
 }
\ No newline at end of file
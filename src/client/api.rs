@@ -13,14 +13,20 @@ use serde::{Deserialize, Serialize};
 use tokio::task::JoinHandle;
 use tracing::{debug, error, info, instrument};
 
-use crate::{CertifiedLog, CertifiedReadMessageResponse, CertifiedRecord, Log};
+use crate::{
+    CertifiedLog, CertifiedReadMessageResponse, CertifiedRecord, ClientError, Log, ReadError,
+    RetentionPolicy, ValidatorError,
+};
 
 use super::{Client, ClientSpec};
 
 const WRITE_PATH: &str = "/api/v1/write";
+const WRITE_BATCH_PATH: &str = "/api/v1/write_batch";
+const WRITE_DELAYED_PATH: &str = "/api/v1/write_delayed";
 const READ_PATH: &str = "/api/v1/read";
 const READ_CERTIFIED_PATH: &str = "/api/v1/read_certified";
 const READ_MESSAGE_PATH: &str = "/api/v1/read_message";
+const RETENTION_PATH: &str = "/api/v1/retention";
 const SUBSCRIBE_PATH: &str = "/api/v1/subscribe";
 const SUBSCRIBE_CERTIFIED_PATH: &str = "/api/v1/subscribe_certified";
 
@@ -29,9 +35,12 @@ impl Client {
     pub async fn run_api(self, port: u16) -> std::io::Result<JoinHandle<()>> {
         let router: Router = Router::new()
             .route(WRITE_PATH, post(write))
+            .route(WRITE_BATCH_PATH, post(write_batch))
+            .route(WRITE_DELAYED_PATH, post(write_delayed))
             .route(READ_PATH, get(read))
             .route(READ_CERTIFIED_PATH, get(read_certified))
             .route(READ_MESSAGE_PATH, get(read_message))
+            .route(RETENTION_PATH, get(retention))
             .route(SUBSCRIBE_PATH, get(subscribe))
             .route(SUBSCRIBE_CERTIFIED_PATH, get(subscribe_certified))
             .with_state(Arc::new(self));
@@ -50,6 +59,34 @@ impl Client {
     }
 }
 
+/// Returns the HTTP status code to respond with when a request fails with the given error.
+fn status_code(err: ClientError) -> StatusCode {
+    match err {
+        ClientError::Validator(err) => match err {
+            ValidatorError::NamespaceRejected |
+            ValidatorError::NamespaceTooLong { .. } |
+            ValidatorError::Unauthorized => StatusCode::FORBIDDEN,
+            ValidatorError::MessageTooLarge { .. } | ValidatorError::BatchTooLarge { .. } => {
+                StatusCode::PAYLOAD_TOO_LARGE
+            }
+            ValidatorError::TooManyNamespaces { .. } | ValidatorError::TimelockFull { .. } => {
+                StatusCode::INSUFFICIENT_STORAGE
+            }
+            ValidatorError::RateLimited => StatusCode::TOO_MANY_REQUESTS,
+            ValidatorError::Timelocked { .. } => StatusCode::TOO_EARLY,
+            ValidatorError::Backfilling => StatusCode::SERVICE_UNAVAILABLE,
+            ValidatorError::FutureQueryTime { .. } => StatusCode::BAD_REQUEST,
+            // The validators can't serve requests from this client, e.g. during a rollout
+            ValidatorError::UnsupportedVersion { .. } | ValidatorError::MalformedRequest(_) => {
+                StatusCode::BAD_GATEWAY
+            }
+            _ => StatusCode::INTERNAL_SERVER_ERROR,
+        },
+        ClientError::Read(ReadError::OutsideRetention { .. }) => StatusCode::GONE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 struct WriteRequest {
     namespace: String,
@@ -64,11 +101,48 @@ async fn write(
     let namespace = Bytes::from(request.namespace.as_bytes().to_owned());
     debug!(namespace = %request.namespace, "New write request");
 
+    client.write(namespace, request.message.into()).await.map(Json).map_err(status_code)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WriteBatchRequest {
+    namespace: String,
+    messages: Vec<Bytes>,
+}
+
+#[instrument(skip(client, request))]
+async fn write_batch(
+    State(client): State<Arc<Client>>,
+    Json(request): Json<WriteBatchRequest>,
+) -> Result<Json<Vec<CertifiedRecord>>, StatusCode> {
+    let namespace = Bytes::from(request.namespace.as_bytes().to_owned());
+    debug!(namespace = %request.namespace, len = request.messages.len(), "New write batch request");
+
+    let messages = request.messages.into_iter().map(Into::into).collect();
+
+    client.write_batch(namespace, messages).await.map(Json).map_err(status_code)
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct WriteDelayedRequest {
+    namespace: String,
+    message: Bytes,
+    release_after: u64,
+}
+
+#[instrument(skip(client, request))]
+async fn write_delayed(
+    State(client): State<Arc<Client>>,
+    Json(request): Json<WriteDelayedRequest>,
+) -> Result<Json<CertifiedRecord>, StatusCode> {
+    let namespace = Bytes::from(request.namespace.as_bytes().to_owned());
+    debug!(namespace = %request.namespace, request.release_after, "New delayed write request");
+
     client
-        .write(namespace, request.message.into())
+        .write_delayed(namespace, request.message.into(), request.release_after.into())
         .await
         .map(Json)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+        .map_err(status_code)
 }
 
 #[derive(Debug, Deserialize)]
@@ -90,7 +164,7 @@ async fn read(
         .read(namespace, params.start.into(), params.end.into())
         .await
         .map(Json)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+        .map_err(status_code)
 }
 
 #[instrument(skip(client, params))]
@@ -105,13 +179,14 @@ async fn read_certified(
         .read_certified(namespace, params.start.into(), params.end.into())
         .await
         .map(Json)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+        .map_err(status_code)
 }
 
 #[derive(Debug, Deserialize)]
 struct ReadMessageParams {
     namespace: String,
     msg_id: B256,
+    as_of: Option<u64>,
 }
 
 #[instrument(skip(client, params))]
@@ -122,11 +197,12 @@ async fn read_message(
     let namespace = Bytes::from(params.namespace.as_bytes().to_owned());
     debug!("New read_message request for namespace: {namespace}");
 
-    client
-        .read_message(namespace, params.msg_id)
-        .await
-        .map(Json)
-        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)
+    let response = match params.as_of {
+        Some(as_of) => client.read_message_as_of(namespace, params.msg_id, as_of.into()).await,
+        None => client.read_message(namespace, params.msg_id).await,
+    };
+
+    response.map(Json).map_err(status_code)
 }
 
 #[derive(Debug, Deserialize)]
@@ -134,6 +210,17 @@ struct NamespaceParams {
     namespace: String,
 }
 
+#[instrument(skip(client, params))]
+async fn retention(
+    State(client): State<Arc<Client>>,
+    Query(params): Query<NamespaceParams>,
+) -> Result<Json<Vec<(usize, RetentionPolicy)>>, StatusCode> {
+    let namespace = Bytes::from(params.namespace.as_bytes().to_owned());
+    debug!(namespace = %params.namespace, "New retention request");
+
+    client.retention(namespace).await.map(Json).map_err(status_code)
+}
+
 #[instrument(skip(client, params))]
 async fn subscribe(
     State(client): State<Arc<Client>>,
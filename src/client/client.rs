@@ -1,15 +1,22 @@
 use std::{
     collections::HashMap,
     net::SocketAddr,
+    sync::{Arc, RwLock},
     time::{Duration, Instant},
 };
 
-use alloy::primitives::B256;
+use alloy::primitives::{Bytes, B256};
 use async_trait::async_trait;
-use blst::min_pk::{AggregateSignature, PublicKey};
-use futures::stream::{FuturesUnordered, StreamExt};
+use blst::min_pk::{
+    AggregateSignature, PublicKey as BlsPublicKey, SecretKey as BlsSecretKey,
+    Signature as BlsSignature,
+};
+use futures::{
+    future,
+    stream::{select_all, FuturesUnordered, Stream, StreamExt},
+};
 use hashmore::FIFOMap;
-use msg::{tcp::Tcp, ReqError, ReqSocket, SubSocket};
+use msg::{tcp::Tcp, ReqError, ReqSocket, SubOptions, SubSocket};
 use tokio::{
     net::{lookup_host, ToSocketAddrs},
     sync::mpsc::{self, error::TrySendError},
@@ -20,11 +27,18 @@ use tracing::{debug, info, instrument, trace, warn};
 
 use crate::{
     common::{
-        CertifiedLog, CertifiedReadMessageResponse, CertifiedRecord, CertifiedUnavailableMessage,
-        ClientError, Log, Message, ReadError, ReadMessageResponse, Record, SubscribeResponse,
-        Timestamp, ValidatorIdentity,
+        has_reached_quorum, CertifiedLog, CertifiedReadMessageResponse, CertifiedRecord,
+        CertifiedUnavailableMessage, ClientError, Log, Message, ReadError, ReadMessageResponse,
+        Record, RetentionPolicy, SignerBitfield, SubscribeResponse, Timestamp, ValidatorError,
+        ValidatorIdentity, ValidatorSet, CERTIFICATE_VERSION,
+    },
+    primitives::{
+        bls::{
+            random_bls_secret, verify_signature, verify_signature_with_dst, BLS_UNAVAILABLE_DST,
+        },
+        codec::{self, Decode},
+        Request, Response,
     },
-    primitives::{bls::verify_signature, Request},
     Namespace, WriteError,
 };
 
@@ -35,109 +49,274 @@ const WRITE_TIMEOUT: Duration = Duration::from_millis(1000);
 const READ_TIMEOUT: Duration = Duration::from_millis(1000);
 
 /// A client that can write and read log records from validators.
-#[derive(Default)]
+///
+/// The set of validators can be updated while the client is running, e.g. by the
+/// [registry reconciler](Client::spawn_reconciler). Cloning the client is cheap, and
+/// all clones share the same set of validators.
+#[derive(Clone)]
 #[allow(missing_debug_implementations)]
 pub struct Client {
-    /// Mapping from validator public keys to their IDs.
-    validators: HashMap<usize, PublicKey>,
+    /// The connected validators, shared with the clones of the client.
+    state: Arc<RwLock<ClientState>>,
+    /// The key the client signs its subscribe requests with, which validators check
+    /// against the subscribers allowed for private namespaces.
+    subscriber_key: Arc<BlsSecretKey>,
+}
+
+impl Default for Client {
+    fn default() -> Self {
+        Self { state: Default::default(), subscriber_key: Arc::new(random_bls_secret()) }
+    }
+}
+
+/// A snapshot of the validators a client is connected to.
+#[derive(Default, Clone)]
+struct ClientState {
+    /// The validator set of the current epoch, indexed by validator IDs. Validators that
+    /// couldn't be connected to are still part of the set, as they count towards the
+    /// total stake.
+    validators: ValidatorSet,
     /// Mapping from validator IDs to their socket addresses and sockets.
-    validator_sockets: HashMap<usize, (SocketAddr, ReqSocket<Tcp>)>,
+    validator_sockets: HashMap<usize, (SocketAddr, Arc<ReqSocket<Tcp>>)>,
+}
+
+impl ClientState {
+    /// Returns the identity of the validator with the given ID.
+    fn validator(&self, index: usize) -> &ValidatorIdentity {
+        self.validators.get(index).expect("Validator not found")
+    }
+
+    /// Sends an encoded request to every connected validator, and returns a stream of the
+    /// decoded response of each validator that answers within the timeout, along with its ID,
+    /// in the order they arrive. Validators that fail to answer or send a malformed response
+    /// are logged and skipped.
+    fn broadcast<T: Decode + Send + 'static>(
+        &self,
+        request: Bytes,
+        timeout: Duration,
+    ) -> impl Stream<Item = (usize, Response<T>)> + Unpin + Send + 'static {
+        self.validator_sockets
+            .iter()
+            .map(|(&index, (_, socket))| {
+                let socket = Arc::clone(socket);
+                let cloned_req = request.clone();
+                async move {
+                    // Send the request to the validator with a timeout.
+                    let response =
+                        tokio::time::timeout(timeout, socket.request(cloned_req.into())).await;
+                    let bytes = match response {
+                        Ok(Ok(response)) => response,
+                        Ok(Err(e)) => {
+                            warn!(error = %e, "Error sending request to validator {index}");
+                            return None
+                        }
+                        Err(e) => {
+                            warn!(error = %e, "Timed out sending request to validator {index}");
+                            return None
+                        }
+                    };
+                    trace!("Received response from validator {index}: {bytes:?}");
+
+                    match codec::decode_response::<T>(&bytes) {
+                        Ok(response) => {
+                            if let Err(err) = &response {
+                                warn!(error = %err, "Error response from validator {index}");
+                            }
+                            Some((index, response))
+                        }
+                        Err(err) => {
+                            warn!(error = ?err, "Error decoding response from validator {index}");
+                            None
+                        }
+                    }
+                }
+            })
+            .collect::<FuturesUnordered<_>>()
+            .filter_map(future::ready)
+    }
 }
 
 impl Client {
-    /// Create a new client.
+    /// Create a new client, with a random subscriber key.
     pub fn new() -> Self {
         Self::default()
     }
 
-    /// Connect to a certain validator at the given address.
+    /// Sign the subscribe requests with the given key, e.g. one allowed by validators to
+    /// subscribe to private namespaces. Clones made before keep their key.
+    pub fn with_subscriber_key(mut self, subscriber_key: BlsSecretKey) -> Self {
+        self.subscriber_key = Arc::new(subscriber_key);
+        self
+    }
+
+    /// Connect to a certain validator at the given address, and add it to the validator set
+    /// of the current epoch. If a validator with the same ID is already connected, it is
+    /// replaced.
     pub async fn connect_validator<A: ToSocketAddrs>(
-        &mut self,
+        &self,
         validator: ValidatorIdentity,
         addr: A,
     ) -> Result<(), ReqError> {
-        // TODO: add timeout
-        let mut socket = ReqSocket::new(Tcp::default());
+        let (endpoint, socket) = connect(addr).await?;
+
+        let mut state = self.state.write().expect("Client state lock poisoned");
+        state.validator_sockets.insert(validator.index, (endpoint, Arc::new(socket)));
+        state.validators.insert(validator);
+
+        Ok(())
+    }
 
-        let mut addrs = lookup_host(addr).await?;
-        let endpoint = addrs.next().ok_or_else(|| {
-            std::io::Error::new(
-                std::io::ErrorKind::InvalidInput,
-                "could not find any valid address",
-            )
-        })?;
+    /// Switch to the given validator set, e.g. the one of a new epoch, connecting to each
+    /// validator at its given address. The connections to validators whose address didn't
+    /// change are kept, and the others are closed.
+    ///
+    /// The switch is atomic: requests in flight keep using the previous set, and requests
+    /// started after this returns use the new one. Returns the IDs of the validators that
+    /// couldn't be connected to, which are part of the set but won't serve requests.
+    pub async fn set_validators(
+        &self,
+        validators: ValidatorSet,
+        addrs: &HashMap<usize, SocketAddr>,
+    ) -> Vec<usize> {
+        let current = self.snapshot();
+        let mut validator_sockets = HashMap::with_capacity(validators.len());
+        let mut failed = Vec::new();
+
+        for validator in validators.iter() {
+            let index = validator.index;
+            let Some(addr) = addrs.get(&index).copied() else {
+                failed.push(index);
+                continue;
+            };
 
-        socket.connect(endpoint).await?;
+            // Keep the existing connection if the address didn't change
+            if let Some((current_addr, socket)) = current.validator_sockets.get(&index) {
+                if *current_addr == addr {
+                    validator_sockets.insert(index, (addr, Arc::clone(socket)));
+                    continue;
+                }
+            }
 
-        self.validators.insert(validator.index, validator.pubkey);
-        self.validator_sockets.insert(validator.index, (endpoint, socket));
+            debug!(index, %addr, "Connecting to validator");
+            match connect(addr).await {
+                Ok((addr, socket)) => {
+                    validator_sockets.insert(index, (addr, Arc::new(socket)));
+                }
+                Err(err) => {
+                    warn!(index, %addr, ?err, "Failed to connect to validator");
+                    failed.push(index);
+                }
+            }
+        }
 
-        Ok(())
+        *self.state.write().expect("Client state lock poisoned") =
+            ClientState { validators, validator_sockets };
+
+        failed
+    }
+
+    /// Disconnect from the validator with the given ID, and remove it from the validator set.
+    /// Returns the identity of the removed validator, if it was connected.
+    pub fn disconnect_validator(&self, index: usize) -> Option<ValidatorIdentity> {
+        let mut state = self.state.write().expect("Client state lock poisoned");
+        state.validator_sockets.remove(&index);
+        state.validators.remove(index)
+    }
+
+    /// Updates the identity (i.e. the public key and stake) of a connected validator,
+    /// without reconnecting to it. Returns false if the validator is not connected.
+    pub fn update_validator(&self, validator: ValidatorIdentity) -> bool {
+        let mut state = self.state.write().expect("Client state lock poisoned");
+        if !state.validator_sockets.contains_key(&validator.index) {
+            return false
+        }
+
+        state.validators.insert(validator);
+        true
+    }
+
+    /// Returns the address of the validator with the given ID, if it is connected.
+    pub fn validator_addr(&self, index: usize) -> Option<SocketAddr> {
+        let state = self.state.read().expect("Client state lock poisoned");
+        state.validator_sockets.get(&index).map(|(addr, _)| *addr)
+    }
+
+    /// Returns the validator set of the current epoch.
+    pub fn validator_set(&self) -> ValidatorSet {
+        self.state.read().expect("Client state lock poisoned").validators.clone()
+    }
+
+    /// Sends an encoded request to every connected validator, and returns a stream of the
+    /// decoded response of each validator that answers within the timeout, along with its ID.
+    pub(crate) fn broadcast<T: Decode + Send + 'static>(
+        &self,
+        request: Bytes,
+        timeout: Duration,
+    ) -> impl Stream<Item = (usize, Response<T>)> + Unpin + Send + 'static {
+        self.snapshot().broadcast(request, timeout)
+    }
+
+    /// Returns a snapshot of the connected validators, so that a request is served by a
+    /// consistent set of validators even if the set changes while it is in flight.
+    fn snapshot(&self) -> ClientState {
+        self.state.read().expect("Client state lock poisoned").clone()
     }
-}
 
-#[async_trait]
-impl ClientSpec for Client {
     #[instrument(skip(self, message))]
-    async fn write(
+    /// Write a message to the log for the given namespace, to be released after the given
+    /// time if set, and collect the certified record.
+    async fn write_inner(
         &self,
         namespace: Namespace,
         message: Message,
+        release_after: Option<Timestamp>,
     ) -> Result<CertifiedRecord, ClientError> {
+        let state = self.snapshot();
         let start = Instant::now();
-        let mut responses = FuturesUnordered::new();
-
-        let request = Request::Write { namespace: namespace.clone(), message: message.clone() };
-        let serialized_req = request.serialize();
-
-        for (index, (_, socket)) in &self.validator_sockets {
-            let cloned_req = serialized_req.clone();
-            responses.push(async {
-                // Send the request to the validator with a timeout.
-                match tokio::time::timeout(WRITE_TIMEOUT, socket.request(cloned_req.into())).await {
-                    Ok(Ok(response)) => Some((*index, response)),
-                    Ok(Err(e)) => {
-                        warn!(error = %e, "Error writing to validator {}", *index);
-                        None
-                    }
-                    Err(e) => {
-                        warn!(error = %e, "Timed out writing to validator {}", *index);
-                        None
-                    }
-                }
-            });
-        }
+
+        let request = match release_after {
+            Some(release_after) => Request::WriteDelayed {
+                namespace: namespace.clone(),
+                message: message.clone(),
+                release_after,
+            },
+            None => Request::Write { namespace: namespace.clone(), message: message.clone() },
+        };
+        let mut responses = state.broadcast(request.serialize(), WRITE_TIMEOUT);
 
         // Pre-allocate and set to all zeroes
-        let mut timestamps = vec![Timestamp::default(); self.validators.len()];
+        let mut timestamps = vec![Timestamp::default(); state.validators.index_bound()];
+        let mut signers = SignerBitfield::with_len(state.validators.index_bound());
+        let mut proofs = vec![Vec::new(); state.validators.index_bound()];
 
+        let total_stake = state.validators.total_stake();
         let mut quorum_signature: Option<AggregateSignature> = None;
-        let mut votes = 0;
+        let mut signed_stake = 0;
+        let mut errors = Vec::new();
 
         // Iterate over the responses until we have a quorum of valid responses OR we run out of
         // valid responses.
-        while let Some(Some((index, bytes))) = responses.next().await {
-            trace!("Received response from validator {index}: {bytes:?}");
-
-            let record = match serde_json::from_slice::<Record>(&bytes) {
+        while let Some((index, response)) = responses.next().await {
+            let record: Record = match response {
                 Ok(record) => record,
                 Err(err) => {
-                    warn!(error = ?err, "Error deserializing response from validator {index}");
+                    errors.push((err, state.validator(index).stake));
                     continue;
                 }
             };
 
-            let pubkey = self.validators.get(&index).expect("Validator not found");
+            let validator = state.validator(index);
+            let pubkey = &validator.pubkey;
 
             if record.message != message {
                 warn!("Message mismatch from validator {:?}", index);
                 continue;
             }
 
-            let digest = record.digest(&namespace);
+            let signed_message = record.signed_message(&namespace);
 
             // Verify the BLS signature
-            if !verify_signature(&record.signature, pubkey, digest) {
+            if !verify_signature(&record.signature, pubkey, signed_message) {
                 warn!(?pubkey, "Invalid signature from validator {index}");
                 continue;
             }
@@ -150,21 +329,32 @@ impl ClientSpec for Client {
                 quorum_signature = Some(AggregateSignature::from_signature(&record.signature));
             }
 
-            // Increase the number of votes, and store the timestamp
-            votes += 1;
+            // Add the validator stake to the quorum, and store the timestamp and proof
+            signed_stake += validator.stake;
             timestamps[index] = record.timestamp;
+            signers.set(index);
+            proofs[index] = record.proof;
 
-            if has_reached_quorum(self.validators.len(), votes) {
+            if has_reached_quorum(total_stake, signed_stake) {
                 break;
             }
         }
 
-        if !has_reached_quorum(self.validators.len(), votes) {
-            return Err(WriteError::NoQuorum { got: votes, needed: self.validators.len() }.into());
+        if !has_reached_quorum(total_stake, signed_stake) {
+            if let Some(err) = dominant_error(errors) {
+                return Err(err.into())
+            }
+
+            return Err(WriteError::NoQuorum { got: signed_stake, total: total_stake }.into());
         }
 
-        let mut certified_record = CertifiedRecord {
+        let certified_record = CertifiedRecord {
+            version: CERTIFICATE_VERSION,
+            epoch: state.validators.epoch(),
+            namespace,
             timestamps,
+            signers,
+            proofs,
             message,
             quorum_signature: quorum_signature.expect("Quorum passed"),
         };
@@ -176,171 +366,49 @@ impl ClientSpec for Client {
         Ok(certified_record)
     }
 
-    // TODO: this implementation can be sped up by using a single request to read all messages
-    // in the range and then filtering out the certified messages in a single pass.
-    #[instrument(skip(self))]
-    async fn read_certified(
-        &self,
-        namespace: Namespace,
-        start: Timestamp,
-        end: Timestamp,
-    ) -> Result<CertifiedLog, ClientError> {
-        // start by reading all messages in a range
-        let log = self.read(namespace.clone(), start, end).await?;
-
-        // for each message in the log, attempt to read the certified message
-        let mut certified_log = CertifiedLog::default();
-        for record in log.records {
-            let msg_id = record.message_digest(&namespace);
-            match self.read_message(namespace.clone(), msg_id).await {
-                Ok(CertifiedReadMessageResponse::Available(certified_record)) => {
-                    certified_log.records.push(certified_record);
-                }
-                Ok(CertifiedReadMessageResponse::Unavailable(_)) => {
-                    // skip unavailable messages
-                }
-                Err(e) => {
-                    warn!(error = %e, "Error reading certified message");
-                }
-            }
-        }
-
-        Ok(certified_log)
-    }
-
-    #[instrument(skip(self))]
-    async fn read(
-        &self,
-        namespace: Namespace,
-        start: Timestamp,
-        end: Timestamp,
-    ) -> Result<Log, ClientError> {
-        let start_ts = Instant::now();
-        let mut responses = FuturesUnordered::new();
-
-        let request = Request::ReadRange { namespace: namespace.clone(), start, end };
-        let serialized_req = request.serialize();
-
-        for (index, (_, socket)) in &self.validator_sockets {
-            let cloned_req = serialized_req.clone();
-            responses.push(async {
-                // Send the request to the validator with a timeout.
-                match tokio::time::timeout(READ_TIMEOUT, socket.request(cloned_req.into())).await {
-                    Ok(Ok(response)) => Some((*index, response)),
-                    Ok(Err(e)) => {
-                        warn!(error = %e, "Error reading from validator {}", *index);
-                        None
-                    }
-                    Err(e) => {
-                        warn!(error = %e, "Timed out reading from validator {}", *index);
-                        None
-                    }
-                }
-            });
-        }
-
-        let mut verify_tasks = JoinSet::new();
-
-        while let Some(Some((index, bytes))) = responses.next().await {
-            trace!("Received response from validator {index}: {bytes:?}");
-
-            let log = match serde_json::from_slice::<Log>(&bytes) {
-                Ok(log) => log,
-                Err(err) => {
-                    warn!(error = ?err, "Error deserializing response from validator {index}");
-                    continue;
-                }
-            };
-
-            debug!(len = log.len(), "Got log from validator {index}");
-            let pubkey = self.validators.get(&index).cloned().expect("Validator not found");
-            let namespace = namespace.clone();
-
-            // Verify the BLS signatures
-            verify_tasks.spawn(async move {
-                let start = Instant::now();
-
-                for record in &log.records {
-                    let digest = record.digest(&namespace);
-
-                    if !verify_signature(&record.signature, &pubkey, digest) {
-                        warn!(?pubkey, "Invalid signature from validator {index}");
-                        return None;
-                    }
-                }
-
-                debug!(elapsed = ?start.elapsed(), len = log.len(), "Signatures verified for validator {index}");
-                Some(log)
-            });
-        }
-
-        let mut final_log: Option<Log> = None;
-        while let Some(Ok(Some(log))) = verify_tasks.join_next().await {
-            if let Some(ref mut first) = final_log {
-                first.records.extend(log.records);
-            } else {
-                final_log = Some(log);
-            }
-        }
-
-        let mut final_log = final_log.unwrap_or_default();
-        final_log.records.sort_by_key(|r| r.timestamp);
-        debug!(elapsed = ?start_ts.elapsed(), records = final_log.len(), "Read completed");
-
-        Ok(final_log)
-    }
-
     #[instrument(skip(self))]
-    async fn read_message(
+    /// Attempt to read the message specified by the given namespace and message ID, or
+    /// certify that it had not been seen as of the given time (or as of now, if not set).
+    async fn read_message_inner(
         &self,
         namespace: Namespace,
         msg_id: B256,
+        as_of: Option<Timestamp>,
     ) -> Result<CertifiedReadMessageResponse, ClientError> {
+        let state = self.snapshot();
         let start_ts = Instant::now();
-        let mut responses = FuturesUnordered::new();
-
-        let request = Request::ReadMessage { namespace: namespace.clone(), msg_id };
-        let serialized_req = request.serialize();
-
-        for (index, (_, socket)) in &self.validator_sockets {
-            let cloned_req = serialized_req.clone();
-            responses.push(async {
-                // Send the request to the validator with a timeout.
-                match tokio::time::timeout(READ_TIMEOUT, socket.request(cloned_req.into())).await {
-                    Ok(Ok(response)) => Some((*index, response)),
-                    Ok(Err(e)) => {
-                        warn!(error = %e, "Error reading from validator {}", *index);
-                        None
-                    }
-                    Err(e) => {
-                        warn!(error = %e, "Timed out reading from validator {}", *index);
-                        None
-                    }
-                }
-            });
-        }
+
+        let request = Request::ReadMessage { namespace: namespace.clone(), msg_id, as_of };
+        let mut responses = state.broadcast(request.serialize(), READ_TIMEOUT);
 
         // IMPORTANT: Pre-allocate and set to all zeroes
-        let mut available_timestamps = vec![Timestamp::default(); self.validators.len()];
-        let mut unavailable_timestamps = vec![Timestamp::default(); self.validators.len()];
+        let mut available_timestamps = vec![Timestamp::default(); state.validators.index_bound()];
+        let mut unavailable_timestamps = vec![Timestamp::default(); state.validators.index_bound()];
+        let mut available_signers = SignerBitfield::with_len(state.validators.index_bound());
+        let mut unavailable_signers = SignerBitfield::with_len(state.validators.index_bound());
+        let mut available_proofs = vec![Vec::new(); state.validators.index_bound()];
 
         let mut available_quorum_signature: Option<AggregateSignature> = None;
         let mut unavailable_quorum_signature: Option<AggregateSignature> = None;
 
-        let mut available_votes = 0;
-        let mut unavailable_votes = 0;
+        let total_stake = state.validators.total_stake();
+        let mut available_stake = 0;
+        let mut unavailable_stake = 0;
+        let mut responses_count = 0;
+
+        // The latest time up to which a validator without the message pruned records
+        let mut low_water_mark: Option<Timestamp> = None;
 
         let mut message: Message = Default::default();
+        let mut errors = Vec::new();
 
         // Iterate over the responses until we have a quorum of valid responses OR we run out of
         // valid responses.
-        while let Some(Some((index, bytes))) = responses.next().await {
-            trace!("Received response from validator {index}: {bytes:?}");
-
-            let response = match serde_json::from_slice::<ReadMessageResponse>(&bytes) {
+        while let Some((index, response)) = responses.next().await {
+            let response: ReadMessageResponse = match response {
                 Ok(response) => response,
                 Err(err) => {
-                    warn!(error = ?err, "Error deserializing response from validator {index}");
+                    errors.push((err, state.validator(index).stake));
                     continue;
                 }
             };
@@ -353,16 +421,36 @@ impl ClientSpec for Client {
                         continue;
                     }
 
-                    message = record.message.clone();
-                    let pubkey = self.validators.get(&index).expect("Validator not found");
+                    // A record synced from another validator counts for the validator that
+                    // signed it, once
+                    let signer = match &record.origin {
+                        Some(origin) => state.validators.find(origin).map(|v| v.index),
+                        None => Some(index),
+                    };
+                    let Some(signer) = signer else {
+                        warn!("Record from unknown validator served by validator {index}");
+                        continue;
+                    };
+                    if available_signers.is_set(signer) {
+                        trace!("Duplicate record of validator {signer} from validator {index}");
+                        continue;
+                    }
+
+                    let Some(validator) = state.validators.get(signer) else {
+                        warn!("Record from unknown validator {signer} served by validator {index}");
+                        continue;
+                    };
+                    let pubkey = &validator.pubkey;
 
-                    let digest = record.digest(&namespace);
+                    let signed_message = record.signed_message(&namespace);
 
-                    if !verify_signature(&record.signature, pubkey, digest) {
+                    if !verify_signature(&record.signature, pubkey, signed_message) {
                         warn!(?pubkey, "Invalid signature from validator {index}");
                         continue;
                     }
 
+                    message = record.message.clone();
+
                     trace!("Validated response from validator {index}");
 
                     if let Some(q) = available_quorum_signature.as_mut() {
@@ -372,14 +460,28 @@ impl ClientSpec for Client {
                             Some(AggregateSignature::from_signature(&record.signature));
                     }
 
-                    available_votes += 1;
-                    available_timestamps[index] = record.timestamp;
+                    available_stake += validator.stake;
+                    available_timestamps[signer] = record.timestamp;
+                    available_signers.set(signer);
+                    available_proofs[signer] = record.proof;
                 }
                 ReadMessageResponse::Unavailable(unavailable) => {
-                    let pubkey = self.validators.get(&index).expect("Validator not found");
-                    let digest = unavailable.digest();
+                    // All signers need to attest to the same query time, if one was requested
+                    if as_of.is_some_and(|as_of| as_of != unavailable.timestamp) {
+                        warn!("Query time mismatch from validator {index}");
+                        continue;
+                    }
 
-                    if !verify_signature(&unavailable.signature, pubkey, digest) {
+                    let validator = state.validator(index);
+                    let pubkey = &validator.pubkey;
+                    let digest = unavailable.digest(&namespace);
+
+                    if !verify_signature_with_dst(
+                        &unavailable.signature,
+                        pubkey,
+                        digest,
+                        BLS_UNAVAILABLE_DST,
+                    ) {
                         warn!(?pubkey, "Invalid signature from validator {index}");
                         continue;
                     }
@@ -393,29 +495,35 @@ impl ClientSpec for Client {
                             Some(AggregateSignature::from_signature(&unavailable.signature));
                     }
 
-                    unavailable_votes += 1;
+                    unavailable_stake += validator.stake;
                     unavailable_timestamps[index] = unavailable.timestamp;
+                    unavailable_signers.set(index);
+                }
+                ReadMessageResponse::OutsideRetention { low_water_mark: mark } => {
+                    debug!(%mark, "Message outside the retention window of validator {index}");
+                    low_water_mark = low_water_mark.max(Some(mark));
                 }
             }
 
-            if has_reached_quorum(self.validators.len(), available_votes) ||
-                has_reached_quorum(self.validators.len(), unavailable_votes) ||
-                available_votes + unavailable_votes >= self.validators.len()
+            responses_count += 1;
+            if has_reached_quorum(total_stake, available_stake) ||
+                has_reached_quorum(total_stake, unavailable_stake) ||
+                responses_count >= state.validators.len()
             {
                 break;
             }
         }
 
-        trace!(
-            available_votes,
-            unavailable_votes,
-            validators = self.validators.len(),
-            "Quorum check"
-        );
+        trace!(available_stake, unavailable_stake, total_stake, "Quorum check");
 
-        if has_reached_quorum(self.validators.len(), available_votes) {
-            let mut certified_record = CertifiedRecord {
+        if has_reached_quorum(total_stake, available_stake) {
+            let certified_record = CertifiedRecord {
+                version: CERTIFICATE_VERSION,
+                epoch: state.validators.epoch(),
+                namespace,
                 timestamps: available_timestamps,
+                signers: available_signers,
+                proofs: available_proofs,
                 message,
                 quorum_signature: available_quorum_signature.expect("Quorum passed"),
             };
@@ -425,9 +533,13 @@ impl ClientSpec for Client {
             debug!(elapsed = ?start_ts.elapsed(), median_timestamp = timestamp, "Quorum reached");
 
             Ok(CertifiedReadMessageResponse::Available(certified_record))
-        } else if has_reached_quorum(self.validators.len(), unavailable_votes) {
-            let mut certified_unavailable_message = CertifiedUnavailableMessage {
+        } else if has_reached_quorum(total_stake, unavailable_stake) {
+            let certified_unavailable_message = CertifiedUnavailableMessage {
+                version: CERTIFICATE_VERSION,
+                epoch: state.validators.epoch(),
+                namespace,
                 timestamps: unavailable_timestamps,
+                signers: unavailable_signers,
                 msg_id,
                 quorum_signature: unavailable_quorum_signature.expect("Quorum passed"),
             };
@@ -437,95 +549,107 @@ impl ClientSpec for Client {
             debug!(elapsed = ?start_ts.elapsed(), median_timestamp = timestamp, "Quorum reached");
 
             Ok(CertifiedReadMessageResponse::Unavailable(certified_unavailable_message))
+        } else if let Some(low_water_mark) = low_water_mark {
+            Err(ReadError::OutsideRetention { low_water_mark }.into())
+        } else if let Some(err) = dominant_error(errors) {
+            Err(err.into())
         } else {
-            Err(ReadError::NoQuorum { available: available_votes, unavailable: unavailable_votes }
+            Err(ReadError::NoQuorum { available: available_stake, unavailable: unavailable_stake }
                 .into())
         }
     }
 
-    #[instrument(skip(self))]
-    async fn subscribe(&self, namespace: Namespace) -> Result<ReceiverStream<Record>, ClientError> {
-        let mut responses = FuturesUnordered::new();
-
-        let request = Request::Subscribe { namespace: namespace.clone() };
-        let serialized_req = request.serialize();
+    #[instrument(skip(state, subscriber_key))]
+    /// Subscribe to all messages in the given namespace, with a request signed by the given
+    /// subscriber key, and verify the signature of each record against the validator that
+    /// published it. Records with an invalid signature are dropped, and valid ones are
+    /// yielded along with the ID of their validator.
+    ///
+    /// The subscription is served by the validators of the given snapshot for its whole
+    /// lifetime, even if the validator set changes in the meantime.
+    async fn subscribe_attributed(
+        state: ClientState,
+        subscriber_key: &BlsSecretKey,
+        namespace: Namespace,
+    ) -> Result<ReceiverStream<(usize, Record)>, ClientError> {
+        let request = Request::subscribe(namespace.clone(), subscriber_key);
 
         // request subscription to the selected namespace from all validators
-        for (index, (remote_socket_addr, socket)) in &self.validator_sockets {
-            let cloned_req = serialized_req.clone();
-            responses.push(async {
-                // Send the request to the validator with a timeout.
-                match tokio::time::timeout(WRITE_TIMEOUT, socket.request(cloned_req.into())).await {
-                    Ok(Ok(response)) => Some((*index, *remote_socket_addr, response)),
-                    Ok(Err(e)) => {
-                        warn!(error = %e, "Error subscribing to validator {}", *index);
-                        None
-                    }
-                    Err(e) => {
-                        warn!(error = %e, "Timed out subscribing to validator {}", *index);
-                        None
-                    }
-                }
-            });
-        }
+        let mut responses = state.broadcast(request.serialize(), WRITE_TIMEOUT);
 
         let mut validator_publisher_sockets = HashMap::new();
+        let mut errors = Vec::new();
 
         // collect all publisher socket addresses from validators
-        while let Some(Some((index, remote_addr, bytes))) = responses.next().await {
-            trace!("Received response from validator {index}: {bytes:?}");
-
-            let sub_response = match serde_json::from_slice::<SubscribeResponse>(&bytes) {
+        while let Some((index, response)) = responses.next().await {
+            let sub_response: SubscribeResponse = match response {
                 Ok(response) => response,
                 Err(err) => {
-                    warn!(error = ?err, "Error deserializing response from validator {index}");
+                    errors.push((err, state.validator(index).stake));
                     continue;
                 }
             };
 
-            validator_publisher_sockets.insert((remote_addr.ip(), sub_response.port), index);
+            let (remote_addr, _) = state.validator_sockets[&index];
+            validator_publisher_sockets
+                .insert((remote_addr.ip(), sub_response.port), (index, sub_response.auth_token));
+        }
+
+        if validator_publisher_sockets.is_empty() {
+            if let Some(err) = dominant_error(errors) {
+                return Err(err.into())
+            }
         }
 
         let (record_sub_tx, record_sub_rx) = mpsc::channel(512);
+        let validators = state.validators.clone();
 
         tokio::spawn(async move {
-            let mut sub_socket = SubSocket::new(Tcp::default());
+            // Each publisher only accepts the token it issued, so we need a socket per validator
+            let mut sub_sockets = Vec::with_capacity(validator_publisher_sockets.len());
 
             let topic_string = String::from_utf8_lossy(&namespace).to_string();
-            for (pub_socket_addr, _validator_index) in validator_publisher_sockets {
-                // TODO: use index to keep track of which validator we're connected to
+            for (pub_socket_addr, (index, auth_token)) in validator_publisher_sockets {
+                let options = SubOptions::default().auth_token(auth_token.to_bytes().into());
+                let mut sub_socket = SubSocket::with_options(Tcp::default(), options);
 
+                // A publisher that can't be reached only drops the records of its validator
                 if let Err(err) = sub_socket.connect(pub_socket_addr).await {
-                    warn!(error = %err, "Failed to connect to validator publisher");
-                    return;
+                    warn!(index, error = %err, "Failed to connect to validator publisher");
+                    continue;
                 };
                 debug!(?pub_socket_addr, "Connected to publisher");
 
                 if let Err(err) = sub_socket.subscribe(topic_string.clone()).await {
-                    warn!(error = %err, "Failed to subscribe to namespace");
-                    return;
+                    warn!(index, error = %err, "Failed to subscribe to namespace");
+                    continue;
                 }
 
                 info!(?pub_socket_addr, "Subscribed to publisher topic");
+
+                // Tag each message with the validator that published it
+                sub_sockets.push(sub_socket.map(move |pub_msg| (index, pub_msg)));
             }
 
-            while let Some(pub_msg) = sub_socket.next().await {
-                trace!(?pub_msg, "Received message from publisher");
+            let mut sub_stream = select_all(sub_sockets);
+            while let Some((index, pub_msg)) = sub_stream.next().await {
+                trace!(?pub_msg, "Received message from publisher of validator {index}");
 
-                if let Ok(record) = serde_json::from_slice::<Record>(&pub_msg.into_payload()) {
-                    // TODO: use map of connected pub sockets to index and index to pubkey
-                    // to verify the signature of each incoming message
+                let payload = pub_msg.into_payload();
+                let Some(record) = verify_published(&validators, index, &namespace, &payload)
+                else {
+                    continue;
+                };
 
-                    if let Err(err) = record_sub_tx.try_send(record) {
-                        match err {
-                            TrySendError::Closed(_) => {
-                                warn!("API consumer closed subscription, stopping background task");
-                                return;
-                            }
-                            TrySendError::Full(_) => {
-                                warn!("API consumer subscription buffer full, dropping message");
-                                continue;
-                            }
+                if let Err(err) = record_sub_tx.try_send((index, record)) {
+                    match err {
+                        TrySendError::Closed(_) => {
+                            warn!("API consumer closed subscription, stopping background task");
+                            return;
+                        }
+                        TrySendError::Full(_) => {
+                            warn!("API consumer subscription buffer full, dropping message");
+                            continue;
                         }
                     }
                 }
@@ -534,37 +658,473 @@ impl ClientSpec for Client {
 
         Ok(ReceiverStream::new(record_sub_rx))
     }
+}
+
+/// Connects a new socket to the given address, and returns it along with the resolved address.
+async fn connect<A: ToSocketAddrs>(addr: A) -> Result<(SocketAddr, ReqSocket<Tcp>), ReqError> {
+    // TODO: add timeout
+    let mut socket = ReqSocket::new(Tcp::default());
+
+    let mut addrs = lookup_host(addr).await?;
+    let endpoint = addrs.next().ok_or_else(|| {
+        std::io::Error::new(std::io::ErrorKind::InvalidInput, "could not find any valid address")
+    })?;
+
+    socket.connect(endpoint).await?;
+
+    Ok((endpoint, socket))
+}
+
+/// Decodes a record published by the validator with the given ID, and verifies its signature
+/// against that validator. Returns `None` if the record is malformed, if the validator is not
+/// part of the given set, or if the signature is invalid.
+fn verify_published(
+    validators: &ValidatorSet,
+    index: usize,
+    namespace: &Namespace,
+    payload: &[u8],
+) -> Option<Record> {
+    let Ok(record) = codec::decode::<Record>(payload) else {
+        warn!("Received malformed record from validator {index}");
+        return None
+    };
+
+    let Some(validator) = validators.get(index) else {
+        warn!("Received record from unknown validator {index}");
+        return None
+    };
+
+    let signed_message = record.signed_message(namespace);
+    if !verify_signature(&record.signature, &validator.pubkey, signed_message) {
+        warn!(pubkey = ?validator.pubkey, "Invalid signature from validator {index}");
+        return None
+    }
+
+    Some(record)
+}
+
+/// Verifies the signatures of the given records against the public key of their signer.
+/// Consecutive records signed together in a batch share their signature, which is only
+/// verified once.
+fn verify_records<'a>(
+    records: impl IntoIterator<Item = (&'a Record, &'a BlsPublicKey)>,
+    namespace: &Namespace,
+) -> bool {
+    let mut verified: Option<(&BlsSignature, &BlsPublicKey, Vec<u8>)> = None;
+
+    records.into_iter().all(|(record, pubkey)| {
+        let signed_message = record.signed_message(namespace);
+        if verified.as_ref().is_some_and(|(signature, signer, message)| {
+            **signature == record.signature && *signer == pubkey && *message == signed_message
+        }) {
+            return true
+        }
+
+        let valid = verify_signature(&record.signature, pubkey, &signed_message);
+        verified = Some((&record.signature, pubkey, signed_message));
+        valid
+    })
+}
+
+/// Returns the error reported by the validators with the most stake, given the errors
+/// returned by each validator along with its stake. Used to explain why a request failed.
+fn dominant_error(errors: Vec<(ValidatorError, u128)>) -> Option<ValidatorError> {
+    let mut stake_by_error: Vec<(ValidatorError, u128)> = Vec::new();
+
+    for (err, stake) in errors {
+        match stake_by_error.iter_mut().find(|(other, _)| *other == err) {
+            Some((_, total)) => *total += stake,
+            None => stake_by_error.push((err, stake)),
+        }
+    }
+
+    stake_by_error.into_iter().max_by_key(|(_, stake)| *stake).map(|(err, _)| err)
+}
+
+#[async_trait]
+impl ClientSpec for Client {
+    #[instrument(skip(self, message))]
+    async fn write(
+        &self,
+        namespace: Namespace,
+        message: Message,
+    ) -> Result<CertifiedRecord, ClientError> {
+        self.write_inner(namespace, message, None).await
+    }
+
+    #[instrument(skip(self, message))]
+    async fn write_delayed(
+        &self,
+        namespace: Namespace,
+        message: Message,
+        release_after: Timestamp,
+    ) -> Result<CertifiedRecord, ClientError> {
+        self.write_inner(namespace, message, Some(release_after)).await
+    }
+
+    #[instrument(skip(self, messages), fields(len = messages.len()))]
+    async fn write_batch(
+        &self,
+        namespace: Namespace,
+        messages: Vec<Message>,
+    ) -> Result<Vec<CertifiedRecord>, ClientError> {
+        if messages.is_empty() {
+            return Ok(Vec::new())
+        }
+
+        let state = self.snapshot();
+        let start = Instant::now();
+
+        let request =
+            Request::WriteBatch { namespace: namespace.clone(), messages: messages.clone() };
+        let mut responses = state.broadcast(request.serialize(), WRITE_TIMEOUT);
+
+        // Pre-allocate and set to all zeroes, with one list of timestamps per message
+        let mut timestamps =
+            vec![vec![Timestamp::default(); state.validators.index_bound()]; messages.len()];
+        let mut signers = SignerBitfield::with_len(state.validators.index_bound());
+        let mut proofs = vec![vec![Vec::new(); state.validators.index_bound()]; messages.len()];
+
+        let total_stake = state.validators.total_stake();
+        let mut quorum_signatures: Vec<Option<AggregateSignature>> =
+            messages.iter().map(|_| None).collect();
+        let mut signed_stake = 0;
+        let mut errors = Vec::new();
+
+        // Iterate over the responses until we have a quorum of valid responses OR we run out of
+        // valid responses.
+        while let Some((index, response)) = responses.next().await {
+            let records: Vec<Record> = match response {
+                Ok(records) => records,
+                Err(err) => {
+                    errors.push((err, state.validator(index).stake));
+                    continue;
+                }
+            };
+
+            let validator = state.validator(index);
+            let pubkey = &validator.pubkey;
+
+            // The validator only counts towards the quorum if it signed the whole batch
+            if records.len() != messages.len() {
+                warn!(len = records.len(), "Batch length mismatch from validator {index}");
+                continue;
+            }
+
+            if records.iter().zip(&messages).any(|(record, message)| record.message != *message) {
+                warn!("Message mismatch from validator {:?}", index);
+                continue;
+            }
+
+            // Verify the BLS signatures
+            if !verify_records(records.iter().map(|record| (record, pubkey)), &namespace) {
+                warn!(?pubkey, "Invalid signature from validator {index}");
+                continue;
+            }
+
+            trace!("Validated response from validator {index}");
+
+            for (i, record) in records.into_iter().enumerate() {
+                if let Some(q) = quorum_signatures[i].as_mut() {
+                    q.add_signature(&record.signature, false).unwrap();
+                } else {
+                    quorum_signatures[i] =
+                        Some(AggregateSignature::from_signature(&record.signature));
+                }
+
+                timestamps[i][index] = record.timestamp;
+                proofs[i][index] = record.proof;
+            }
+
+            // Add the validator stake to the quorum
+            signed_stake += validator.stake;
+            signers.set(index);
+
+            if has_reached_quorum(total_stake, signed_stake) {
+                break;
+            }
+        }
+
+        if !has_reached_quorum(total_stake, signed_stake) {
+            if let Some(err) = dominant_error(errors) {
+                return Err(err.into())
+            }
+
+            return Err(WriteError::NoQuorum { got: signed_stake, total: total_stake }.into());
+        }
+
+        let certified_records: Vec<_> = messages
+            .into_iter()
+            .zip(timestamps)
+            .zip(proofs)
+            .zip(quorum_signatures)
+            .map(|(((message, timestamps), proofs), quorum_signature)| CertifiedRecord {
+                version: CERTIFICATE_VERSION,
+                epoch: state.validators.epoch(),
+                namespace: namespace.clone(),
+                timestamps,
+                signers: signers.clone(),
+                proofs,
+                message,
+                quorum_signature: quorum_signature.expect("Quorum passed"),
+            })
+            .collect();
+
+        debug!(elapsed = ?start.elapsed(), len = certified_records.len(), "Quorum reached");
+
+        Ok(certified_records)
+    }
+
+    // TODO: this implementation can be sped up by using a single request to read all messages
+    // in the range and then filtering out the certified messages in a single pass.
+    #[instrument(skip(self))]
+    async fn read_certified(
+        &self,
+        namespace: Namespace,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<CertifiedLog, ClientError> {
+        // start by reading all messages in a range
+        let log = self.read(namespace.clone(), start, end).await?;
+
+        // for each message in the log, attempt to read the certified message
+        let mut certified_log = CertifiedLog::default();
+        for record in log.records {
+            let msg_id = record.message_digest(&namespace);
+            match self.read_message(namespace.clone(), msg_id).await {
+                Ok(CertifiedReadMessageResponse::Available(certified_record)) => {
+                    certified_log.records.push(certified_record);
+                }
+                Ok(CertifiedReadMessageResponse::Unavailable(_)) => {
+                    // skip unavailable messages
+                }
+                Err(e) => {
+                    warn!(error = %e, "Error reading certified message");
+                }
+            }
+        }
+
+        Ok(certified_log)
+    }
+
+    #[instrument(skip(self))]
+    async fn read(
+        &self,
+        namespace: Namespace,
+        start: Timestamp,
+        end: Timestamp,
+    ) -> Result<Log, ClientError> {
+        let state = self.snapshot();
+        let start_ts = Instant::now();
+
+        let request = Request::ReadRange { namespace: namespace.clone(), start, end };
+        let mut responses = state.broadcast(request.serialize(), READ_TIMEOUT);
+
+        let mut verify_tasks = JoinSet::new();
+        let mut errors = Vec::new();
+
+        while let Some((index, response)) = responses.next().await {
+            let log: Log = match response {
+                Ok(log) => log,
+                Err(err) => {
+                    errors.push((err, state.validator(index).stake));
+                    continue;
+                }
+            };
+
+            debug!(len = log.len(), "Got log from validator {index}");
+            let validators = state.validators.clone();
+            let namespace = namespace.clone();
+
+            // Verify the BLS signatures, against the validator that signed each record: records
+            // synced from other validators keep the signature of their origin
+            verify_tasks.spawn(async move {
+                let start = Instant::now();
+                let served_by = validators.get(index)?;
+                let mut log = log;
+
+                log.records.retain(|record| {
+                    let known = record
+                        .origin
+                        .as_ref()
+                        .is_none_or(|origin| validators.find(origin).is_some());
+                    if !known {
+                        warn!("Record from an unknown signer served by validator {index}");
+                    }
+                    known
+                });
+
+                let signer = |record: &Record| record.origin.unwrap_or(served_by.pubkey);
+                let signers = log.records.iter().map(signer).collect::<Vec<_>>();
+
+                // An invalid record only drops that record, so the records are then checked
+                // one by one
+                if !verify_records(log.records.iter().zip(&signers), &namespace) {
+                    warn!("Invalid signature from validator {index}");
+                    log.records
+                        .retain(|record| verify_records([(record, &signer(record))], &namespace));
+                }
+
+                debug!(elapsed = ?start.elapsed(), len = log.len(), "Signatures verified for validator {index}");
+                Some(log)
+            });
+        }
+
+        // Fail only if no validator could serve the read
+        if verify_tasks.is_empty() {
+            if let Some(err) = dominant_error(errors) {
+                return Err(err.into())
+            }
+        }
+
+        let mut final_log: Option<Log> = None;
+        while let Some(Ok(Some(log))) = verify_tasks.join_next().await {
+            if let Some(ref mut first) = final_log {
+                first.records.extend(log.records);
+            } else {
+                final_log = Some(log);
+            }
+        }
+
+        let mut final_log = final_log.unwrap_or_default();
+        final_log.records.sort_by_key(|r| r.timestamp);
+        debug!(elapsed = ?start_ts.elapsed(), records = final_log.len(), "Read completed");
+
+        Ok(final_log)
+    }
+
+    async fn read_message(
+        &self,
+        namespace: Namespace,
+        msg_id: B256,
+    ) -> Result<CertifiedReadMessageResponse, ClientError> {
+        self.read_message_inner(namespace, msg_id, None).await
+    }
+
+    async fn read_message_as_of(
+        &self,
+        namespace: Namespace,
+        msg_id: B256,
+        as_of: Timestamp,
+    ) -> Result<CertifiedReadMessageResponse, ClientError> {
+        self.read_message_inner(namespace, msg_id, Some(as_of)).await
+    }
+
+    #[instrument(skip(self))]
+    async fn retention(
+        &self,
+        namespace: Namespace,
+    ) -> Result<Vec<(usize, RetentionPolicy)>, ClientError> {
+        let state = self.snapshot();
+        let request = Request::Retention { namespace }.serialize();
+
+        let mut policies = Vec::new();
+        let mut errors = Vec::new();
+
+        let mut responses = state.broadcast(request, READ_TIMEOUT);
+        while let Some((index, response)) = responses.next().await {
+            match response {
+                Ok(policy) => policies.push((index, policy)),
+                Err(err) => errors.push((err, state.validator(index).stake)),
+            }
+        }
+
+        // Only fail if no validator advertised its policy
+        if policies.is_empty() {
+            if let Some(err) = dominant_error(errors) {
+                return Err(err.into())
+            }
+        }
+
+        policies.sort_unstable_by_key(|(index, _)| *index);
+        Ok(policies)
+    }
+
+    #[instrument(skip(self))]
+    async fn subscribe(&self, namespace: Namespace) -> Result<ReceiverStream<Record>, ClientError> {
+        let mut attributed_stream =
+            Self::subscribe_attributed(self.snapshot(), &self.subscriber_key, namespace).await?;
+
+        let (record_tx, record_rx) = mpsc::channel(512);
+
+        // spawn a background task to strip the validator IDs from the verified records. It
+        // waits for the consumer instead of dropping records, so that a lagging consumer fills
+        // the buffer of the verified records first, where the newest ones are then dropped.
+        tokio::spawn(async move {
+            while let Some((_, record)) = attributed_stream.next().await {
+                if let Err(err) = record_tx.send(record).await {
+                    warn!(?err, "API consumer closed subscription, stopping background task");
+                    return;
+                }
+            }
+        });
+
+        Ok(ReceiverStream::new(record_rx))
+    }
 
     #[instrument(skip(self))]
     async fn subscribe_certified(
         &self,
         namespace: Namespace,
     ) -> Result<ReceiverStream<CertifiedRecord>, ClientError> {
-        // perform a regular subscription to get all records
-        let mut record_stream = self.subscribe(namespace.clone()).await?;
+        let state = self.snapshot();
+        let validators = state.validators.clone();
+        let total_stake = validators.total_stake();
+
+        // subscribe to all records, verified and attributed to their validators. The
+        // certificates are pinned to the epoch of the validator set at the time of subscribing.
+        let mut record_stream =
+            Self::subscribe_attributed(state, &self.subscriber_key, namespace.clone()).await?;
 
         let (certified_record_tx, certified_record_rx) = mpsc::channel(512);
-        let validators_count = self.validators.len();
 
         // spawn a background task to aggregate records into certified records and
         // send them to the consumer stream
         tokio::spawn(async move {
-            let mut records_by_id = FIFOMap::<B256, Vec<Record>>::with_capacity(1024);
+            // The records received so far for each message, or `None` once the message
+            // has been certified, so that each certificate is only emitted once.
+            let mut records_by_id =
+                FIFOMap::<B256, Option<Vec<(usize, Record)>>>::with_capacity(1024);
 
-            while let Some(record) = record_stream.next().await {
+            while let Some((index, record)) = record_stream.next().await {
                 let id = record.message_digest(&namespace);
 
                 // TODO: clean this up with FIFOMap::entry API when available
-                let records = if let Some(records) = records_by_id.get_mut(&id) {
-                    records.push(record);
-                    records
+                let entry = if let Some(entry) = records_by_id.get_mut(&id) {
+                    entry
                 } else {
-                    records_by_id.insert(id, vec![record]);
+                    records_by_id.insert(id, Some(Vec::new()));
                     records_by_id.get_mut(&id).unwrap()
                 };
 
-                if has_reached_quorum(validators_count, records.len()) {
-                    let certified_record = CertifiedRecord::from_records_unchecked(records);
+                let Some(records) = entry.as_mut() else {
+                    trace!(
+                        ?id,
+                        "Message already certified, skipping record from validator {index}"
+                    );
+                    continue;
+                };
+
+                // Each validator can only count once towards the quorum
+                if records.iter().any(|(signer, _)| *signer == index) {
+                    continue;
+                }
+                records.push((index, record));
+
+                let signed_stake = records
+                    .iter()
+                    .filter_map(|(signer, _)| validators.get(*signer))
+                    .map(|validator| validator.stake)
+                    .sum();
+
+                if has_reached_quorum(total_stake, signed_stake) {
+                    let records = entry.take().expect("Records are present");
+                    let certified_record = CertifiedRecord::from_records_unchecked(
+                        namespace.clone(),
+                        &records,
+                        &validators,
+                    );
+
                     if let Err(err) = certified_record_tx.send(certified_record).await {
                         warn!(?err, "API consumer closed subscription, stopping background task");
                         return;
@@ -577,13 +1137,40 @@ impl ClientSpec for Client {
     }
 }
 
-/// Function to compute if the quorum has been reached. A quorum is reached when the number of votes
-/// is greater than or equal to 2/3 of the total number of validators.
-fn has_reached_quorum(total_validators: usize, votes: usize) -> bool {
-    if total_validators < 3 {
-        // 1 of 1 or 2 of 2 validators == quorum
-        return votes == total_validators;
-    }
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bls::sign_with_prefix;
+
+    #[test]
+    fn test_verify_published() {
+        let keys = [random_bls_secret(), random_bls_secret()];
+        let validators = ValidatorSet::new(
+            keys.iter().enumerate().map(|(index, sk)| ValidatorIdentity::new(index, sk.sk_to_pk())),
+        );
 
-    votes >= 2 * total_validators / 3
+        let namespace = Namespace::from_static(b"test");
+        let mut record = Record {
+            timestamp: Timestamp::now(),
+            message: Message(Bytes::from_static(b"hello")),
+            signature: sign_with_prefix(&keys[1], B256::ZERO),
+            proof: Vec::new(),
+            origin: None,
+        };
+        record.signature = sign_with_prefix(&keys[1], record.signed_message(&namespace));
+        let payload = codec::encode(&record);
+
+        let verified = verify_published(&validators, 1, &namespace, &payload).unwrap();
+        assert_eq!(verified.message, record.message);
+
+        // Records signed by another validator than the publisher are dropped
+        assert!(verify_published(&validators, 0, &namespace, &payload).is_none());
+
+        // As well as records of another namespace, or from a validator not in the set
+        let other = Namespace::from_static(b"other");
+        assert!(verify_published(&validators, 1, &other, &payload).is_none());
+        assert!(verify_published(&validators, 2, &namespace, &payload).is_none());
+
+        assert!(verify_published(&validators, 1, &namespace, b"malformed").is_none());
+    }
 }
@@ -4,5 +4,8 @@ mod api;
 mod client;
 pub use client::Client;
 
+mod reconciler;
+pub use reconciler::DEFAULT_RECONCILE_INTERVAL;
+
 mod spec;
 pub use spec::ClientSpec;
@@ -0,0 +1,128 @@
+use std::{collections::HashMap, time::Duration};
+
+use tokio::{
+    net::lookup_host,
+    task::JoinHandle,
+    time::{interval_at, Instant, MissedTickBehavior},
+};
+use tokio_stream::{wrappers::ReceiverStream, StreamExt};
+use tracing::{debug, info, warn};
+
+use crate::{
+    registry::{Registry, RegistryEvent, ValidatorInfo},
+    ValidatorSet,
+};
+
+use super::Client;
+
+/// The default interval at which the validator set is reloaded from the registry.
+pub const DEFAULT_RECONCILE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// The interval at which the registry is checked for changes to the validator set.
+const WATCH_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+impl Client {
+    /// Spawns a background task that reloads the validator set of the current epoch from the
+    /// given registry whenever the registry reports a change, and at least every `interval`,
+    /// and reconciles the connected validators with it.
+    ///
+    /// The client (and any API server running on a clone of it) keeps serving requests
+    /// in the meantime, with each request using the validator set at the time it started.
+    pub fn spawn_reconciler<R>(&self, registry: R, interval: Duration) -> JoinHandle<()>
+    where
+        R: Registry + Send + Sync + 'static,
+    {
+        let client = self.clone();
+
+        tokio::spawn(async move {
+            // If the registry can't be watched, fall back to reloading it periodically
+            let mut events = match registry.watch(WATCH_POLL_INTERVAL).await {
+                Ok(events) => Some(events),
+                Err(err) => {
+                    warn!(?err, "Failed to watch the registry, reloading it periodically");
+                    None
+                }
+            };
+
+            // The validators are expected to be connected already, so skip the first tick
+            let mut ticker = interval_at(Instant::now() + interval, interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                tokio::select! {
+                    _ = ticker.tick() => {}
+                    Some(event) = next_event(&mut events) => {
+                        debug!(?event, "Validator set changed in the registry");
+
+                        // The whole set is reloaded anyway, so skip the other pending events
+                        if let Some(events) = events.as_mut() {
+                            while events.as_mut().try_recv().is_ok() {}
+                        }
+                    }
+                }
+
+                match latest_epoch(&registry).await {
+                    Ok((epoch, validators)) => client.reconcile(epoch, &validators).await,
+                    Err(err) => warn!(?err, "Failed to read validators from the registry"),
+                }
+            }
+        })
+    }
+
+    /// Reconciles the connected validators with the validator set of the given epoch:
+    /// validators that are no longer in the set are disconnected, new ones are connected, and
+    /// the ones whose socket changed are reconnected. The switch to the new set is atomic, see
+    /// [`Client::set_validators`].
+    ///
+    /// Failing to connect to a validator is logged and doesn't abort the reconciliation,
+    /// the validator will be retried on the next one.
+    pub async fn reconcile(&self, epoch: u64, validators: &[ValidatorInfo]) {
+        let current = self.validator_set();
+        let validators = validators.iter().filter(|validator| validator.exists).collect::<Vec<_>>();
+
+        let mut addrs = HashMap::with_capacity(validators.len());
+        for validator in &validators {
+            let index = validator.index as usize;
+            match lookup_host(&validator.socket).await.map(|mut addrs| addrs.next()) {
+                Ok(Some(addr)) => {
+                    addrs.insert(index, addr);
+                }
+                Ok(None) | Err(_) => {
+                    warn!(index, socket = %validator.socket, "Failed to resolve validator socket");
+                }
+            }
+        }
+
+        let next = ValidatorSet::new(validators.iter().map(|validator| validator.identity()))
+            .with_epoch(epoch);
+
+        if epoch != current.epoch() {
+            info!(from = current.epoch(), to = epoch, "Switching to a new validator set epoch");
+        }
+
+        for removed in current.iter().filter(|validator| next.get(validator.index).is_none()) {
+            info!(index = removed.index, "Validator removed from the registry, disconnecting");
+        }
+
+        let failed = self.set_validators(next, &addrs).await;
+        if !failed.is_empty() {
+            warn!(epoch, ?failed, "Failed to connect to some validators");
+        }
+    }
+}
+
+/// Returns the current epoch of the registry, along with its validators.
+async fn latest_epoch<R: Registry>(registry: &R) -> eyre::Result<(u64, Vec<ValidatorInfo>)> {
+    let epoch = registry.epoch().await?;
+    let validators = registry.validators_at(epoch).await?;
+
+    Ok((epoch, validators))
+}
+
+/// Returns the next registry event, or never resolves if the registry isn't watched.
+async fn next_event(events: &mut Option<ReceiverStream<RegistryEvent>>) -> Option<RegistryEvent> {
+    match events {
+        Some(events) => events.next().await,
+        None => std::future::pending().await,
+    }
+}
@@ -4,7 +4,7 @@ use tokio_stream::wrappers::ReceiverStream;
 
 use crate::{
     common::{CertifiedReadMessageResponse, ClientError},
-    CertifiedLog, CertifiedRecord, Log, Message, Namespace, Record, Timestamp,
+    CertifiedLog, CertifiedRecord, Log, Message, Namespace, Record, RetentionPolicy, Timestamp,
 };
 
 /// A client specification for interacting with DATO network validators.
@@ -18,6 +18,26 @@ pub trait ClientSpec {
         message: Message,
     ) -> Result<CertifiedRecord, ClientError>;
 
+    /// Write a batch of messages to the log for the given namespace, in a single round trip
+    /// to each validator. Returns the certified records in the order of the messages.
+    /// Validators reject batches of more than [`MAX_WRITE_BATCH`](crate::MAX_WRITE_BATCH)
+    /// messages with [`ValidatorError::BatchTooLarge`](crate::ValidatorError::BatchTooLarge).
+    async fn write_batch(
+        &self,
+        namespace: Namespace,
+        messages: Vec<Message>,
+    ) -> Result<Vec<CertifiedRecord>, ClientError>;
+
+    /// Write a message to the log for the given namespace, to be released after the given
+    /// time. The validators timestamp the message now, but withhold it from readers and
+    /// subscribers until the release time. Returns the certified record or a write error.
+    async fn write_delayed(
+        &self,
+        namespace: Namespace,
+        message: Message,
+        release_after: Timestamp,
+    ) -> Result<CertifiedRecord, ClientError>;
+
     /// Get the certified log for the given namespace and time range.
     async fn read_certified(
         &self,
@@ -35,16 +55,38 @@ pub trait ClientSpec {
     ) -> Result<Log, ClientError>;
 
     /// Attempt to read the message specified by the given namespace and message ID.
+    /// If the message is not available, returns a certificate that it had not been seen
+    /// as of the time of the request.
     async fn read_message(
         &self,
         namespace: Namespace,
         msg_id: B256,
     ) -> Result<CertifiedReadMessageResponse, ClientError>;
 
-    /// Subscribe to all messages in the given namespace.
+    /// Attempt to read the message specified by the given namespace and message ID, as of
+    /// the given time. If the message had not been seen by then, returns a certificate of
+    /// its unavailability as of that time.
+    async fn read_message_as_of(
+        &self,
+        namespace: Namespace,
+        msg_id: B256,
+        as_of: Timestamp,
+    ) -> Result<CertifiedReadMessageResponse, ClientError>;
+
+    /// Get the retention policy each validator advertises for the given namespace, along with
+    /// its ID. A validator may have pruned the records beyond its policy, so its attestation
+    /// that a message is unavailable doesn't mean that the message was never written.
+    async fn retention(
+        &self,
+        namespace: Namespace,
+    ) -> Result<Vec<(usize, RetentionPolicy)>, ClientError>;
+
+    /// Subscribe to all messages in the given namespace. Records are buffered while the
+    /// consumer lags behind, and the newest ones are dropped once the buffer is full.
     async fn subscribe(&self, namespace: Namespace) -> Result<ReceiverStream<Record>, ClientError>;
 
-    /// Subscribe to all certified records in the given namespace.
+    /// Subscribe to all certified records in the given namespace. Records are buffered while
+    /// the consumer lags behind, and the newest ones are dropped once the buffer is full.
     async fn subscribe_certified(
         &self,
         namespace: Namespace,
@@ -1,9 +1,13 @@
 use std::{
+    collections::BTreeMap,
     fmt,
     time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
 
-use alloy::primitives::{Bytes, Keccak256, B256};
+use alloy::{
+    primitives::{Bytes, Keccak256, B256, U256},
+    sol_types::SolValue,
+};
 use blst::min_pk::{
     AggregateSignature, PublicKey as BlsPublicKey, SecretKey as BlsSecretKey,
     Signature as BlsSignature,
@@ -11,11 +15,21 @@ use blst::min_pk::{
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
-use crate::bls::sign_with_prefix;
+use crate::{
+    abi,
+    bls::{
+        aggregate_verify, sign_with_dst, signature_to_eip2537, verify_signature_with_dst,
+        BLS_DST_PREFIX, BLS_SUBSCRIPTION_DST, BLS_UNAVAILABLE_DST,
+    },
+    codec, merkle,
+};
 
 /// A namespace for a log record.
 pub type Namespace = Bytes;
 
+/// The current format version of certificates.
+pub const CERTIFICATE_VERSION: u8 = 3;
+
 /// A message to be written to the log.
 #[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq, Eq)]
 pub struct Message(pub Bytes);
@@ -51,6 +65,47 @@ pub enum ClientError {
     Read(#[from] ReadError),
     #[error("Subscription error: {0:?}")]
     SubscriptionError(#[from] SubscriptionError),
+    #[error("Validator error: {0}")]
+    Validator(#[from] ValidatorError),
+}
+
+/// An error returned by a validator in place of a response, so that clients can tell why a
+/// request failed instead of timing out.
+#[derive(Debug, Clone, PartialEq, Eq, Error)]
+#[allow(missing_docs)]
+pub enum ValidatorError {
+    #[error("Unsupported protocol version {version}, the validator supports version {supported}")]
+    UnsupportedVersion { version: u8, supported: u8 },
+    #[error("Malformed request: {0}")]
+    MalformedRequest(String),
+    #[error("Namespace rejected")]
+    NamespaceRejected,
+    #[error("Rate limited")]
+    RateLimited,
+    #[error("Storage error: {0}")]
+    Storage(String),
+    #[error("Message is timelocked until {release_after}")]
+    Timelocked { release_after: Timestamp },
+    #[error("Cannot attest to unavailability before joining at {joined_at}")]
+    BeforeJoin { joined_at: Timestamp },
+    #[error("Cannot attest to unavailability while backfilling records written before joining")]
+    Backfilling,
+    #[error("Message of {size} bytes is larger than the limit of {max} bytes")]
+    MessageTooLarge { size: u64, max: u64 },
+    #[error("Namespace of {len} bytes is longer than the limit of {max} bytes")]
+    NamespaceTooLong { len: u64, max: u64 },
+    #[error("Already holding records for the maximum of {max} namespaces")]
+    TooManyNamespaces { max: u64 },
+    #[error("Cannot attest to unavailability as of a future time, it is {now}")]
+    FutureQueryTime { now: Timestamp },
+    #[error("Request not signed by an authorized key, or signed too long ago")]
+    Unauthorized,
+    #[error("Internal error: {0}")]
+    Internal(String),
+    #[error("Batch of {len} messages is larger than the limit of {max} messages")]
+    BatchTooLarge { len: u64, max: u64 },
+    #[error("Timelock full, withholding at most {max_records} records or {max_bytes} bytes")]
+    TimelockFull { max_records: u64, max_bytes: u64 },
 }
 
 /// An error that can occur when writing to the log.
@@ -61,8 +116,8 @@ pub enum WriteError {
     Timeout,
     #[error("Network error: {0:?}")]
     Network(#[from] msg::ReqError),
-    #[error("No quorum reached, only {got} out of {needed} validators signed")]
-    NoQuorum { got: usize, needed: usize },
+    #[error("No quorum reached, only {got} out of {total} stake signed")]
+    NoQuorum { got: u128, total: u128 },
 }
 
 /// An error that can occur when reading from the log.
@@ -71,8 +126,28 @@ pub enum WriteError {
 pub enum ReadError {
     #[error("Timed out")]
     Timeout,
-    #[error("No quorum reached, available: {available}, unavailable: {unavailable}")]
-    NoQuorum { available: usize, unavailable: usize },
+    #[error("No quorum reached, available stake: {available}, unavailable stake: {unavailable}")]
+    NoQuorum { available: u128, unavailable: u128 },
+    #[error("Message may have been pruned, validators pruned records up to {low_water_mark}")]
+    OutsideRetention { low_water_mark: Timestamp },
+}
+
+/// An error that can occur when verifying a certificate.
+#[derive(Debug, Error)]
+#[allow(missing_docs)]
+pub enum VerificationError {
+    #[error("Unsupported certificate version: {0}")]
+    UnsupportedVersion(u8),
+    #[error("Certificate is from epoch {certificate}, but the validator set is from epoch {validator_set}")]
+    EpochMismatch { certificate: u64, validator_set: u64 },
+    #[error("Signer {0} is not in the validator set")]
+    UnknownSigner(usize),
+    #[error("Missing timestamp for signer {0}")]
+    MissingTimestamp(usize),
+    #[error("No quorum reached, only {signed} out of {total} stake signed")]
+    NoQuorum { signed: u128, total: u128 },
+    #[error("Invalid quorum signature")]
+    InvalidSignature,
 }
 
 /// An error that can occur when subscribing to the log.
@@ -99,6 +174,11 @@ impl Timestamp {
         Timestamp(since_the_epoch.as_millis())
     }
 
+    /// Returns the duration from now until the timestamp, or zero if it is in the past.
+    pub fn time_until(&self) -> Duration {
+        Duration::from_millis(self.0.saturating_sub(Timestamp::now().0) as u64)
+    }
+
     /// Returns the duration since the given timestamp.
     pub fn duration_since(&self, other: Instant) -> Duration {
         let since = SystemTime::now().duration_since(UNIX_EPOCH).expect("Time went backwards");
@@ -148,11 +228,29 @@ impl std::ops::Div<u128> for Timestamp {
 
 /// A certified record of a message at a particular time. Contains
 /// the quorum signature for the message.
-/// The signature is over the msg_id, message, and timestamp.
+/// The signature is over the msg_id, message, and timestamp, or over the Merkle root of a
+/// batch of such records for signers that signed the message as part of a batch.
+///
+/// The stake backing the certificate can be derived from the validator set,
+/// as each signer is identified by its index in the signer bitfield.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CertifiedRecord {
+    /// The certificate format version.
+    pub version: u8,
+    /// The epoch of the validator set the certificate was produced under. The signer
+    /// indexes are only meaningful in that epoch's validator set.
+    pub epoch: u64,
+    /// The namespace the message was written to.
+    pub namespace: Namespace,
     /// An indexed array of timestamps. The index is the validator ID.
     pub timestamps: Vec<Timestamp>,
+    /// The validators that signed the record, indexed by validator ID.
+    pub signers: SignerBitfield,
+    /// An indexed array of the inclusion proofs of the record in the batch signed by each
+    /// validator, see [`merkle`]. The proof is empty for validators that signed the record
+    /// on its own. The index is the validator ID.
+    #[serde(default)]
+    pub proofs: Vec<Vec<B256>>,
     /// The message that was certified.
     pub message: Message,
     /// The aggregated signature for the message from all validators.
@@ -161,23 +259,86 @@ pub struct CertifiedRecord {
 }
 
 impl CertifiedRecord {
-    /// Returns the median of all the timestamps in the array.
-    pub fn certified_timestamp(&mut self) -> Timestamp {
-        self.timestamps.sort();
-        if self.timestamps.len() % 2 == 0 {
-            let mid = self.timestamps.len() / 2;
-            (self.timestamps[mid - 1] + self.timestamps[mid]) / 2
-        } else {
-            self.timestamps[self.timestamps.len() / 2]
+    /// Returns the median of the timestamps of all signers.
+    pub fn certified_timestamp(&self) -> Timestamp {
+        median_timestamp(self.signers.iter().filter_map(|i| self.timestamps.get(i).copied()))
+    }
+
+    /// Returns the message ID, i.e. the digest of the namespace and message.
+    pub fn msg_id(&self) -> B256 {
+        self.message.digest(&self.namespace)
+    }
+
+    /// Verifies the certificate against the given validator set, by reconstructing the
+    /// message signed by each signer from its own timestamp and inclusion proof, and checking
+    /// the quorum signature. The validator set must be the one of the certificate's epoch.
+    pub fn verify(&self, validator_set: &ValidatorSet) -> Result<(), VerificationError> {
+        check_version(self.version)?;
+        check_epoch(self.epoch, validator_set)?;
+
+        let messages = self
+            .signers
+            .iter()
+            .map(|index| {
+                let timestamp =
+                    self.timestamps.get(index).ok_or(VerificationError::MissingTimestamp(index))?;
+                let digest = self.message.record_digest(&self.namespace, *timestamp);
+                let proof = self.proofs.get(index).map_or(&[][..], Vec::as_slice);
+                Ok(merkle::signed_message(digest, proof))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        validator_set.verify_quorum_signature(
+            &self.signers,
+            &messages,
+            &self.quorum_signature,
+            BLS_DST_PREFIX,
+        )
+    }
+
+    /// Returns the ABI encoding of the certificate, as expected by the `DatoVerifier` contract.
+    pub fn abi_encode(&self) -> Bytes {
+        abi::CertifiedRecord {
+            version: self.version,
+            epoch: self.epoch,
+            namespace: self.namespace.clone(),
+            timestamps: self.timestamps.iter().map(|ts| ts.0).collect(),
+            signers: self.signers.clone().into(),
+            proofs: self.proofs.clone(),
+            message: self.message.0.clone(),
+            quorumSignature: encode_quorum_signature(&self.quorum_signature),
         }
+        .abi_encode()
+        .into()
     }
 
-    /// Returns the certified record from a list of records.
-    /// This method DOES NOT check the hash of each individual record message.
-    pub fn from_records_unchecked(records: &[Record]) -> Self {
-        let timestamps = records.iter().map(|r| r.timestamp).collect::<Vec<_>>();
-        let sigs = records.iter().map(|r| r.signature).collect::<Vec<_>>();
-        let message = records[0].message.clone();
+    /// Returns the certified record from a list of records in the given namespace, each
+    /// attributed to the ID of the validator that signed it in the given validator set. The
+    /// certificate is pinned to the epoch of the set, and its timestamps and signers are sized
+    /// to the set's [`index_bound`](ValidatorSet::index_bound), like those of certificates
+    /// built by the client.
+    /// This method DOES NOT check the hash or signature of each individual record.
+    pub fn from_records_unchecked(
+        namespace: Namespace,
+        records: &[(usize, Record)],
+        validator_set: &ValidatorSet,
+    ) -> Self {
+        let index_bound = records
+            .iter()
+            .map(|(index, _)| index + 1)
+            .fold(validator_set.index_bound(), usize::max);
+
+        let mut timestamps = vec![Timestamp::default(); index_bound];
+        let mut signers = SignerBitfield::with_len(index_bound);
+        let mut proofs = vec![Vec::new(); index_bound];
+        for (index, record) in records {
+            timestamps[*index] = record.timestamp;
+            signers.set(*index);
+            proofs[*index] = record.proof.clone();
+        }
+
+        let sigs = records.iter().map(|(_, r)| r.signature).collect::<Vec<_>>();
+        let message = records[0].1.message.clone();
 
         // TODO: there's probably a better way to do this
         let mut quorum_signature = AggregateSignature::from_signature(&sigs[0]);
@@ -185,7 +346,16 @@ impl CertifiedRecord {
             let _ = quorum_signature.add_signature(sig, false);
         }
 
-        CertifiedRecord { timestamps, message, quorum_signature }
+        CertifiedRecord {
+            version: CERTIFICATE_VERSION,
+            epoch: validator_set.epoch(),
+            namespace,
+            timestamps,
+            signers,
+            proofs,
+            message,
+            quorum_signature,
+        }
     }
 }
 
@@ -214,13 +384,29 @@ pub enum ReadMessageResponse {
     Available(Record),
     /// A record of the unavailability of the message.
     Unavailable(UnavailableMessage),
+    /// The message is not in the store, but the validator may have pruned its record, so it
+    /// can't attest that it never saw the message.
+    OutsideRetention {
+        /// The timestamp of the latest record the validator pruned in the namespace.
+        low_water_mark: Timestamp,
+    },
 }
 
 /// A certified "non-existence" record for a message.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CertifiedUnavailableMessage {
-    /// An indexed array of timestamps. The index is the validator ID.
+    /// The certificate format version.
+    pub version: u8,
+    /// The epoch of the validator set the certificate was produced under. The signer
+    /// indexes are only meaningful in that epoch's validator set.
+    pub epoch: u64,
+    /// The namespace the message was looked up in.
+    pub namespace: Namespace,
+    /// An indexed array of the times as of which each validator had not seen
+    /// the message. The index is the validator ID.
     pub timestamps: Vec<Timestamp>,
+    /// The validators that signed the unavailability, indexed by validator ID.
+    pub signers: SignerBitfield,
     /// The message ID that is unavailable.
     pub msg_id: B256,
     /// The aggregated signature for the message from all validators.
@@ -229,52 +415,183 @@ pub struct CertifiedUnavailableMessage {
 }
 
 impl CertifiedUnavailableMessage {
-    /// Returns the median of all the timestamps in the array.
-    pub fn certified_timestamp(&mut self) -> Timestamp {
-        self.timestamps.sort();
-        if self.timestamps.len() % 2 == 0 {
-            let mid = self.timestamps.len() / 2;
-            (self.timestamps[mid - 1] + self.timestamps[mid]) / 2
-        } else {
-            self.timestamps[self.timestamps.len() / 2]
+    /// Returns the median of the timestamps of all signers.
+    pub fn certified_timestamp(&self) -> Timestamp {
+        median_timestamp(self.signers.iter().filter_map(|i| self.timestamps.get(i).copied()))
+    }
+
+    /// Verifies the certificate against the given validator set, by reconstructing the
+    /// digest signed by each signer from its own timestamp and checking the quorum signature.
+    /// The validator set must be the one of the certificate's epoch.
+    pub fn verify(&self, validator_set: &ValidatorSet) -> Result<(), VerificationError> {
+        check_version(self.version)?;
+        check_epoch(self.epoch, validator_set)?;
+
+        let digests = self
+            .signers
+            .iter()
+            .map(|index| {
+                let timestamp =
+                    self.timestamps.get(index).ok_or(VerificationError::MissingTimestamp(index))?;
+                Ok(UnavailableMessage::compute_digest(&self.namespace, self.msg_id, *timestamp))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        validator_set.verify_quorum_signature(
+            &self.signers,
+            &digests,
+            &self.quorum_signature,
+            BLS_UNAVAILABLE_DST,
+        )
+    }
+
+    /// Returns the ABI encoding of the certificate, as expected by the `DatoVerifier` contract.
+    pub fn abi_encode(&self) -> Bytes {
+        abi::CertifiedUnavailableMessage {
+            version: self.version,
+            epoch: self.epoch,
+            namespace: self.namespace.clone(),
+            timestamps: self.timestamps.iter().map(|ts| ts.0).collect(),
+            signers: self.signers.clone().into(),
+            msgId: self.msg_id,
+            quorumSignature: encode_quorum_signature(&self.quorum_signature),
         }
+        .abi_encode()
+        .into()
+    }
+}
+
+/// Checks that the given certificate version is supported.
+fn check_version(version: u8) -> Result<(), VerificationError> {
+    if version != CERTIFICATE_VERSION {
+        return Err(VerificationError::UnsupportedVersion(version))
+    }
+
+    Ok(())
+}
+
+/// Checks that the given validator set is the one of the certificate's epoch.
+fn check_epoch(epoch: u64, validator_set: &ValidatorSet) -> Result<(), VerificationError> {
+    if epoch != validator_set.epoch() {
+        return Err(VerificationError::EpochMismatch {
+            certificate: epoch,
+            validator_set: validator_set.epoch(),
+        })
+    }
+
+    Ok(())
+}
+
+/// Encodes the given quorum signature as an uncompressed EIP-2537 G2 point.
+fn encode_quorum_signature(signature: &AggregateSignature) -> Bytes {
+    Bytes::copy_from_slice(&signature_to_eip2537(&signature.to_signature()))
+}
+
+/// Returns the median of the given timestamps, or the default timestamp if there are none.
+fn median_timestamp(timestamps: impl Iterator<Item = Timestamp>) -> Timestamp {
+    let mut timestamps = timestamps.collect::<Vec<_>>();
+    if timestamps.is_empty() {
+        return Timestamp::default()
+    }
+
+    timestamps.sort();
+    if timestamps.len() % 2 == 0 {
+        let mid = timestamps.len() / 2;
+        (timestamps[mid - 1] + timestamps[mid]) / 2
+    } else {
+        timestamps[timestamps.len() / 2]
     }
 }
 
-/// A signed "non-existence" record for a message.
+/// A bitfield of the validators that signed a certificate, where bit `i`
+/// is set if the validator with ID `i` signed.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "Bytes", into = "Bytes")]
+pub struct SignerBitfield(Vec<u8>);
+
+impl SignerBitfield {
+    /// Creates an empty bitfield that can hold at least `len` validators.
+    pub fn with_len(len: usize) -> Self {
+        Self(vec![0; len.div_ceil(8)])
+    }
+
+    /// Marks the validator with the given ID as a signer.
+    pub fn set(&mut self, index: usize) {
+        if index / 8 >= self.0.len() {
+            self.0.resize(index / 8 + 1, 0);
+        }
+
+        self.0[index / 8] |= 1 << (index % 8);
+    }
+
+    /// Returns true if the validator with the given ID is a signer.
+    pub fn is_set(&self, index: usize) -> bool {
+        self.0.get(index / 8).is_some_and(|byte| byte & (1 << (index % 8)) != 0)
+    }
+
+    /// Returns an iterator over the IDs of all signers, in ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        (0..self.0.len() * 8).filter(|i| self.is_set(*i))
+    }
+
+    /// Returns the number of signers.
+    pub fn count(&self) -> usize {
+        self.0.iter().map(|byte| byte.count_ones() as usize).sum()
+    }
+}
+
+impl From<Bytes> for SignerBitfield {
+    fn from(bytes: Bytes) -> Self {
+        Self(bytes.to_vec())
+    }
+}
+
+impl From<SignerBitfield> for Bytes {
+    fn from(bitfield: SignerBitfield) -> Self {
+        bitfield.0.into()
+    }
+}
+
+/// A signed "non-existence" record for a message, attesting that the message
+/// had not been seen in the namespace as of the given timestamp.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UnavailableMessage {
-    /// The timestamp of the unavailability message.
+    /// The time as of which the message had not been seen.
     pub timestamp: Timestamp,
     /// The message ID that is unavailable.
     pub msg_id: B256,
-    /// The signature for the message ID and timestamp.
+    /// The signature for the namespace, message ID and timestamp.
     #[serde(with = "serde_bls")]
     pub signature: BlsSignature,
 }
 
 impl UnavailableMessage {
-    /// Create a signed certificate for an unavailable message, by signing over its
-    /// message ID and timestamp with the given secret key.
-    pub fn create_signed(msg_id: B256, secret_key: &BlsSecretKey) -> Self {
-        let timestamp = Timestamp::now();
-        let digest = {
-            let mut hasher = Keccak256::new();
-            hasher.update(msg_id);
-            hasher.update(timestamp.0.to_le_bytes());
-            hasher.finalize()
-        };
+    /// Create a signed attestation for an unavailable message, by signing over its
+    /// namespace, message ID and the time as of which it was not seen with the given
+    /// secret key, using the unavailability domain separator.
+    pub fn create_signed(
+        namespace: &Namespace,
+        msg_id: B256,
+        as_of: Timestamp,
+        secret_key: &BlsSecretKey,
+    ) -> Self {
+        let digest = Self::compute_digest(namespace, msg_id, as_of);
+        let signature = sign_with_dst(secret_key, digest, BLS_UNAVAILABLE_DST);
 
-        let signature = sign_with_prefix(secret_key, digest);
+        UnavailableMessage { timestamp: as_of, msg_id, signature }
+    }
 
-        UnavailableMessage { timestamp, msg_id, signature }
+    /// Returns the digest of the namespace, message ID and timestamp.
+    pub fn digest(&self, namespace: &Namespace) -> B256 {
+        Self::compute_digest(namespace, self.msg_id, self.timestamp)
     }
 
-    /// Returns the digest of the message ID and timestamp.
-    pub fn digest(&self) -> B256 {
+    /// Returns the digest of the given namespace, message ID and timestamp.
+    pub fn compute_digest(namespace: &Namespace, msg_id: B256, timestamp: Timestamp) -> B256 {
         let mut hasher = Keccak256::new();
-        hasher.update(self.msg_id);
-        hasher.update(self.timestamp.0.to_le_bytes());
+        hasher.update(namespace);
+        hasher.update(msg_id);
+        hasher.update(timestamp.0.to_le_bytes());
         hasher.finalize()
     }
 }
@@ -286,9 +603,19 @@ pub struct Record {
     pub timestamp: Timestamp,
     /// The message that was observed.
     pub message: Message,
-    /// The signature for the namepsace, message, and timestamp.
+    /// The signature for the namepsace, message, and timestamp, or for the Merkle root of
+    /// the batch of records the record was signed with.
     #[serde(with = "serde_bls")]
     pub signature: BlsSignature,
+    /// The inclusion proof of the record in the batch it was signed with, see [`merkle`].
+    /// Empty if the record was signed on its own.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub proof: Vec<B256>,
+    /// The public key of the validator that signed the record, if it was synced from another
+    /// validator. Records without an origin are signed by the validator that serves them.
+    /// Unlike validator IDs, the key still identifies the signer after the epoch changed.
+    #[serde(default, skip_serializing_if = "Option::is_none", with = "serde_bls_pubkey_option")]
+    pub origin: Option<BlsPublicKey>,
 }
 
 mod serde_bls_aggregate {
@@ -321,6 +648,59 @@ mod serde_bls_aggregate {
     }
 }
 
+mod serde_bls_pubkey {
+    use blst::min_pk::PublicKey as BlsPublicKey;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(pubkey: &BlsPublicKey, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&alloy::hex::encode_prefixed(pubkey.compress()))
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<BlsPublicKey, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        alloy::hex::decode(String::deserialize(deserializer)?)
+            .map_err(serde::de::Error::custom)
+            .and_then(|bytes| {
+                BlsPublicKey::from_bytes(&bytes).map_err(|e| {
+                    serde::de::Error::custom(format!(
+                        "failed to deserialize BLS public key: {:?}",
+                        e
+                    ))
+                })
+            })
+    }
+}
+
+mod serde_bls_pubkey_option {
+    use blst::min_pk::PublicKey as BlsPublicKey;
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S>(pubkey: &Option<BlsPublicKey>, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        match pubkey {
+            Some(pubkey) => super::serde_bls_pubkey::serialize(pubkey, serializer),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D>(deserializer: D) -> Result<Option<BlsPublicKey>, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        #[derive(Deserialize)]
+        struct Wrapper(#[serde(with = "super::serde_bls_pubkey")] BlsPublicKey);
+
+        Ok(Option::<Wrapper>::deserialize(deserializer)?.map(|Wrapper(pubkey)| pubkey))
+    }
+}
+
 mod serde_bls {
     use blst::min_pk::Signature as BlsSignature;
     use serde::{Deserialize, Deserializer, Serializer};
@@ -360,6 +740,12 @@ impl Record {
         hasher.finalize()
     }
 
+    /// Returns the message covered by the signature of the record, i.e. the digest of the
+    /// record or the root of its batch, see [`merkle::signed_message`].
+    pub fn signed_message(&self, namespace: &Namespace) -> Vec<u8> {
+        merkle::signed_message(self.digest(namespace), &self.proof)
+    }
+
     /// Returns the inner message digest for the record.
     pub fn message_digest(&self, namespace: &Namespace) -> B256 {
         let mut hasher = Keccak256::new();
@@ -377,6 +763,16 @@ pub struct Log {
     pub records: Vec<Record>,
 }
 
+/// The response of a validator to a sync request: the records missing from the syncing
+/// validator within a page of the requested range, the oldest first.
+#[derive(Debug, Default)]
+pub struct SyncResponse {
+    /// The missing records, along with their namespace.
+    pub records: Vec<(Namespace, Record)>,
+    /// The start of the next page, if the requested range has more records.
+    pub next: Option<Timestamp>,
+}
+
 impl Log {
     /// Create a new log from a list of records.
     pub fn extend(&mut self, other: Log) {
@@ -394,24 +790,572 @@ impl Log {
     }
 }
 
-/// A validator identity, consisting of an index and a public key.
-#[derive(Debug, Clone)]
+/// How long a validator keeps the records of a namespace. Records beyond any of the limits
+/// are pruned, so a validator may answer that a message is unavailable because it pruned it.
+/// No limit is set by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RetentionPolicy {
+    /// The maximum number of records kept.
+    pub max_records: Option<u64>,
+    /// The maximum total size of the messages kept, in bytes.
+    pub max_bytes: Option<u64>,
+    /// The maximum age of the records kept, by their timestamp.
+    pub max_age: Option<Duration>,
+}
+
+impl RetentionPolicy {
+    /// Sets the maximum number of records kept.
+    pub fn with_max_records(mut self, max_records: u64) -> Self {
+        self.max_records = Some(max_records);
+        self
+    }
+
+    /// Sets the maximum total size of the messages kept, in bytes.
+    pub fn with_max_bytes(mut self, max_bytes: u64) -> Self {
+        self.max_bytes = Some(max_bytes);
+        self
+    }
+
+    /// Sets the maximum age of the records kept.
+    pub fn with_max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Returns the timestamp before which records are expired at the given time, if they
+    /// expire.
+    pub fn cutoff(&self, now: Timestamp) -> Option<Timestamp> {
+        self.max_age.map(|max_age| Timestamp(now.0.saturating_sub(max_age.as_millis())))
+    }
+
+    /// Returns true if the given number of records, with messages of the given total size,
+    /// exceeds the record or size limit.
+    pub(crate) fn is_exceeded(&self, records: usize, bytes: u64) -> bool {
+        self.max_records.is_some_and(|max| records as u64 > max) ||
+            self.max_bytes.is_some_and(|max| bytes > max)
+    }
+
+    /// Returns true if the given number of records, with messages of the given total size,
+    /// reaches the record or size limit, so that keeping another record prunes one.
+    pub(crate) fn is_reached(&self, records: usize, bytes: u64) -> bool {
+        self.max_records.is_some_and(|max| records as u64 >= max) ||
+            self.max_bytes.is_some_and(|max| bytes >= max)
+    }
+}
+
+/// A validator identity, consisting of an index, a public key and a stake.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ValidatorIdentity {
     /// The validator incremental index
     pub index: usize,
     /// The validator public key used to sign messages
     pub pubkey: BlsPublicKey,
+    /// The validator stake in wei, used to weigh its signature in quorums
+    pub stake: u128,
 }
 
 impl ValidatorIdentity {
-    /// Create a new `ValidatorIdentity` from an index and public key.
+    /// Create a new `ValidatorIdentity` from an index and public key, with a stake of 1.
     pub fn new(index: usize, pubkey: BlsPublicKey) -> Self {
-        ValidatorIdentity { index, pubkey }
+        ValidatorIdentity { index, pubkey, stake: 1 }
     }
+
+    /// Set the stake of the validator.
+    pub fn with_stake(mut self, stake: u128) -> Self {
+        self.stake = stake;
+        self
+    }
+}
+
+/// A set of validators, indexed by validator ID.
+///
+/// Validator IDs are only stable within an epoch: the registry numbers each version of the
+/// validator set, and certificates record the epoch they were produced under, so that they
+/// can be verified against the matching set after it has changed.
+#[derive(Debug, Clone, Default)]
+pub struct ValidatorSet {
+    /// The epoch of the validator set.
+    epoch: u64,
+    validators: BTreeMap<usize, ValidatorIdentity>,
+}
+
+impl ValidatorSet {
+    /// Create a new `ValidatorSet` from the given validator identities, in epoch 0.
+    pub fn new(validators: impl IntoIterator<Item = ValidatorIdentity>) -> Self {
+        Self { epoch: 0, validators: validators.into_iter().map(|v| (v.index, v)).collect() }
+    }
+
+    /// Set the epoch of the validator set.
+    pub fn with_epoch(mut self, epoch: u64) -> Self {
+        self.epoch = epoch;
+        self
+    }
+
+    /// Returns the epoch of the validator set.
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    /// Insert a validator in the set, replacing any validator with the same ID.
+    pub fn insert(&mut self, validator: ValidatorIdentity) {
+        self.validators.insert(validator.index, validator);
+    }
+
+    /// Remove the validator with the given ID, and return it if it was in the set.
+    pub fn remove(&mut self, index: usize) -> Option<ValidatorIdentity> {
+        self.validators.remove(&index)
+    }
+
+    /// Returns the validator with the given ID.
+    pub fn get(&self, index: usize) -> Option<&ValidatorIdentity> {
+        self.validators.get(&index)
+    }
+
+    /// Returns the validator with the given public key, e.g. the origin of a synced record.
+    pub fn find(&self, pubkey: &BlsPublicKey) -> Option<&ValidatorIdentity> {
+        self.validators.values().find(|validator| validator.pubkey == *pubkey)
+    }
+
+    /// Returns an iterator over all validators, ordered by ID.
+    pub fn iter(&self) -> impl Iterator<Item = &ValidatorIdentity> {
+        self.validators.values()
+    }
+
+    /// Returns the number of validators in the set.
+    pub fn len(&self) -> usize {
+        self.validators.len()
+    }
+
+    /// Returns true if the set is empty.
+    pub fn is_empty(&self) -> bool {
+        self.validators.is_empty()
+    }
+
+    /// Returns the size of an array indexed by validator ID, i.e. the highest ID plus one.
+    pub fn index_bound(&self) -> usize {
+        self.validators.keys().next_back().map_or(0, |index| index + 1)
+    }
+
+    /// Returns the total stake of all validators in the set.
+    pub fn total_stake(&self) -> u128 {
+        self.validators.values().map(|v| v.stake).sum()
+    }
+
+    /// Returns true if the given stake is a quorum of the total stake of the set.
+    pub fn is_quorum(&self, signed_stake: u128) -> bool {
+        has_reached_quorum(self.total_stake(), signed_stake)
+    }
+
+    /// Verifies that the given signers form a quorum, and that the aggregate signature
+    /// is valid over the given messages, one for each signer in ascending order.
+    fn verify_quorum_signature<M: AsRef<[u8]>>(
+        &self,
+        signers: &SignerBitfield,
+        messages: &[M],
+        signature: &AggregateSignature,
+        dst: &[u8],
+    ) -> Result<(), VerificationError> {
+        let mut pubkeys = Vec::with_capacity(messages.len());
+        let mut signed_stake = 0;
+
+        for index in signers.iter() {
+            let validator = self.get(index).ok_or(VerificationError::UnknownSigner(index))?;
+            pubkeys.push(&validator.pubkey);
+            signed_stake += validator.stake;
+        }
+
+        if !self.is_quorum(signed_stake) {
+            return Err(VerificationError::NoQuorum {
+                signed: signed_stake,
+                total: self.total_stake(),
+            })
+        }
+
+        if !aggregate_verify(&signature.to_signature(), &pubkeys, messages, dst) {
+            return Err(VerificationError::InvalidSignature)
+        }
+
+        Ok(())
+    }
+}
+
+/// Function to compute if the quorum has been reached. A quorum is reached when the signed stake
+/// is greater than or equal to 2/3 of the total stake of all validators.
+///
+/// With 1 or 2 validators of equal stake, this means that all of them need to sign.
+pub(crate) fn has_reached_quorum(total_stake: u128, signed_stake: u128) -> bool {
+    signed_stake > 0 &&
+        U256::from(signed_stake) * U256::from(3) >= U256::from(total_stake) * U256::from(2)
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct SubscribeResponse {
     pub port: u16,
-    pub auth_token: Bytes,
+    pub auth_token: SubscriptionToken,
+}
+
+/// A token issued by a validator that authorizes a subscriber to connect to its publisher
+/// socket for a namespace, until the token expires.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionToken {
+    /// The namespace the subscription is for.
+    pub namespace: Namespace,
+    /// The public key of the subscriber the token was issued to.
+    #[serde(with = "serde_bls_pubkey")]
+    pub subscriber: BlsPublicKey,
+    /// The time after which the token is no longer valid.
+    pub expiry: Timestamp,
+    /// The signature of the issuing validator over the namespace, subscriber and expiry.
+    #[serde(with = "serde_bls")]
+    pub signature: BlsSignature,
+}
+
+impl SubscriptionToken {
+    /// Create a token for the given namespace and subscriber that expires at the given time,
+    /// signed with the given secret key using the subscription domain separator.
+    pub fn create_signed(
+        namespace: Namespace,
+        subscriber: BlsPublicKey,
+        expiry: Timestamp,
+        secret_key: &BlsSecretKey,
+    ) -> Self {
+        let digest = Self::compute_digest(&namespace, &subscriber, expiry);
+        let signature = sign_with_dst(secret_key, digest, BLS_SUBSCRIPTION_DST);
+
+        SubscriptionToken { namespace, subscriber, expiry, signature }
+    }
+
+    /// Returns the digest of the given namespace, subscriber and expiry.
+    pub fn compute_digest(
+        namespace: &Namespace,
+        subscriber: &BlsPublicKey,
+        expiry: Timestamp,
+    ) -> B256 {
+        let mut hasher = Keccak256::new();
+        hasher.update(namespace);
+        hasher.update(subscriber.compress());
+        hasher.update(expiry.0.to_le_bytes());
+        hasher.finalize()
+    }
+
+    /// Returns true if the token has not expired and was signed by the given public key.
+    pub fn verify(&self, pubkey: &BlsPublicKey) -> bool {
+        let digest = Self::compute_digest(&self.namespace, &self.subscriber, self.expiry);
+
+        self.expiry > Timestamp::now() &&
+            verify_signature_with_dst(&self.signature, pubkey, digest, BLS_SUBSCRIPTION_DST)
+    }
+
+    /// Serializes the token, as presented to the publisher socket when connecting.
+    pub fn to_bytes(&self) -> Bytes {
+        codec::encode(self)
+    }
+
+    /// Deserializes a token presented to the publisher socket.
+    pub fn from_bytes(bytes: &[u8]) -> Option<Self> {
+        codec::decode(bytes).ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::bls::{random_bls_secret, sign_with_prefix, verify_signature};
+
+    #[test]
+    fn test_has_reached_quorum() {
+        // Equal stakes
+        assert!(has_reached_quorum(1, 1));
+        assert!(!has_reached_quorum(2, 1));
+        assert!(has_reached_quorum(2, 2));
+        assert!(has_reached_quorum(3, 2));
+        assert!(!has_reached_quorum(4, 2));
+        assert!(has_reached_quorum(4, 3));
+
+        // Stake-weighted: a single validator with 2/3 of the stake is enough
+        assert!(has_reached_quorum(300, 200));
+        assert!(!has_reached_quorum(300, 199));
+
+        assert!(!has_reached_quorum(0, 0));
+
+        // Stakes in wei don't overflow, e.g. 100 validators with a million ether each
+        let stake = 1_000_000 * 10u128.pow(18);
+        assert!(has_reached_quorum(100 * stake, 67 * stake));
+        assert!(!has_reached_quorum(100 * stake, 66 * stake));
+        assert!(has_reached_quorum(u128::MAX, u128::MAX));
+    }
+
+    #[test]
+    fn test_verify_certified_record() {
+        let namespace = Namespace::from_static(b"test");
+        let message = Message(Bytes::from_static(b"made with chatgpt"));
+
+        let keys = (0..3).map(|_| random_bls_secret()).collect::<Vec<_>>();
+        let validator_set = ValidatorSet::new(
+            keys.iter().enumerate().map(|(i, sk)| ValidatorIdentity::new(i, sk.sk_to_pk())),
+        );
+
+        // Validators 0 and 2 sign with their own timestamps
+        let mut timestamps = vec![Timestamp::default(); 3];
+        let mut signers = SignerBitfield::with_len(3);
+        let mut quorum_signature: Option<AggregateSignature> = None;
+        for index in [0, 2] {
+            let timestamp = Timestamp::from(1000 + index as u64);
+            let signature =
+                sign_with_prefix(&keys[index], message.record_digest(&namespace, timestamp));
+
+            timestamps[index] = timestamp;
+            signers.set(index);
+            match quorum_signature.as_mut() {
+                Some(q) => q.add_signature(&signature, false).unwrap(),
+                None => quorum_signature = Some(AggregateSignature::from_signature(&signature)),
+            }
+        }
+
+        let mut record = CertifiedRecord {
+            version: CERTIFICATE_VERSION,
+            epoch: 0,
+            namespace: namespace.clone(),
+            timestamps,
+            signers,
+            proofs: Vec::new(),
+            message,
+            quorum_signature: quorum_signature.unwrap(),
+        };
+        record.verify(&validator_set).unwrap();
+        assert_eq!(record.certified_timestamp(), Timestamp::from(1001u64));
+
+        // The certificate is bound to its namespace and version
+        record.namespace = Namespace::from_static(b"other");
+        assert!(matches!(record.verify(&validator_set), Err(VerificationError::InvalidSignature)));
+        record.namespace = namespace;
+        record.version = CERTIFICATE_VERSION + 1;
+        assert!(matches!(
+            record.verify(&validator_set),
+            Err(VerificationError::UnsupportedVersion(_))
+        ));
+        record.version = CERTIFICATE_VERSION;
+
+        // The certificate is only valid against the validator set of its epoch
+        assert!(matches!(
+            record.verify(&validator_set.clone().with_epoch(1)),
+            Err(VerificationError::EpochMismatch { certificate: 0, validator_set: 1 })
+        ));
+
+        // Claiming a different signer fails the signature check
+        record.signers = SignerBitfield::with_len(3);
+        record.signers.set(0);
+        record.signers.set(1);
+        record.timestamps[1] = Timestamp::from(1002u64);
+        assert!(matches!(record.verify(&validator_set), Err(VerificationError::InvalidSignature)));
+
+        // A single signer is not a quorum
+        record.signers = SignerBitfield::with_len(3);
+        record.signers.set(0);
+        assert!(matches!(
+            record.verify(&validator_set),
+            Err(VerificationError::NoQuorum { signed: 1, total: 3 })
+        ));
+    }
+
+    #[test]
+    fn test_verify_batched_certified_record() {
+        let namespace = Namespace::from_static(b"test");
+        let message = Message(Bytes::from_static(b"hello"));
+
+        let keys = (0..3).map(|_| random_bls_secret()).collect::<Vec<_>>();
+        let validator_set = ValidatorSet::new(
+            keys.iter().enumerate().map(|(i, sk)| ValidatorIdentity::new(i, sk.sk_to_pk())),
+        );
+
+        // Validator 0 signs the message on its own
+        let timestamp = Timestamp::from(1000u64);
+        let digest = message.record_digest(&namespace, timestamp);
+        let signature = sign_with_prefix(&keys[0], digest);
+        let single = Record {
+            timestamp,
+            message: message.clone(),
+            signature,
+            proof: Vec::new(),
+            origin: None,
+        };
+
+        // Validator 1 signs it in a batch with other records
+        let timestamp = Timestamp::from(1001u64);
+        let digest = message.record_digest(&namespace, timestamp);
+        let (_, mut proofs) =
+            merkle::build_tree(&[B256::repeat_byte(1), digest, B256::repeat_byte(2)]);
+        let proof = proofs.remove(1);
+        let signature = sign_with_prefix(&keys[1], merkle::signed_message(digest, &proof));
+        let batched =
+            Record { timestamp, message: message.clone(), signature, proof, origin: None };
+
+        let pubkey = keys[1].sk_to_pk();
+        assert!(verify_signature(&batched.signature, &pubkey, batched.signed_message(&namespace)));
+        assert!(!verify_signature(&batched.signature, &pubkey, batched.digest(&namespace)));
+
+        let mut record = CertifiedRecord::from_records_unchecked(
+            namespace,
+            &[(0, single), (1, batched)],
+            &validator_set,
+        );
+        record.verify(&validator_set).unwrap();
+
+        // The batch signature doesn't cover the record without its inclusion proof
+        record.proofs[1].clear();
+        assert!(matches!(record.verify(&validator_set), Err(VerificationError::InvalidSignature)));
+    }
+
+    #[test]
+    fn test_certified_record_from_attributed_records() {
+        let namespace = Namespace::from_static(b"test");
+        let message = Message(Bytes::from_static(b"hello"));
+
+        let keys = (0..3).map(|_| random_bls_secret()).collect::<Vec<_>>();
+        let validator_set = ValidatorSet::new(
+            keys.iter().enumerate().map(|(i, sk)| ValidatorIdentity::new(i, sk.sk_to_pk())),
+        );
+
+        // Records arrive out of validator order
+        let records = [2, 0]
+            .into_iter()
+            .map(|index| {
+                let timestamp = Timestamp::from(1000 + index as u64);
+                let digest = message.record_digest(&namespace, timestamp);
+                let signature = sign_with_prefix(&keys[index], digest);
+                (
+                    index,
+                    Record {
+                        timestamp,
+                        message: message.clone(),
+                        signature,
+                        proof: Vec::new(),
+                        origin: None,
+                    },
+                )
+            })
+            .collect::<Vec<_>>();
+
+        let record = CertifiedRecord::from_records_unchecked(namespace, &records, &validator_set);
+        assert_eq!(record.timestamps.len(), 3);
+        assert_eq!(record.signers.iter().collect::<Vec<_>>(), vec![0, 2]);
+        assert_eq!(record.timestamps[0], Timestamp::from(1000u64));
+        assert_eq!(record.timestamps[2], Timestamp::from(1002u64));
+        record.verify(&validator_set).unwrap();
+    }
+
+    #[test]
+    fn test_abi_encode_certified_record() {
+        let namespace = Namespace::from_static(b"test");
+        let message = Message(Bytes::from_static(b"hello"));
+        let timestamp = Timestamp::from(1000u64);
+        let signature =
+            sign_with_prefix(&random_bls_secret(), message.record_digest(&namespace, timestamp));
+        let record = CertifiedRecord::from_records_unchecked(
+            namespace.clone(),
+            &[(
+                0,
+                Record {
+                    timestamp,
+                    message: message.clone(),
+                    signature,
+                    proof: Vec::new(),
+                    origin: None,
+                },
+            )],
+            &ValidatorSet::default().with_epoch(7),
+        );
+
+        let decoded = abi::CertifiedRecord::abi_decode(&record.abi_encode(), true).unwrap();
+        assert_eq!(decoded.version, CERTIFICATE_VERSION);
+        assert_eq!(decoded.epoch, 7);
+        assert_eq!(decoded.namespace, namespace);
+        assert_eq!(decoded.timestamps, vec![1000u128]);
+        assert_eq!(decoded.signers, Bytes::from(record.signers.clone()));
+        assert_eq!(decoded.message, message.0);
+
+        // The signature is an uncompressed G2 point with each coordinate padded to 64 bytes
+        let serialized = signature.serialize();
+        let encoded = decoded.quorumSignature;
+        assert_eq!(encoded.len(), 256);
+        assert!(encoded.chunks(64).all(|coordinate| coordinate[..16] == [0; 16]));
+        assert_eq!(encoded[16..64], serialized[48..96]);
+        assert_eq!(encoded[80..128], serialized[..48]);
+        assert_eq!(encoded[144..192], serialized[144..192]);
+        assert_eq!(encoded[208..256], serialized[96..144]);
+    }
+
+    #[test]
+    fn test_unavailable_message_domain_separation() {
+        let namespace = Namespace::from_static(b"test");
+        let secret_key = random_bls_secret();
+        let pubkey = secret_key.sk_to_pk();
+
+        let unavailable =
+            UnavailableMessage::create_signed(&namespace, B256::ZERO, 1000u64.into(), &secret_key);
+        let digest = unavailable.digest(&namespace);
+
+        assert!(crate::bls::verify_signature_with_dst(
+            &unavailable.signature,
+            &pubkey,
+            digest,
+            BLS_UNAVAILABLE_DST
+        ));
+
+        // The signature is not valid as an availability signature over the same digest
+        assert!(!verify_signature(&unavailable.signature, &pubkey, digest));
+
+        // The signature is bound to the namespace
+        let other = Namespace::from_static(b"other");
+        assert_ne!(digest, unavailable.digest(&other));
+    }
+
+    #[test]
+    fn test_subscription_token() {
+        let namespace = Namespace::from_static(b"test");
+        let secret_key = random_bls_secret();
+        let pubkey = secret_key.sk_to_pk();
+
+        let subscriber = random_bls_secret().sk_to_pk();
+
+        let expiry = Timestamp::from(u128::from(Timestamp::now()) + 60_000);
+        let token =
+            SubscriptionToken::create_signed(namespace.clone(), subscriber, expiry, &secret_key);
+        let token = SubscriptionToken::from_bytes(&token.to_bytes()).unwrap();
+        assert!(token.verify(&pubkey));
+
+        // The token is only valid for the issuing validator
+        assert!(!token.verify(&random_bls_secret().sk_to_pk()));
+
+        // The token is bound to its namespace, subscriber and expiry
+        let mut forged = token.clone();
+        forged.namespace = Namespace::from_static(b"other");
+        assert!(!forged.verify(&pubkey));
+        forged.namespace = namespace.clone();
+        forged.subscriber = random_bls_secret().sk_to_pk();
+        assert!(!forged.verify(&pubkey));
+        forged.subscriber = subscriber;
+        forged.expiry = Timestamp::from(u128::from(expiry) + 1);
+        assert!(!forged.verify(&pubkey));
+
+        let expired =
+            SubscriptionToken::create_signed(namespace, subscriber, 1000u64.into(), &secret_key);
+        assert!(!expired.verify(&pubkey));
+    }
+
+    #[test]
+    fn test_signer_bitfield() {
+        let mut signers = SignerBitfield::with_len(3);
+        signers.set(0);
+        signers.set(2);
+        signers.set(9);
+
+        assert!(signers.is_set(0) && !signers.is_set(1) && signers.is_set(9));
+        assert_eq!(signers.iter().collect::<Vec<_>>(), vec![0, 2, 9]);
+        assert_eq!(signers.count(), 3);
+
+        let json = serde_json::to_string(&signers).unwrap();
+        assert_eq!(json, "\"0x0502\"");
+        assert_eq!(serde_json::from_str::<SignerBitfield>(&json).unwrap(), signers);
+    }
 }
@@ -5,19 +5,29 @@
 
 mod common;
 pub use common::{
-    CertifiedLog, CertifiedReadMessageResponse, CertifiedRecord, CertifiedUnavailableMessage, Log,
-    Message, Namespace, ReadError, ReadMessageResponse, Record, Timestamp, UnavailableMessage,
-    ValidatorIdentity, WriteError,
+    CertifiedLog, CertifiedReadMessageResponse, CertifiedRecord, CertifiedUnavailableMessage,
+    ClientError, Log, Message, Namespace, ReadError, ReadMessageResponse, Record, RetentionPolicy,
+    SignerBitfield, SubscriptionToken, SyncResponse, Timestamp, UnavailableMessage, ValidatorError,
+    ValidatorIdentity, ValidatorSet, VerificationError, WriteError, CERTIFICATE_VERSION,
 };
 
 mod primitives;
-pub use primitives::bls;
+pub use primitives::{abi, bls, codec, merkle};
 
 mod client;
-pub use client::{Client, ClientSpec};
+pub use client::{Client, ClientSpec, DEFAULT_RECONCILE_INTERVAL};
 
 mod validator;
-pub use validator::{Validator, ValidatorSpec};
+pub use validator::{
+    AdmissionPolicy, DataStore, FilesystemStore, InMemoryStore, Validator, ValidatorSpec,
+    MAX_WRITE_BATCH,
+};
 
 mod registry;
-pub use registry::{FilesystemRegistry, Registry, SmartContractRegistry};
+pub use registry::{
+    FilesystemRegistry, Registry, RegistryEvent, RegistryOperator, SmartContractRegistry,
+    ValidatorInfo,
+};
+
+#[cfg(test)]
+mod test_utils;
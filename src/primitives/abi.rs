@@ -0,0 +1,64 @@
+//! Solidity ABI bindings for DATO certificates, as consumed by the `DatoVerifier` contract.
+//!
+//! Certificates are encoded with `abi.encode`, so that they can be decoded on-chain with
+//! `abi.decode(certificate, (CertifiedRecord))`. Unlike the JSON representation, the quorum
+//! signature is encoded as an uncompressed G2 point in the EIP-2537 format, since the
+//! verifier can't cheaply decompress it.
+
+use alloy::sol;
+
+sol! {
+    /// The ABI representation of a [`crate::CertifiedRecord`].
+    #[derive(Debug, PartialEq, Eq)]
+    struct CertifiedRecord {
+        /// The certificate format version.
+        uint8 version;
+        /// The epoch of the validator set the certificate was produced under.
+        uint64 epoch;
+        /// The namespace the message was written to.
+        bytes namespace;
+        /// The timestamps signed by each validator, indexed by validator ID.
+        uint128[] timestamps;
+        /// The signer bitfield, where bit `i` is set if validator `i` signed.
+        bytes signers;
+        /// The inclusion proofs of the record in the batch signed by each validator,
+        /// indexed by validator ID. Empty for validators that signed the record on its own.
+        bytes32[][] proofs;
+        /// The message that was certified.
+        bytes message;
+        /// The aggregated signature, as an uncompressed EIP-2537 G2 point.
+        bytes quorumSignature;
+    }
+
+    /// The ABI representation of a [`crate::CertifiedUnavailableMessage`].
+    #[derive(Debug, PartialEq, Eq)]
+    struct CertifiedUnavailableMessage {
+        /// The certificate format version.
+        uint8 version;
+        /// The epoch of the validator set the certificate was produced under.
+        uint64 epoch;
+        /// The namespace the message was looked up in.
+        bytes namespace;
+        /// The times as of which each validator had not seen the message,
+        /// indexed by validator ID.
+        uint128[] timestamps;
+        /// The signer bitfield, where bit `i` is set if validator `i` signed.
+        bytes signers;
+        /// The message ID that is unavailable.
+        bytes32 msgId;
+        /// The aggregated signature, as an uncompressed EIP-2537 G2 point.
+        bytes quorumSignature;
+    }
+
+    /// The on-chain verifier of DATO certificates.
+    #[allow(missing_docs)]
+    interface DatoVerifier {
+        /// Reverts unless the given ABI-encoded `CertifiedRecord` is signed by a quorum
+        /// of the validators registered at the epoch of the certificate.
+        function verifyRecord(bytes calldata certificate) external view;
+
+        /// Reverts unless the given ABI-encoded `CertifiedUnavailableMessage` is signed
+        /// by a quorum of the validators registered at the epoch of the certificate.
+        function verifyUnavailable(bytes calldata certificate) external view;
+    }
+}
@@ -0,0 +1,92 @@
+//! Bloom filters over message IDs, used by validators to summarize the records they hold when
+//! syncing with each other.
+//!
+//! The bit positions of an item are derived from the hash of the item and a seed, so that a
+//! message that is a false positive in one filter is unlikely to be one in the next filter,
+//! built with another seed.
+
+use alloy::primitives::{Keccak256, B256};
+use serde::{Deserialize, Serialize};
+
+/// The number of bits per item of a filter built with [`BloomFilter::with_capacity`], for a
+/// false positive rate of about 1%.
+const BITS_PER_ITEM: usize = 10;
+
+/// The number of bit positions per item of a filter built with [`BloomFilter::with_capacity`].
+const HASHES: u8 = 7;
+
+/// A Bloom filter over message IDs.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BloomFilter {
+    /// The seed of the hash of each item.
+    pub seed: u64,
+    /// The number of bit positions set per item.
+    pub hashes: u8,
+    /// The bits of the filter.
+    pub bits: Vec<u8>,
+}
+
+impl BloomFilter {
+    /// Creates an empty filter sized for the given number of items, with the given seed.
+    pub fn with_capacity(items: usize, seed: u64) -> Self {
+        let len = (items * BITS_PER_ITEM).div_ceil(8).max(1);
+        Self { seed, hashes: HASHES, bits: vec![0; len] }
+    }
+
+    /// Adds an item to the filter.
+    pub fn insert(&mut self, item: B256) {
+        for position in self.positions(item) {
+            self.bits[position / 8] |= 1 << (position % 8);
+        }
+    }
+
+    /// Returns true if the item may have been added to the filter, and false if it definitely
+    /// wasn't.
+    pub fn contains(&self, item: B256) -> bool {
+        // An empty filter (e.g. a malformed one received from a peer) contains nothing
+        if self.bits.is_empty() {
+            return false
+        }
+
+        self.positions(item).all(|position| self.bits[position / 8] & (1 << (position % 8)) != 0)
+    }
+
+    /// Returns the bit positions of the given item, derived from two hashes of the item by
+    /// double hashing. The filter must not be empty.
+    fn positions(&self, item: B256) -> impl Iterator<Item = usize> {
+        let mut hasher = Keccak256::new();
+        hasher.update(self.seed.to_le_bytes());
+        hasher.update(item);
+        let hash = hasher.finalize();
+
+        let h1 = u64::from_le_bytes(hash[..8].try_into().expect("8 bytes"));
+        let h2 = u64::from_le_bytes(hash[8..16].try_into().expect("8 bytes"));
+
+        let len = self.bits.len() as u64 * 8;
+        (0..self.hashes as u64).map(move |i| (h1.wrapping_add(i.wrapping_mul(h2)) % len) as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bloom_filter() {
+        let items = (0..1000u64).map(|i| B256::left_padding_from(&i.to_be_bytes()));
+
+        let mut filter = BloomFilter::with_capacity(1000, 42);
+        for item in items.clone() {
+            filter.insert(item);
+        }
+        assert!(items.clone().all(|item| filter.contains(item)));
+
+        // The false positive rate is about 1%
+        let others = (1000..11000u64).map(|i| B256::left_padding_from(&i.to_be_bytes()));
+        let false_positives = others.filter(|item| filter.contains(*item)).count();
+        assert!(false_positives < 300, "{false_positives} false positives");
+
+        let empty = BloomFilter { seed: 0, hashes: HASHES, bits: Vec::new() };
+        assert!(!empty.contains(B256::ZERO));
+    }
+}
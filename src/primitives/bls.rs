@@ -9,11 +9,46 @@ use rand::{thread_rng, RngCore};
 /// The BLS Domain Separator used in Ethereum 2.0.
 pub const BLS_DST_PREFIX: &[u8] = b"BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
 
+/// The BLS Domain Separator used for unavailability attestations. It differs from
+/// [`BLS_DST_PREFIX`] so that an availability signature can never be reinterpreted
+/// as an unavailability one, and vice versa.
+pub const BLS_UNAVAILABLE_DST: &[u8] =
+    b"DATO_UNAVAILABLE_BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+/// The BLS Domain Separator used for subscription tokens, so that a token can never be
+/// reinterpreted as an attestation about a message.
+pub const BLS_SUBSCRIPTION_DST: &[u8] =
+    b"DATO_SUBSCRIPTION_BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+/// The BLS Domain Separator used by subscribers to sign their subscribe requests, so that
+/// a request can never be reinterpreted as a token or an attestation.
+pub const BLS_SUBSCRIBER_DST: &[u8] =
+    b"DATO_SUBSCRIBER_BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+/// The BLS Domain Separator used by validators to sign their sync requests, so that a sync
+/// request can never be reinterpreted as a subscribe request or an attestation.
+pub const BLS_SYNC_DST: &[u8] = b"DATO_SYNC_BLS_SIG_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
+/// The BLS Domain Separator used for proofs of possession, as defined by the `_POP_`
+/// ciphersuite that [`BLS_DST_PREFIX`] belongs to.
+pub const BLS_POP_DST: &[u8] = b"BLS_POP_BLS12381G2_XMD:SHA-256_SSWU_RO_POP_";
+
 /// Sign the given data with the given BLS secret key.
 /// Returns the BLS signature.
 #[inline]
 pub(crate) fn sign_with_prefix(key: &BlsSecretKey, data: impl AsRef<[u8]>) -> BlsSignature {
-    key.sign(data.as_ref(), BLS_DST_PREFIX, &[])
+    sign_with_dst(key, data, BLS_DST_PREFIX)
+}
+
+/// Sign the given data with the given BLS secret key and domain separator.
+/// Returns the BLS signature.
+#[inline]
+pub(crate) fn sign_with_dst(
+    key: &BlsSecretKey,
+    data: impl AsRef<[u8]>,
+    dst: &[u8],
+) -> BlsSignature {
+    key.sign(data.as_ref(), dst, &[])
 }
 
 /// Verify the given BLS signature against the given message digest and the public key.
@@ -24,8 +59,83 @@ pub(crate) fn verify_signature(
     pubkey: &BlsPublicKey,
     digest: impl AsRef<[u8]>,
 ) -> bool {
-    signature.verify(false, digest.as_ref(), BLS_DST_PREFIX, &[], pubkey, true) ==
-        BLST_ERROR::BLST_SUCCESS
+    verify_signature_with_dst(signature, pubkey, digest, BLS_DST_PREFIX)
+}
+
+/// Verify the given BLS signature against the given message digest, public key and
+/// domain separator. Returns `true` if the signature is valid, `false` otherwise.
+#[inline]
+pub(crate) fn verify_signature_with_dst(
+    signature: &BlsSignature,
+    pubkey: &BlsPublicKey,
+    digest: impl AsRef<[u8]>,
+    dst: &[u8],
+) -> bool {
+    signature.verify(false, digest.as_ref(), dst, &[], pubkey, true) == BLST_ERROR::BLST_SUCCESS
+}
+
+/// Verify the given aggregate BLS signature against the given messages and public keys,
+/// where each message is signed by the public key at the same position with the given domain
+/// separator. Returns `true` if the signature is valid, `false` otherwise.
+#[inline]
+pub(crate) fn aggregate_verify<M: AsRef<[u8]>>(
+    signature: &BlsSignature,
+    pubkeys: &[&BlsPublicKey],
+    messages: &[M],
+    dst: &[u8],
+) -> bool {
+    let messages = messages.iter().map(|message| message.as_ref()).collect::<Vec<_>>();
+    signature.aggregate_verify(true, &messages, dst, pubkeys, true) == BLST_ERROR::BLST_SUCCESS
+}
+
+/// Returns a proof of possession of the given secret key, i.e. a signature over its own
+/// compressed public key. Registering it along with the public key prevents rogue-key
+/// attacks on aggregate signatures.
+pub fn proof_of_possession(key: &BlsSecretKey) -> BlsSignature {
+    sign_with_dst(key, key.sk_to_pk().compress(), BLS_POP_DST)
+}
+
+/// Verify the given proof of possession of the secret key of the given public key.
+/// Returns `true` if the proof is valid, `false` otherwise.
+pub fn verify_proof_of_possession(pubkey: &BlsPublicKey, proof: &BlsSignature) -> bool {
+    verify_signature_with_dst(proof, pubkey, pubkey.compress(), BLS_POP_DST)
+}
+
+/// Encode the given BLS signature as an uncompressed G2 point in the format expected by the
+/// EIP-2537 precompiles, i.e. `x.c0 || x.c1 || y.c0 || y.c1` with each coordinate left-padded
+/// from 48 to 64 bytes.
+pub(crate) fn signature_to_eip2537(signature: &BlsSignature) -> [u8; 256] {
+    // The uncompressed serialization is `x.c1 || x.c0 || y.c1 || y.c0`, 48 bytes each
+    let serialized = signature.serialize();
+
+    let mut encoded = [0u8; 256];
+    for (i, coordinate) in [1, 0, 3, 2].into_iter().enumerate() {
+        let offset = coordinate * 64 + 16;
+        encoded[offset..offset + 48].copy_from_slice(&serialized[i * 48..(i + 1) * 48]);
+    }
+
+    encoded
+}
+
+/// Decode a BLS signature from an uncompressed G2 point in the format expected by the EIP-2537
+/// precompiles, as returned by [`signature_to_eip2537`]. Returns `None` if it is not a valid
+/// point, or if the padding of a coordinate is not zero.
+pub(crate) fn signature_from_eip2537(encoded: &[u8]) -> Option<BlsSignature> {
+    if encoded.len() != 256 {
+        return None
+    }
+
+    let mut serialized = [0u8; 192];
+    for (i, coordinate) in [1, 0, 3, 2].into_iter().enumerate() {
+        let offset = coordinate * 64;
+        if encoded[offset..offset + 16].iter().any(|byte| *byte != 0) {
+            return None
+        }
+
+        serialized[i * 48..(i + 1) * 48].copy_from_slice(&encoded[offset + 16..offset + 64]);
+    }
+
+    BlsSignature::from_bytes(&serialized).ok()
 }
 
 /// Generate a random BLS secret key.
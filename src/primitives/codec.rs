@@ -0,0 +1,852 @@
+//! Binary wire codec for the requests and responses exchanged with validators over the
+//! `msg` sockets. The HTTP API of the client keeps using JSON.
+//!
+//! Every encoded message starts with a version byte, followed by its fields in order:
+//! - integers are encoded in little-endian,
+//! - byte strings and lists are prefixed with their length as a `u32`,
+//! - hashes, BLS public keys and signatures are encoded as-is, in their fixed-size compressed form,
+//! - optional values and enums are prefixed with a one-byte tag.
+//!
+//! There is no negotiation between versions: messages are always encoded with the current
+//! [`WIRE_VERSION`], and a message of any other version is rejected, so clients and validators
+//! must run the same version. A validator answers a request of another version with a
+//! [`ValidatorError::UnsupportedVersion`] error.
+//!
+//! Responses are wrapped in a [`Response`], tagged `0` for a success and `1` for a
+//! [`ValidatorError`]. The wrapper and the layout of the `UnsupportedVersion` error, tagged `0`,
+//! never change, so that a client can tell that a validator doesn't support its version, e.g.
+//! during a rollout. Other errors may have been added after the version of the client, which
+//! then fails to decode them.
+
+use std::time::Duration;
+
+use alloy::primitives::{Bytes, B256};
+use blst::min_pk::{PublicKey as BlsPublicKey, Signature as BlsSignature};
+use thiserror::Error;
+
+use crate::{
+    common::{
+        Log, Message, ReadMessageResponse, Record, RetentionPolicy, SubscribeResponse,
+        SubscriptionToken, SyncResponse, Timestamp, UnavailableMessage, ValidatorError,
+    },
+    primitives::{bloom::BloomFilter, Request, Response},
+};
+
+/// The current version of the wire encoding. It is bumped on every change of the layout,
+/// including new request, response and error tags, which a peer of the previous version would
+/// fail to decode.
+pub const WIRE_VERSION: u8 = 11;
+
+/// The size of a compressed BLS signature.
+const SIGNATURE_LEN: usize = 96;
+
+/// The size of a compressed BLS public key.
+const PUBLIC_KEY_LEN: usize = 48;
+
+/// An error that can occur when decoding a message from the wire.
+#[derive(Debug, Error)]
+#[allow(missing_docs)]
+pub enum CodecError {
+    #[error("Unsupported wire version: {0}")]
+    UnsupportedVersion(u8),
+    #[error("Unexpected end of input")]
+    UnexpectedEof,
+    #[error("Invalid tag: {0}")]
+    InvalidTag(u8),
+    #[error("Invalid BLS signature")]
+    InvalidSignature,
+    #[error("Invalid BLS public key")]
+    InvalidPublicKey,
+    #[error("Invalid UTF-8 string")]
+    InvalidString,
+    #[error("{0} trailing bytes after the message")]
+    TrailingBytes(usize),
+}
+
+/// A type that can be encoded on the wire.
+pub trait Encode {
+    /// Appends the encoding of the value to the given buffer.
+    fn encode(&self, buf: &mut Vec<u8>);
+}
+
+/// A type that can be decoded from the wire.
+pub trait Decode: Sized {
+    /// Decodes a value from the start of the given buffer, and advances it past the value.
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError>;
+}
+
+/// Encodes the given value as a message of the current wire version.
+pub fn encode<T: Encode + ?Sized>(value: &T) -> Bytes {
+    let mut buf = vec![WIRE_VERSION];
+    value.encode(&mut buf);
+    buf.into()
+}
+
+/// Decodes a message, which must be of a supported wire version and contain exactly one value.
+pub fn decode<T: Decode>(bytes: &[u8]) -> Result<T, CodecError> {
+    let mut buf = bytes;
+
+    let version = u8::decode(&mut buf)?;
+    if version != WIRE_VERSION {
+        return Err(CodecError::UnsupportedVersion(version))
+    }
+
+    let value = T::decode(&mut buf)?;
+    if !buf.is_empty() {
+        return Err(CodecError::TrailingBytes(buf.len()))
+    }
+
+    Ok(value)
+}
+
+/// Decodes a response from a validator. Error responses are decoded whatever their version, as
+/// long as their error is known, see the [module docs](self).
+pub fn decode_response<T: Decode>(bytes: &[u8]) -> Result<Response<T>, CodecError> {
+    let mut buf = bytes;
+
+    let version = u8::decode(&mut buf)?;
+    let response = match u8::decode(&mut buf)? {
+        0 if version != WIRE_VERSION => return Err(CodecError::UnsupportedVersion(version)),
+        0 => Ok(T::decode(&mut buf)?),
+        1 => Err(ValidatorError::decode(&mut buf)?),
+        tag => return Err(CodecError::InvalidTag(tag)),
+    };
+
+    if !buf.is_empty() {
+        return Err(CodecError::TrailingBytes(buf.len()))
+    }
+
+    Ok(response)
+}
+
+/// Splits the first `len` bytes off the buffer.
+fn take<'a>(buf: &mut &'a [u8], len: usize) -> Result<&'a [u8], CodecError> {
+    if buf.len() < len {
+        return Err(CodecError::UnexpectedEof)
+    }
+
+    let (head, tail) = buf.split_at(len);
+    *buf = tail;
+    Ok(head)
+}
+
+/// Encodes the length of a byte string or list.
+fn encode_len(len: usize, buf: &mut Vec<u8>) {
+    u32::try_from(len).expect("Length fits in a u32").encode(buf);
+}
+
+macro_rules! impl_int {
+    ($($ty:ty),*) => {$(
+        impl Encode for $ty {
+            fn encode(&self, buf: &mut Vec<u8>) {
+                buf.extend_from_slice(&self.to_le_bytes());
+            }
+        }
+
+        impl Decode for $ty {
+            fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+                let bytes = take(buf, std::mem::size_of::<$ty>())?;
+                Ok(<$ty>::from_le_bytes(bytes.try_into().expect("Length checked")))
+            }
+        }
+    )*};
+}
+
+impl_int!(u8, u16, u32, u64, u128);
+
+impl Encode for () {
+    fn encode(&self, _buf: &mut Vec<u8>) {}
+}
+
+impl Decode for () {
+    fn decode(_buf: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(())
+    }
+}
+
+impl Encode for Bytes {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_len(self.len(), buf);
+        buf.extend_from_slice(self);
+    }
+}
+
+impl Decode for Bytes {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        let len = u32::decode(buf)? as usize;
+        Ok(Bytes::copy_from_slice(take(buf, len)?))
+    }
+}
+
+impl Encode for String {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_len(self.len(), buf);
+        buf.extend_from_slice(self.as_bytes());
+    }
+}
+
+impl Decode for String {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        let len = u32::decode(buf)? as usize;
+        String::from_utf8(take(buf, len)?.to_vec()).map_err(|_| CodecError::InvalidString)
+    }
+}
+
+impl Encode for B256 {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(self.as_slice());
+    }
+}
+
+impl Decode for B256 {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(B256::from_slice(take(buf, 32)?))
+    }
+}
+
+impl Encode for BlsSignature {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.compress());
+    }
+}
+
+impl Decode for BlsSignature {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        BlsSignature::from_bytes(take(buf, SIGNATURE_LEN)?)
+            .map_err(|_| CodecError::InvalidSignature)
+    }
+}
+
+impl Encode for BlsPublicKey {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        buf.extend_from_slice(&self.compress());
+    }
+}
+
+impl Decode for BlsPublicKey {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        BlsPublicKey::from_bytes(take(buf, PUBLIC_KEY_LEN)?)
+            .map_err(|_| CodecError::InvalidPublicKey)
+    }
+}
+
+impl<T: Encode> Encode for Option<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            None => buf.push(0),
+            Some(value) => {
+                buf.push(1);
+                value.encode(buf);
+            }
+        }
+    }
+}
+
+impl<T: Decode> Decode for Option<T> {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        match u8::decode(buf)? {
+            0 => Ok(None),
+            1 => Ok(Some(T::decode(buf)?)),
+            tag => Err(CodecError::InvalidTag(tag)),
+        }
+    }
+}
+
+impl<T: Encode> Encode for [T] {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        encode_len(self.len(), buf);
+        for value in self {
+            value.encode(buf);
+        }
+    }
+}
+
+impl<T: Encode> Encode for Vec<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.as_slice().encode(buf);
+    }
+}
+
+impl<T: Decode> Decode for Vec<T> {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        let len = u32::decode(buf)? as usize;
+
+        // Don't trust the length for the allocation, each value takes at least a byte
+        let mut values = Vec::with_capacity(len.min(buf.len()));
+        for _ in 0..len {
+            values.push(T::decode(buf)?);
+        }
+
+        Ok(values)
+    }
+}
+
+impl<A: Encode, B: Encode> Encode for (A, B) {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+        self.1.encode(buf);
+    }
+}
+
+impl<A: Decode, B: Decode> Decode for (A, B) {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok((A::decode(buf)?, B::decode(buf)?))
+    }
+}
+
+// Responses are decoded with [`decode_response`], which also handles the version.
+impl<T: Encode> Encode for Response<T> {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Ok(value) => {
+                buf.push(0);
+                value.encode(buf);
+            }
+            Err(err) => {
+                buf.push(1);
+                err.encode(buf);
+            }
+        }
+    }
+}
+
+impl Encode for ValidatorError {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            ValidatorError::UnsupportedVersion { version, supported } => {
+                buf.push(0);
+                version.encode(buf);
+                supported.encode(buf);
+            }
+            ValidatorError::MalformedRequest(reason) => {
+                buf.push(1);
+                reason.encode(buf);
+            }
+            ValidatorError::NamespaceRejected => buf.push(2),
+            ValidatorError::RateLimited => buf.push(3),
+            ValidatorError::Storage(reason) => {
+                buf.push(4);
+                reason.encode(buf);
+            }
+            ValidatorError::Timelocked { release_after } => {
+                buf.push(5);
+                release_after.encode(buf);
+            }
+            ValidatorError::BeforeJoin { joined_at } => {
+                buf.push(6);
+                joined_at.encode(buf);
+            }
+            ValidatorError::Backfilling => buf.push(7),
+            ValidatorError::MessageTooLarge { size, max } => {
+                buf.push(8);
+                size.encode(buf);
+                max.encode(buf);
+            }
+            ValidatorError::NamespaceTooLong { len, max } => {
+                buf.push(9);
+                len.encode(buf);
+                max.encode(buf);
+            }
+            ValidatorError::TooManyNamespaces { max } => {
+                buf.push(10);
+                max.encode(buf);
+            }
+            ValidatorError::FutureQueryTime { now } => {
+                buf.push(11);
+                now.encode(buf);
+            }
+            ValidatorError::Unauthorized => buf.push(12),
+            ValidatorError::Internal(reason) => {
+                buf.push(13);
+                reason.encode(buf);
+            }
+            ValidatorError::BatchTooLarge { len, max } => {
+                buf.push(14);
+                len.encode(buf);
+                max.encode(buf);
+            }
+            ValidatorError::TimelockFull { max_records, max_bytes } => {
+                buf.push(15);
+                max_records.encode(buf);
+                max_bytes.encode(buf);
+            }
+        }
+    }
+}
+
+impl Decode for ValidatorError {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(match u8::decode(buf)? {
+            0 => ValidatorError::UnsupportedVersion {
+                version: Decode::decode(buf)?,
+                supported: Decode::decode(buf)?,
+            },
+            1 => ValidatorError::MalformedRequest(Decode::decode(buf)?),
+            2 => ValidatorError::NamespaceRejected,
+            3 => ValidatorError::RateLimited,
+            4 => ValidatorError::Storage(Decode::decode(buf)?),
+            5 => ValidatorError::Timelocked { release_after: Decode::decode(buf)? },
+            6 => ValidatorError::BeforeJoin { joined_at: Decode::decode(buf)? },
+            7 => ValidatorError::Backfilling,
+            8 => ValidatorError::MessageTooLarge {
+                size: Decode::decode(buf)?,
+                max: Decode::decode(buf)?,
+            },
+            9 => ValidatorError::NamespaceTooLong {
+                len: Decode::decode(buf)?,
+                max: Decode::decode(buf)?,
+            },
+            10 => ValidatorError::TooManyNamespaces { max: Decode::decode(buf)? },
+            11 => ValidatorError::FutureQueryTime { now: Decode::decode(buf)? },
+            12 => ValidatorError::Unauthorized,
+            13 => ValidatorError::Internal(Decode::decode(buf)?),
+            14 => ValidatorError::BatchTooLarge {
+                len: Decode::decode(buf)?,
+                max: Decode::decode(buf)?,
+            },
+            15 => ValidatorError::TimelockFull {
+                max_records: Decode::decode(buf)?,
+                max_bytes: Decode::decode(buf)?,
+            },
+            tag => return Err(CodecError::InvalidTag(tag)),
+        })
+    }
+}
+
+impl Encode for Timestamp {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        u128::from(*self).encode(buf);
+    }
+}
+
+impl Decode for Timestamp {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Timestamp::from(u128::decode(buf)?))
+    }
+}
+
+impl Encode for Message {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.0.encode(buf);
+    }
+}
+
+impl Decode for Message {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Message(Bytes::decode(buf)?))
+    }
+}
+
+impl Encode for Request {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            Request::Write { namespace, message } => {
+                buf.push(0);
+                namespace.encode(buf);
+                message.encode(buf);
+            }
+            Request::ReadRange { namespace, start, end } => {
+                buf.push(1);
+                namespace.encode(buf);
+                start.encode(buf);
+                end.encode(buf);
+            }
+            Request::ReadMessage { namespace, msg_id, as_of } => {
+                buf.push(2);
+                namespace.encode(buf);
+                msg_id.encode(buf);
+                as_of.encode(buf);
+            }
+            Request::Subscribe { namespace, subscriber, timestamp, signature } => {
+                buf.push(3);
+                namespace.encode(buf);
+                subscriber.encode(buf);
+                timestamp.encode(buf);
+                signature.encode(buf);
+            }
+            Request::WriteBatch { namespace, messages } => {
+                buf.push(4);
+                namespace.encode(buf);
+                messages.encode(buf);
+            }
+            Request::WriteDelayed { namespace, message, release_after } => {
+                buf.push(5);
+                namespace.encode(buf);
+                message.encode(buf);
+                release_after.encode(buf);
+            }
+            Request::Sync { syncer, start, end, filters, timestamp, signature } => {
+                buf.push(6);
+                syncer.encode(buf);
+                start.encode(buf);
+                end.encode(buf);
+                filters.encode(buf);
+                timestamp.encode(buf);
+                signature.encode(buf);
+            }
+            Request::Retention { namespace } => {
+                buf.push(7);
+                namespace.encode(buf);
+            }
+        }
+    }
+}
+
+impl Decode for Request {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(match u8::decode(buf)? {
+            0 => Request::Write { namespace: Decode::decode(buf)?, message: Decode::decode(buf)? },
+            1 => Request::ReadRange {
+                namespace: Decode::decode(buf)?,
+                start: Decode::decode(buf)?,
+                end: Decode::decode(buf)?,
+            },
+            2 => Request::ReadMessage {
+                namespace: Decode::decode(buf)?,
+                msg_id: Decode::decode(buf)?,
+                as_of: Decode::decode(buf)?,
+            },
+            3 => Request::Subscribe {
+                namespace: Decode::decode(buf)?,
+                subscriber: Decode::decode(buf)?,
+                timestamp: Decode::decode(buf)?,
+                signature: Decode::decode(buf)?,
+            },
+            4 => Request::WriteBatch {
+                namespace: Decode::decode(buf)?,
+                messages: Decode::decode(buf)?,
+            },
+            5 => Request::WriteDelayed {
+                namespace: Decode::decode(buf)?,
+                message: Decode::decode(buf)?,
+                release_after: Decode::decode(buf)?,
+            },
+            6 => Request::Sync {
+                syncer: Decode::decode(buf)?,
+                start: Decode::decode(buf)?,
+                end: Decode::decode(buf)?,
+                filters: Decode::decode(buf)?,
+                timestamp: Decode::decode(buf)?,
+                signature: Decode::decode(buf)?,
+            },
+            7 => Request::Retention { namespace: Decode::decode(buf)? },
+            tag => return Err(CodecError::InvalidTag(tag)),
+        })
+    }
+}
+
+impl Encode for Record {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.timestamp.encode(buf);
+        self.message.encode(buf);
+        self.signature.encode(buf);
+        self.proof.encode(buf);
+        self.origin.encode(buf);
+    }
+}
+
+impl Decode for Record {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Record {
+            timestamp: Decode::decode(buf)?,
+            message: Decode::decode(buf)?,
+            signature: Decode::decode(buf)?,
+            proof: Decode::decode(buf)?,
+            origin: Decode::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for BloomFilter {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.seed.encode(buf);
+        self.hashes.encode(buf);
+        Bytes::copy_from_slice(&self.bits).encode(buf);
+    }
+}
+
+impl Decode for BloomFilter {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(BloomFilter {
+            seed: Decode::decode(buf)?,
+            hashes: Decode::decode(buf)?,
+            bits: Bytes::decode(buf)?.to_vec(),
+        })
+    }
+}
+
+impl Encode for RetentionPolicy {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.max_records.encode(buf);
+        self.max_bytes.encode(buf);
+        // The maximum age is encoded in milliseconds, like timestamps
+        self.max_age.map(|max_age| max_age.as_millis() as u64).encode(buf);
+    }
+}
+
+impl Decode for RetentionPolicy {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(RetentionPolicy {
+            max_records: Decode::decode(buf)?,
+            max_bytes: Decode::decode(buf)?,
+            max_age: Option::<u64>::decode(buf)?.map(Duration::from_millis),
+        })
+    }
+}
+
+impl Encode for Log {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.records.encode(buf);
+    }
+}
+
+impl Decode for Log {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(Log { records: Decode::decode(buf)? })
+    }
+}
+
+impl Encode for SyncResponse {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.records.encode(buf);
+        self.next.encode(buf);
+    }
+}
+
+impl Decode for SyncResponse {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(SyncResponse { records: Decode::decode(buf)?, next: Decode::decode(buf)? })
+    }
+}
+
+impl Encode for UnavailableMessage {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.timestamp.encode(buf);
+        self.msg_id.encode(buf);
+        self.signature.encode(buf);
+    }
+}
+
+impl Decode for UnavailableMessage {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(UnavailableMessage {
+            timestamp: Decode::decode(buf)?,
+            msg_id: Decode::decode(buf)?,
+            signature: Decode::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for ReadMessageResponse {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        match self {
+            ReadMessageResponse::Available(record) => {
+                buf.push(0);
+                record.encode(buf);
+            }
+            ReadMessageResponse::Unavailable(unavailable) => {
+                buf.push(1);
+                unavailable.encode(buf);
+            }
+            ReadMessageResponse::OutsideRetention { low_water_mark } => {
+                buf.push(2);
+                low_water_mark.encode(buf);
+            }
+        }
+    }
+}
+
+impl Decode for ReadMessageResponse {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(match u8::decode(buf)? {
+            0 => ReadMessageResponse::Available(Decode::decode(buf)?),
+            1 => ReadMessageResponse::Unavailable(Decode::decode(buf)?),
+            2 => ReadMessageResponse::OutsideRetention { low_water_mark: Decode::decode(buf)? },
+            tag => return Err(CodecError::InvalidTag(tag)),
+        })
+    }
+}
+
+impl Encode for SubscriptionToken {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.namespace.encode(buf);
+        self.subscriber.encode(buf);
+        self.expiry.encode(buf);
+        self.signature.encode(buf);
+    }
+}
+
+impl Decode for SubscriptionToken {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(SubscriptionToken {
+            namespace: Decode::decode(buf)?,
+            subscriber: Decode::decode(buf)?,
+            expiry: Decode::decode(buf)?,
+            signature: Decode::decode(buf)?,
+        })
+    }
+}
+
+impl Encode for SubscribeResponse {
+    fn encode(&self, buf: &mut Vec<u8>) {
+        self.port.encode(buf);
+        self.auth_token.encode(buf);
+    }
+}
+
+impl Decode for SubscribeResponse {
+    fn decode(buf: &mut &[u8]) -> Result<Self, CodecError> {
+        Ok(SubscribeResponse { port: Decode::decode(buf)?, auth_token: Decode::decode(buf)? })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        bls::{random_bls_secret, verify_signature_with_dst, BLS_SYNC_DST},
+        primitives::bloom::BloomFilter,
+        test_utils::signed_record,
+        Namespace,
+    };
+
+    fn record(message: &'static [u8]) -> Record {
+        signed_record(&Namespace::from_static(b"test"), message, 1_700_000_000_000u128)
+    }
+
+    #[test]
+    fn test_roundtrip() {
+        let request = Request::ReadMessage {
+            namespace: Bytes::from_static(b"test"),
+            msg_id: B256::repeat_byte(0xab),
+            as_of: Some(Timestamp::from(42u128)),
+        };
+        let decoded: Request = decode(&encode(&request)).unwrap();
+        assert!(matches!(
+            decoded,
+            Request::ReadMessage { msg_id, as_of: Some(as_of), .. }
+                if msg_id == B256::repeat_byte(0xab) && as_of == Timestamp::from(42u128)
+        ));
+
+        let request = Request::WriteDelayed {
+            namespace: Bytes::from_static(b"test"),
+            message: Message(Bytes::from_static(b"hello")),
+            release_after: Timestamp::from(42u128),
+        };
+        let decoded: Request = decode(&encode(&request)).unwrap();
+        assert!(matches!(
+            decoded,
+            Request::WriteDelayed { message, release_after, .. }
+                if message.0 == b"hello"[..] && release_after == Timestamp::from(42u128)
+        ));
+
+        let secret_key = random_bls_secret();
+        let request = Request::subscribe(Bytes::from_static(b"test"), &secret_key);
+        let decoded: Request = decode(&encode(&request)).unwrap();
+        assert!(matches!(
+            decoded,
+            Request::Subscribe { subscriber, .. } if subscriber == secret_key.sk_to_pk()
+        ));
+
+        let mut filter = BloomFilter::with_capacity(1, 7);
+        filter.insert(B256::repeat_byte(0xab));
+        let filters = vec![(Namespace::from_static(b"test"), filter)];
+        let request = Request::sync(0u64.into(), 42u64.into(), filters, &secret_key);
+        let Request::Sync { syncer, start, end, filters, timestamp, signature } =
+            decode(&encode(&request)).unwrap()
+        else {
+            panic!("Expected a sync request");
+        };
+        assert!(filters[0].1.contains(B256::repeat_byte(0xab)));
+
+        // The signature covers the filters as decoded
+        let digest = Request::sync_digest(&syncer, start, end, &filters, timestamp);
+        assert!(verify_signature_with_dst(&signature, &syncer, digest, BLS_SYNC_DST));
+
+        let response = SyncResponse {
+            records: vec![(Namespace::from_static(b"test"), record(b"hello"))],
+            next: Some(42u64.into()),
+        };
+        let decoded: SyncResponse = decode(&encode(&response)).unwrap();
+        assert_eq!(decoded.records[0].1.message, response.records[0].1.message);
+        assert_eq!(decoded.next, response.next);
+
+        let policy = RetentionPolicy::default()
+            .with_max_records(4096)
+            .with_max_age(Duration::from_secs(60 * 60));
+        assert_eq!(decode::<RetentionPolicy>(&encode(&policy)).unwrap(), policy);
+
+        let log = Log { records: vec![record(b"hello"), record(b"world")] };
+        let decoded: Log = decode(&encode(&log)).unwrap();
+        assert_eq!(decoded.records.len(), 2);
+        assert_eq!(decoded.records[1].message, log.records[1].message);
+        assert_eq!(decoded.records[1].timestamp, log.records[1].timestamp);
+        assert_eq!(decoded.records[1].signature, log.records[1].signature);
+
+        let mut synced = record(b"hello");
+        synced.origin = Some(random_bls_secret().sk_to_pk());
+        assert_eq!(decode::<Record>(&encode(&synced)).unwrap().origin, synced.origin);
+
+        // A record is its fields, a signature, an empty proof, no origin and the version byte
+        let encoded = encode(&record(b"hello"));
+        assert_eq!(encoded.len(), 1 + 16 + 4 + 5 + SIGNATURE_LEN + 4 + 1);
+        assert!(encoded.len() * 2 < serde_json::to_vec(&record(b"hello")).unwrap().len());
+    }
+
+    #[test]
+    fn test_decode_errors() {
+        let encoded = encode(&record(b"hello"));
+
+        let mut wrong_version = encoded.to_vec();
+        wrong_version[0] = WIRE_VERSION + 1;
+        assert!(matches!(decode::<Record>(&wrong_version), Err(CodecError::UnsupportedVersion(_))));
+
+        assert!(matches!(
+            decode::<Record>(&encoded[..encoded.len() - 1]),
+            Err(CodecError::UnexpectedEof)
+        ));
+
+        let mut trailing = encoded.to_vec();
+        trailing.push(0);
+        assert!(matches!(decode::<Record>(&trailing), Err(CodecError::TrailingBytes(1))));
+
+        // JSON is not accepted on the wire
+        let json = serde_json::to_vec(&record(b"hello")).unwrap();
+        assert!(decode::<Record>(&json).is_err());
+    }
+
+    #[test]
+    fn test_error_responses() {
+        let ok: Response<Record> = Ok(record(b"hello"));
+        let decoded = decode_response::<Record>(&encode(&ok)).unwrap().unwrap();
+        assert_eq!(decoded.message, record(b"hello").message);
+
+        for err in [
+            ValidatorError::Storage("disk full".to_owned()),
+            ValidatorError::MessageTooLarge { size: 2048, max: 1024 },
+            ValidatorError::NamespaceTooLong { len: 300, max: 256 },
+            ValidatorError::TooManyNamespaces { max: 16 },
+            ValidatorError::FutureQueryTime { now: Timestamp::now() },
+            ValidatorError::Unauthorized,
+            ValidatorError::Internal("address in use".to_owned()),
+            ValidatorError::BatchTooLarge { len: 4097, max: 4096 },
+            ValidatorError::TimelockFull { max_records: 4096, max_bytes: 1 << 24 },
+        ] {
+            let encoded = encode::<Response<Record>>(&Err(err.clone()));
+            assert_eq!(decode_response::<Record>(&encoded).unwrap().unwrap_err(), err);
+        }
+
+        // Errors can be decoded from a validator that runs another version, but responses can't
+        let err = ValidatorError::UnsupportedVersion { version: 0, supported: WIRE_VERSION + 1 };
+        let mut other_version = encode::<Response<Log>>(&Err(err.clone())).to_vec();
+        other_version[0] = WIRE_VERSION + 1;
+        assert_eq!(decode_response::<Log>(&other_version).unwrap().unwrap_err(), err);
+
+        let mut other_version = encode(&ok).to_vec();
+        other_version[0] = WIRE_VERSION + 1;
+        assert!(matches!(
+            decode_response::<Record>(&other_version),
+            Err(CodecError::UnsupportedVersion(_))
+        ));
+    }
+}
@@ -0,0 +1,113 @@
+//! Merkle trees over record digests, used by validators to sign a batch of records with a
+//! single signature.
+//!
+//! Pairs of nodes are hashed in sorted order, so that an inclusion proof is just the list of
+//! sibling hashes from the leaf to the root, as with OpenZeppelin's `MerkleProof`. Leaves are
+//! hashed once more than the record digest, so that an inner node can't be passed off as the
+//! digest of a record.
+//!
+//! A record signed on its own has an empty proof, and its signature is over its digest. A
+//! record signed as part of a batch is signed over [`BATCH_PREFIX`] followed by the root: as
+//! the signed messages have different lengths, a batch signature can't be reinterpreted as the
+//! signature of a single record, and vice versa.
+
+use alloy::primitives::{keccak256, Keccak256, B256};
+
+/// The prefix of the message signed for a batch of records.
+pub const BATCH_PREFIX: &[u8] = b"DATO_BATCH";
+
+/// Returns the leaf of the given record digest.
+pub fn leaf(digest: B256) -> B256 {
+    keccak256(digest)
+}
+
+/// Returns the hash of a pair of nodes, in sorted order.
+fn hash_pair(a: B256, b: B256) -> B256 {
+    let (left, right) = if a <= b { (a, b) } else { (b, a) };
+
+    let mut hasher = Keccak256::new();
+    hasher.update(left);
+    hasher.update(right);
+    hasher.finalize()
+}
+
+/// Returns the root of the tree that includes the given leaf, from its inclusion proof.
+pub fn process_proof(leaf: B256, proof: &[B256]) -> B256 {
+    proof.iter().fold(leaf, |node, sibling| hash_pair(node, *sibling))
+}
+
+/// Builds the tree of the given record digests, and returns its root along with the inclusion
+/// proof of each digest, in order. The last node of a level with an odd number of nodes is
+/// moved up to the next level as-is.
+///
+/// # Panics
+///
+/// Panics if there are no digests.
+pub fn build_tree(digests: &[B256]) -> (B256, Vec<Vec<B256>>) {
+    assert!(!digests.is_empty(), "Empty tree");
+
+    let mut level: Vec<B256> = digests.iter().copied().map(leaf).collect();
+    let mut proofs = vec![Vec::new(); digests.len()];
+    // The position of each leaf's ancestor in the current level
+    let mut positions: Vec<usize> = (0..digests.len()).collect();
+
+    while level.len() > 1 {
+        for (proof, position) in proofs.iter_mut().zip(positions.iter_mut()) {
+            if let Some(sibling) = level.get(*position ^ 1) {
+                proof.push(*sibling);
+            }
+            *position /= 2;
+        }
+
+        level = level
+            .chunks(2)
+            .map(|pair| if let [a, b] = pair { hash_pair(*a, *b) } else { pair[0] })
+            .collect();
+    }
+
+    (level[0], proofs)
+}
+
+/// Returns the message signed by a validator for the record with the given digest and
+/// inclusion proof, see the [module docs](self).
+pub fn signed_message(digest: B256, proof: &[B256]) -> Vec<u8> {
+    if proof.is_empty() {
+        return digest.to_vec()
+    }
+
+    let root = process_proof(leaf(digest), proof);
+    [BATCH_PREFIX, root.as_slice()].concat()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_inclusion_proofs() {
+        for len in 1..=9 {
+            let digests = (0..len).map(|i| B256::repeat_byte(i as u8)).collect::<Vec<_>>();
+            let (root, proofs) = build_tree(&digests);
+
+            for (digest, proof) in digests.iter().zip(&proofs) {
+                assert_eq!(process_proof(leaf(*digest), proof), root);
+            }
+
+            // A proof is only valid for its own leaf
+            if len > 1 {
+                assert_ne!(process_proof(leaf(digests[0]), &proofs[1]), root);
+            }
+        }
+    }
+
+    #[test]
+    fn test_signed_message() {
+        let digest = B256::repeat_byte(1);
+        assert_eq!(signed_message(digest, &[]), digest.to_vec());
+
+        let (root, proofs) = build_tree(&[digest, B256::repeat_byte(2)]);
+        let message = signed_message(digest, &proofs[0]);
+        assert_eq!(message.len(), BATCH_PREFIX.len() + 32);
+        assert_eq!(message[BATCH_PREFIX.len()..], root[..]);
+    }
+}
@@ -1,34 +1,144 @@
-use alloy::primitives::{Bytes, B256};
+use alloy::primitives::{Bytes, Keccak256, B256};
+use blst::min_pk::{
+    PublicKey as BlsPublicKey, SecretKey as BlsSecretKey, Signature as BlsSignature,
+};
 use serde::{Deserialize, Serialize};
 
-use crate::common::{Message, Namespace, Timestamp};
+use crate::common::{Message, Namespace, Timestamp, ValidatorError};
 
+use self::{
+    bloom::BloomFilter,
+    bls::{sign_with_dst, BLS_SUBSCRIBER_DST, BLS_SYNC_DST},
+};
+
+pub mod abi;
+pub mod bloom;
 pub mod bls;
+pub mod codec;
+pub mod merkle;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
+#[allow(clippy::large_enum_variant)]
 pub enum Request {
     /// Request to write a message to the log.
     /// Expects a [`crate::Record`] response
     Write { namespace: Namespace, message: Message },
 
+    /// Request to write a batch of messages to the log, in order.
+    /// Expects a list of [`crate::Record`]s as response, one per message
+    WriteBatch { namespace: Namespace, messages: Vec<Message> },
+
+    /// Request to write a message to the log, timestamped now but only released to readers
+    /// and subscribers after the given time.
+    /// Expects a [`crate::Record`] response
+    WriteDelayed { namespace: Namespace, message: Message, release_after: Timestamp },
+
     /// Request to read a range of messages from the log.
     /// Expects a [`crate::Log`] response
     ReadRange { namespace: Namespace, start: Timestamp, end: Timestamp },
 
-    /// Request to read a single message from the log.
-    /// Expects a [`crate::Log`] response
-    ReadMessage { namespace: Namespace, msg_id: B256 },
+    /// Request to read a single message from the log, or to attest that it had not
+    /// been seen as of the given time (defaults to the time of the request).
+    /// Expects a [`crate::ReadMessageResponse`] response
+    ReadMessage { namespace: Namespace, msg_id: B256, as_of: Option<Timestamp> },
 
-    /// Request to subscribe to all messages in a namespace.
+    /// Request from another validator to sync the records within the given timestamps that
+    /// it is missing, given a filter of the messages it holds in each namespace. Signed by
+    /// the syncing validator at the given time, see [`Request::sync`].
+    /// Expects a [`crate::SyncResponse`] response
+    Sync {
+        syncer: BlsPublicKey,
+        start: Timestamp,
+        end: Timestamp,
+        filters: Vec<(Namespace, BloomFilter)>,
+        timestamp: Timestamp,
+        signature: BlsSignature,
+    },
+
+    /// Request for the retention policy of a namespace.
+    /// Expects a [`crate::RetentionPolicy`] response
+    Retention { namespace: Namespace },
+
+    /// Request to subscribe to all messages in a namespace, signed by the subscriber at the
+    /// given time, see [`Request::subscribe`].
     /// Expects a response containing the socket address of the
     /// publisher and an authorization token to use for the subscription.
-    Subscribe { namespace: Namespace },
+    Subscribe {
+        namespace: Namespace,
+        subscriber: BlsPublicKey,
+        timestamp: Timestamp,
+        signature: BlsSignature,
+    },
 }
 
+/// The response of a validator to a [`Request`]: either the expected response, or an error
+/// explaining why the request couldn't be served.
+pub type Response<T> = Result<T, ValidatorError>;
+
 impl Request {
+    /// Serializes the request with the binary wire codec, see [`codec`].
     pub fn serialize(&self) -> Bytes {
-        serde_json::to_vec(&self).unwrap().into()
+        codec::encode(self)
+    }
+
+    /// Creates a request to subscribe to the given namespace, signed now by the subscriber
+    /// with the given secret key.
+    pub fn subscribe(namespace: Namespace, secret_key: &BlsSecretKey) -> Self {
+        let subscriber = secret_key.sk_to_pk();
+        let timestamp = Timestamp::now();
+        let digest = Self::subscribe_digest(&namespace, &subscriber, timestamp);
+        let signature = sign_with_dst(secret_key, digest, BLS_SUBSCRIBER_DST);
+
+        Request::Subscribe { namespace, subscriber, timestamp, signature }
+    }
+
+    /// Returns the digest signed by a subscriber to subscribe to the given namespace at the
+    /// given time.
+    pub fn subscribe_digest(
+        namespace: &Namespace,
+        subscriber: &BlsPublicKey,
+        timestamp: Timestamp,
+    ) -> B256 {
+        let mut hasher = Keccak256::new();
+        hasher.update(namespace);
+        hasher.update(subscriber.compress());
+        hasher.update(u128::from(timestamp).to_le_bytes());
+        hasher.finalize()
+    }
+
+    /// Creates a request to sync the records within the given timestamps that are missing
+    /// from the given filters, signed now by the validator with the given secret key.
+    pub fn sync(
+        start: Timestamp,
+        end: Timestamp,
+        filters: Vec<(Namespace, BloomFilter)>,
+        secret_key: &BlsSecretKey,
+    ) -> Self {
+        let syncer = secret_key.sk_to_pk();
+        let timestamp = Timestamp::now();
+        let digest = Self::sync_digest(&syncer, start, end, &filters, timestamp);
+        let signature = sign_with_dst(secret_key, digest, BLS_SYNC_DST);
+
+        Request::Sync { syncer, start, end, filters, timestamp, signature }
+    }
+
+    /// Returns the digest signed by a validator to sync the records within the given
+    /// timestamps that are missing from the given filters, at the given time.
+    pub fn sync_digest(
+        syncer: &BlsPublicKey,
+        start: Timestamp,
+        end: Timestamp,
+        filters: &[(Namespace, BloomFilter)],
+        timestamp: Timestamp,
+    ) -> B256 {
+        let mut hasher = Keccak256::new();
+        hasher.update(syncer.compress());
+        hasher.update(u128::from(start).to_le_bytes());
+        hasher.update(u128::from(end).to_le_bytes());
+        hasher.update(codec::encode(filters));
+        hasher.update(u128::from(timestamp).to_le_bytes());
+        hasher.finalize()
     }
 }
 
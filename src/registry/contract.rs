@@ -1,14 +1,25 @@
+use std::time::Duration;
+
 use alloy::{
-    primitives::Address,
-    providers::{ProviderBuilder, RootProvider},
+    eips::BlockId,
+    network::EthereumWallet,
+    primitives::{Address, U256},
+    providers::{Provider, ProviderBuilder, RootProvider},
+    rpc::types::{Log, TransactionReceipt},
+    signers::local::PrivateKeySigner,
     sol,
     transports::http::Http,
 };
-use blst::min_pk::PublicKey as BlsPublicKey;
+use blst::min_pk::SecretKey as BlsSecretKey;
 use reqwest::Client;
+use tokio::{sync::mpsc, time::MissedTickBehavior};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::{info, warn};
 use url::Url;
 
-use super::ValidatorInfo;
+use crate::bls::{proof_of_possession, signature_to_eip2537};
+
+use super::{parse_validator_key, RegistryEvent, ValidatorInfo};
 
 use ValidatorRegistryContract::{Validator, ValidatorRegistryContractInstance};
 
@@ -30,46 +41,333 @@ impl SmartContractRegistry {
 
     /// Gets the validator count.
     pub async fn get_validator_count(&self) -> eyre::Result<u64> {
-        self.0.getValidatorCount().call().await.map_err(Into::into).map(|count| count._0.to())
+        self.get_validator_count_at(BlockId::latest()).await
     }
 
     /// Gets a validator by index.
     pub async fn get_validator_by_index(&self, index: u64) -> eyre::Result<ValidatorInfo> {
-        self.0
-            .getValidatorByIndex(index)
-            .call()
-            .await
-            .map_err(Into::into)
-            .map(|val| ValidatorInfo::try_from(val._0))
-            .and_then(|val| val)
+        let validator = self.0.getValidatorByIndex(index).call().await?;
+        ValidatorInfo::try_from(validator._0)
     }
 
     /// Gets all validators.
     pub async fn get_all_validators(&self) -> eyre::Result<Vec<ValidatorInfo>> {
-        let count = self.get_validator_count().await?;
+        self.get_all_validators_at(BlockId::latest()).await
+    }
+
+    /// Gets the current epoch.
+    pub async fn get_current_epoch(&self) -> eyre::Result<u64> {
+        self.0.currentEpoch().call().await.map_err(Into::into).map(|epoch| epoch._0.to())
+    }
+
+    /// Gets all validators in the set of the given epoch, i.e. as of the end of the block
+    /// in which the epoch started.
+    pub async fn get_validators_at_epoch(&self, epoch: u64) -> eyre::Result<Vec<ValidatorInfo>> {
+        let current = self.get_current_epoch().await?;
+        if epoch > current {
+            eyre::bail!("Epoch {epoch} hasn't started yet, the current epoch is {current}");
+        }
+
+        let start_block: u64 = self.0.epochStartBlock(U256::from(epoch)).call().await?._0.to();
+
+        self.get_all_validators_at(BlockId::number(start_block)).await
+    }
+
+    /// Gets the validator count as of the given block.
+    async fn get_validator_count_at(&self, block: BlockId) -> eyre::Result<u64> {
+        let count = self.0.getValidatorCount().block(block).call().await?;
+        Ok(count._0.to())
+    }
+
+    /// Gets all validators as of the given block.
+    async fn get_all_validators_at(&self, block: BlockId) -> eyre::Result<Vec<ValidatorInfo>> {
+        let count = self.get_validator_count_at(block).await?;
         let mut validators = Vec::new();
 
         for index in 0..count {
-            if let Ok(validator) = self.get_validator_by_index(index).await {
-                validators.push(validator);
+            // Removed validators leave a gap in the indexes
+            let Ok(validator) = self.0.getValidatorByIndex(index).block(block).call().await else {
+                continue;
+            };
+
+            match ValidatorInfo::try_from(validator._0) {
+                Ok(validator) => validators.push(validator),
+                // Anyone can register, so a single invalid key must not prevent loading the set
+                Err(err) => warn!(?err, index, "Rejecting validator"),
             }
         }
 
         Ok(validators)
     }
+
+    /// Watches the contract for changes to the validator set, by polling for the
+    /// `ValidatorRegistered`, `StakeDeposited` and `ValidatorRemoved` logs every
+    /// `poll_interval`, starting from the next block.
+    pub async fn watch_events(
+        &self,
+        poll_interval: Duration,
+    ) -> eyre::Result<ReceiverStream<RegistryEvent>> {
+        let registry = self.clone();
+        let mut from_block = self.0.provider().get_block_number().await? + 1;
+
+        let (events_tx, events_rx) = mpsc::channel(128);
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(poll_interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                ticker.tick().await;
+
+                let latest = match registry.0.provider().get_block_number().await {
+                    Ok(latest) => latest,
+                    Err(err) => {
+                        warn!(?err, "Failed to get the latest block number");
+                        continue;
+                    }
+                };
+
+                if latest < from_block {
+                    continue;
+                }
+
+                // On errors, the same range is queried again on the next tick
+                let events = match registry.events_in_range(from_block, latest).await {
+                    Ok(events) => events,
+                    Err(err) => {
+                        warn!(?err, from_block, latest, "Failed to query registry events");
+                        continue;
+                    }
+                };
+
+                for event in events {
+                    if events_tx.send(event).await.is_err() {
+                        // The watcher was dropped
+                        return;
+                    }
+                }
+
+                from_block = latest + 1;
+            }
+        });
+
+        Ok(ReceiverStream::new(events_rx))
+    }
+
+    /// Returns the changes to the validator set in the given block range (inclusive),
+    /// in the order in which they happened.
+    async fn events_in_range(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> eyre::Result<Vec<RegistryEvent>> {
+        let mut events = Vec::new();
+
+        let registered = self
+            .0
+            .ValidatorRegistered_filter()
+            .from_block(from_block)
+            .to_block(to_block)
+            .query()
+            .await?;
+
+        for (event, log) in registered {
+            let validator = Validator {
+                index: event.index,
+                blsPubKey: event.blsPubKey,
+                proofOfPossession: event.proofOfPossession,
+                stake: event.stake,
+                socket: event.socket,
+                exists: true,
+            };
+
+            match ValidatorInfo::try_from(validator) {
+                Ok(validator) => {
+                    events.push((log_position(&log), RegistryEvent::Updated(validator)))
+                }
+                Err(err) => warn!(?err, "Rejecting registered validator"),
+            }
+        }
+
+        let deposits = self
+            .0
+            .StakeDeposited_filter()
+            .from_block(from_block)
+            .to_block(to_block)
+            .query()
+            .await?;
+
+        for (event, log) in deposits {
+            // The event only contains the deposited amount, so read the new stake at that block
+            let Some(block) = log.block_number else { continue };
+            let validator = self.0.validators(event.validator).block(BlockId::number(block));
+
+            match ValidatorInfo::try_from(validator.call().await?) {
+                Ok(validator) => {
+                    events.push((log_position(&log), RegistryEvent::Updated(validator)))
+                }
+                // The validator was removed later in the same block
+                Err(err) => warn!(?err, validator = %event.validator, "Skipping stake deposit"),
+            }
+        }
+
+        let removed = self
+            .0
+            .ValidatorRemoved_filter()
+            .from_block(from_block)
+            .to_block(to_block)
+            .query()
+            .await?;
+
+        for (event, log) in removed {
+            // The validator is deleted by the removal, so read its index in the previous block
+            let Some(block) = log.block_number else { continue };
+            let validator = self
+                .0
+                .validators(event.validator)
+                .block(BlockId::number(block.saturating_sub(1)))
+                .call()
+                .await?;
+
+            if !validator.exists {
+                // The validator was registered in the same block, so its index is unknown
+                warn!(validator = %event.validator, "Skipping removal of unknown validator");
+                continue;
+            }
+
+            events.push((log_position(&log), RegistryEvent::Removed(validator.index.to())));
+        }
+
+        events.sort_by_key(|(position, _)| *position);
+
+        Ok(events.into_iter().map(|(_, event)| event).collect())
+    }
+}
+
+/// A handle to the registry contract that sends transactions signed with the ECDSA key of
+/// a validator operator, to manage the registration of its validator.
+#[derive(Debug, Clone)]
+pub struct RegistryOperator {
+    /// The URL of the execution client to send the transactions to.
+    execution_client_url: Url,
+    /// The address of the registry contract.
+    registry_address: Address,
+    /// The signer of the transactions, which owns the registration and the stake.
+    signer: PrivateKeySigner,
+}
+
+impl RegistryOperator {
+    /// Creates a new `RegistryOperator` that sends transactions signed by `signer` to the
+    /// registry contract at `registry_address`.
+    pub fn new<U: Into<Url>>(
+        execution_client_url: U,
+        registry_address: Address,
+        signer: PrivateKeySigner,
+    ) -> Self {
+        Self { execution_client_url: execution_client_url.into(), registry_address, signer }
+    }
+
+    /// Returns the address of the operator, i.e. of its signer.
+    pub fn address(&self) -> Address {
+        self.signer.address()
+    }
+
+    /// Registers a validator with the BLS public key of the given secret key and the given
+    /// socket, depositing `stake` (in wei) from the operator account. The registration includes
+    /// a proof of possession of the secret key, which the contract checks. Waits for the
+    /// transaction to be included.
+    pub async fn register(
+        &self,
+        bls_secret_key: &BlsSecretKey,
+        socket: String,
+        stake: U256,
+    ) -> eyre::Result<TransactionReceipt> {
+        let call = self.contract().registerValidator(
+            bls_secret_key.sk_to_pk().compress().to_vec().into(),
+            signature_to_eip2537(&proof_of_possession(bls_secret_key)).to_vec().into(),
+            socket,
+            stake,
+        );
+
+        confirm(call.value(stake).send().await?.get_receipt().await?)
+    }
+
+    /// Deposits `amount` (in wei) to the stake of the operator's validator. Waits for the
+    /// transaction to be included.
+    pub async fn deposit_stake(&self, amount: U256) -> eyre::Result<TransactionReceipt> {
+        let call = self.contract().depositStake();
+
+        confirm(call.value(amount).send().await?.get_receipt().await?)
+    }
+
+    /// Withdraws all of the stake of the operator's validator, which removes it from the
+    /// validator set. Waits for the transaction to be included.
+    pub async fn withdraw(&self) -> eyre::Result<TransactionReceipt> {
+        let call = self.contract().withdrawAllStake();
+
+        confirm(call.send().await?.get_receipt().await?)
+    }
+
+    /// Returns the registry contract instance, with a provider that fills in and signs the
+    /// transactions.
+    fn contract(
+        &self,
+    ) -> ValidatorRegistryContractInstance<Http<Client>, impl Provider<Http<Client>>> {
+        let provider = ProviderBuilder::new()
+            .with_recommended_fillers()
+            .wallet(EthereumWallet::from(self.signer.clone()))
+            .on_http(self.execution_client_url.clone());
+
+        ValidatorRegistryContract::new(self.registry_address, provider)
+    }
+}
+
+/// Returns the receipt of an included transaction, or an error if the transaction reverted.
+fn confirm(receipt: TransactionReceipt) -> eyre::Result<TransactionReceipt> {
+    if !receipt.status() {
+        eyre::bail!("Transaction {} reverted", receipt.transaction_hash);
+    }
+
+    info!(tx_hash = %receipt.transaction_hash, block = ?receipt.block_number, "Transaction included");
+    Ok(receipt)
+}
+
+/// Returns the position of the log in the chain, i.e. its block number and index in the block.
+fn log_position(log: &Log) -> (u64, u64) {
+    (log.block_number.unwrap_or_default(), log.log_index.unwrap_or_default())
+}
+
+impl TryFrom<ValidatorRegistryContract::validatorsReturn> for ValidatorInfo {
+    type Error = eyre::Report;
+
+    fn try_from(
+        validator: ValidatorRegistryContract::validatorsReturn,
+    ) -> Result<Self, Self::Error> {
+        ValidatorInfo::try_from(Validator {
+            index: validator.index,
+            blsPubKey: validator.blsPubKey,
+            proofOfPossession: validator.proofOfPossession,
+            stake: validator.stake,
+            socket: validator.socket,
+            exists: validator.exists,
+        })
+    }
 }
 
 impl TryFrom<Validator> for ValidatorInfo {
     type Error = eyre::Report;
 
+    /// Converts the validator, and checks the proof of possession of its BLS key.
     fn try_from(validator: Validator) -> Result<Self, Self::Error> {
-        let pubkey = BlsPublicKey::from_bytes(validator.blsPubKey.to_vec().as_slice())
-            .map_err(|e| eyre::eyre!("Failed to parse BLS public key: {:?}", e))?;
+        let index = validator.index.to();
+        let pubkey =
+            parse_validator_key(index, &validator.blsPubKey, &validator.proofOfPossession)?;
 
         Ok(Self {
-            index: validator.index.to(),
+            index,
             bls_pub_key: pubkey,
-            stake: validator.stake.to(),
+            // No validator can stake more than the supply of ether, which fits in a u128
+            stake: validator.stake.saturating_to(),
             socket: validator.socket,
             exists: validator.exists,
         })
@@ -82,6 +380,7 @@ sol! {
         struct Validator {
             uint256 index;
             bytes blsPubKey;
+            bytes proofOfPossession;
             uint256 stake;
             string socket;
             bool exists;
@@ -89,6 +388,16 @@ sol! {
 
         function getValidatorCount() external view returns (uint256);
         function getValidatorByIndex(uint64 _index) external view returns (Validator memory);
+        function validators(address) external view returns (uint256 index, bytes blsPubKey, bytes proofOfPossession, uint256 stake, string socket, bool exists);
+        function currentEpoch() external view returns (uint256);
+        function registerValidator(bytes memory _blsPubKey, bytes memory _proofOfPossession, string memory _socket, uint256 _stake) external payable;
+        function depositStake() external payable;
+        function withdrawAllStake() external;
+        function epochStartBlock(uint256) external view returns (uint256);
+
+        event ValidatorRegistered(address indexed validator, uint256 indexed index, bytes blsPubKey, bytes proofOfPossession, uint256 stake, string socket);
+        event StakeDeposited(address indexed validator, uint256 amount);
+        event ValidatorRemoved(address indexed validator);
     }
 
     library Errors {
@@ -100,9 +409,125 @@ sol! {
 mod tests {
     use std::str::FromStr;
 
+    use futures::StreamExt;
     use tracing::warn;
 
     use super::*;
+    use crate::bls::random_bls_secret;
+
+    /// The URL of the local Ethereum node the tests run against, e.g. `anvil`.
+    const NODE_URL: &str = "http://localhost:8545";
+
+    /// Returns the address of the registry contract deployed on the local node, given by
+    /// `DATO_TEST_REGISTRY_ADDRESS`. Returns `None` if the node is not running or if no
+    /// address is given, in which case the test is skipped.
+    async fn local_registry(test: &str) -> Option<Address> {
+        if reqwest::get(NODE_URL).await.is_err() {
+            warn!("Skipping {test}, as the Ethereum node is not running.");
+            return None
+        }
+
+        let Ok(address) = std::env::var("DATO_TEST_REGISTRY_ADDRESS") else {
+            warn!("Skipping {test}, as DATO_TEST_REGISTRY_ADDRESS is not set.");
+            return None
+        };
+
+        Some(address.parse().expect("Invalid DATO_TEST_REGISTRY_ADDRESS"))
+    }
+
+    /// Returns an operator for the prefunded `anvil` account with the given private key.
+    fn operator(registry_address: Address, private_key: &str) -> RegistryOperator {
+        let signer = private_key.parse().expect("Valid private key");
+        RegistryOperator::new(Url::parse(NODE_URL).expect("Valid URL"), registry_address, signer)
+    }
+
+    /// Returns the next registry event, failing if none is received within a few blocks.
+    async fn next_event(
+        events: &mut ReceiverStream<RegistryEvent>,
+    ) -> eyre::Result<Option<RegistryEvent>> {
+        Ok(tokio::time::timeout(Duration::from_secs(10), events.next()).await?)
+    }
+
+    #[tokio::test]
+    async fn test_watch_events() -> eyre::Result<()> {
+        let _ = tracing_subscriber::fmt::try_init();
+
+        let Some(registry_address) = local_registry("test_watch_events").await else {
+            return Ok(());
+        };
+
+        let registry = SmartContractRegistry::new(Url::parse(NODE_URL)?, registry_address);
+        let mut events = registry.watch_events(Duration::from_millis(100)).await?;
+
+        // The second prefunded account of anvil
+        let operator = operator(
+            registry_address,
+            "0x59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d",
+        );
+        let secret_key = random_bls_secret();
+        let stake = U256::from(10u128.pow(18));
+        operator.register(&secret_key, "localhost:12450".to_owned(), stake).await?;
+
+        let Some(RegistryEvent::Updated(registered)) = next_event(&mut events).await? else {
+            eyre::bail!("Expected the registration event");
+        };
+        assert_eq!(registered.bls_pub_key, secret_key.sk_to_pk());
+        assert_eq!(registered.stake, 10u128.pow(18));
+
+        operator.deposit_stake(stake).await?;
+        let Some(RegistryEvent::Updated(deposited)) = next_event(&mut events).await? else {
+            eyre::bail!("Expected the stake deposit event");
+        };
+        assert_eq!(deposited.index, registered.index);
+        assert_eq!(deposited.stake, 2 * 10u128.pow(18));
+
+        operator.withdraw().await?;
+        let event = next_event(&mut events).await?;
+        assert!(matches!(event, Some(RegistryEvent::Removed(index)) if index == registered.index));
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_operator_roundtrip() -> eyre::Result<()> {
+        let _ = tracing_subscriber::fmt::try_init();
+
+        let Some(registry_address) = local_registry("test_operator_roundtrip").await else {
+            return Ok(());
+        };
+
+        let registry = SmartContractRegistry::new(Url::parse(NODE_URL)?, registry_address);
+
+        // The third prefunded account of anvil, so that it doesn't race with the other tests
+        let operator = operator(
+            registry_address,
+            "0x5de4111afa1a4b94908f83103eb1f1706367c2e68ca870fc3fb9a804cdab365a",
+        );
+        let secret_key = random_bls_secret();
+        let stake = U256::from(10u128.pow(18));
+        operator.register(&secret_key, "localhost:12451".to_owned(), stake).await?;
+
+        let registered = registry
+            .get_all_validators()
+            .await?
+            .into_iter()
+            .find(|validator| validator.bls_pub_key == secret_key.sk_to_pk())
+            .ok_or_else(|| eyre::eyre!("Registered validator not found"))?;
+        assert_eq!(registered.stake, 10u128.pow(18));
+        assert_eq!(registered.socket, "localhost:12451");
+        assert_eq!(registry.get_validator_by_index(registered.index).await?, registered);
+
+        operator.deposit_stake(stake).await?;
+        let deposited = registry.get_validator_by_index(registered.index).await?;
+        assert_eq!(deposited.stake, 2 * 10u128.pow(18));
+
+        operator.withdraw().await?;
+        assert!(registry.get_validator_by_index(registered.index).await.is_err());
+        let validators = registry.get_all_validators().await?;
+        assert!(validators.iter().all(|validator| validator.index != registered.index));
+
+        Ok(())
+    }
 
     #[tokio::test]
     async fn test_get_all_validators() -> eyre::Result<()> {
@@ -1,48 +1,421 @@
 use std::{
-    fs::File,
-    io::{BufRead, BufReader},
-    path::PathBuf,
+    collections::BTreeMap,
+    ffi::OsString,
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::Duration,
 };
 
 use blst::min_pk::PublicKey as BlsPublicKey;
+use serde::{Deserialize, Serialize};
+use tokio::{sync::mpsc, time::MissedTickBehavior};
+use tokio_stream::wrappers::ReceiverStream;
+use tracing::warn;
 
-use super::ValidatorInfo;
+use super::{parse_validator_key, RegistryEvent, ValidatorInfo};
 
-/// A validator registry that reads from the filesystem and caches the results.
+/// The suffix appended to the path of the registry file to get the path of its epoch history,
+/// e.g. `registry.txt.epochs`.
+const HISTORY_SUFFIX: &str = ".epochs";
+
+/// A validator registry that reads from the filesystem. The file is read again every time
+/// the validators are queried, so that changes are picked up while running.
+///
+/// The validator sets of past epochs are kept in an append-only history file next to the
+/// registry file (see [`HISTORY_SUFFIX`]), one line per epoch. Whenever the validators in the
+/// registry file differ from the latest epoch of the history, a new epoch is appended, so
+/// epochs are numbered in order from 0. Every registry reading the same file, whichever process
+/// it runs in and even after a restart, agrees on the epochs and their validators.
 #[derive(Debug, Clone)]
 pub struct FilesystemRegistry {
     /// The path to the file containing the validator information.
     pub path: PathBuf,
     /// The list of validators loaded from the file.
     pub validators: Vec<ValidatorInfo>,
+    /// The validators of each epoch read from the history so far, indexed by epoch.
+    epochs: Arc<Mutex<Vec<Vec<ValidatorInfo>>>>,
+}
+
+/// A validator in an epoch of the history file. Its proof of possession was checked when it
+/// was read from the registry file.
+#[derive(Debug, Serialize, Deserialize)]
+struct HistoryEntry {
+    index: u64,
+    /// The compressed BLS public key, hex encoded.
+    pubkey: String,
+    stake: u128,
+    socket: String,
 }
 
 impl FilesystemRegistry {
     /// Create a new `ValidatorRegistry` that reads from the given path.
     ///
     /// The file should be a CSV with the following columns:
-    /// `index, private_key, pubkey, stake, socket`
+    /// `index, private_key, pubkey, socket, proof_of_possession`
+    ///
+    /// Returns an error if the proof of possession of any validator key is invalid.
     pub fn read_from_file(path: PathBuf) -> eyre::Result<Self> {
-        let file = BufReader::new(File::open(&path)?);
+        let validators = read_validators(&path)?;
+        let registry = Self { path, validators, epochs: Arc::new(Mutex::new(Vec::new())) };
+        registry.refresh_epoch()?;
+
+        Ok(registry)
+    }
+
+    /// Returns the path of the history file of the registry.
+    pub fn history_path(&self) -> PathBuf {
+        let mut path = OsString::from(&self.path);
+        path.push(HISTORY_SUFFIX);
+        path.into()
+    }
+
+    /// Reads the file again, and appends its validators to the history as a new epoch if they
+    /// changed since the latest one. Returns the current epoch.
+    pub fn refresh_epoch(&self) -> eyre::Result<u64> {
+        let validators = read_validators(&self.path)?;
+        let history_path = self.history_path();
+
+        let mut epochs = self.epochs.lock().expect("Epochs lock poisoned");
+        let mut history = read_history(&history_path)?;
+        if history.last() != Some(&validators) {
+            append_history(&history_path, &validators)?;
+
+            // Another registry may have appended an epoch at the same time
+            history = read_history(&history_path)?;
+        }
+        *epochs = history;
+
+        let epoch = epochs
+            .iter()
+            .rposition(|epoch| *epoch == validators)
+            .ok_or_else(|| eyre::eyre!("Validators missing from the history"))?;
+
+        Ok(epoch as u64)
+    }
+
+    /// Returns the validators of the given epoch, if it is in the history.
+    pub fn validators_at_epoch(&self, epoch: u64) -> eyre::Result<Vec<ValidatorInfo>> {
+        if let Some(validators) =
+            self.epochs.lock().expect("Epochs lock poisoned").get(epoch as usize)
+        {
+            return Ok(validators.clone())
+        }
+
+        // The epoch may have been appended by another registry reading the same file
+        self.refresh_epoch()?;
+        match self.epochs.lock().expect("Epochs lock poisoned").get(epoch as usize) {
+            Some(validators) => Ok(validators.clone()),
+            None => eyre::bail!("Unknown epoch {epoch}"),
+        }
+    }
+
+    /// Watches the file for changes to the validator set, by reading it again every
+    /// `poll_interval` and sending the differences with the previous contents as events.
+    pub fn watch_file(
+        &self,
+        poll_interval: Duration,
+    ) -> eyre::Result<ReceiverStream<RegistryEvent>> {
+        let path = self.path.clone();
+        let mut known = by_index(read_validators(&path)?);
+
+        let (events_tx, events_rx) = mpsc::channel(128);
+
+        tokio::spawn(async move {
+            let mut ticker = tokio::time::interval(poll_interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+
+            loop {
+                ticker.tick().await;
 
-        let mut validators = Vec::new();
-        for line in file.lines() {
-            let line = line?;
-            let parts: Vec<&str> = line.split(',').collect();
+                // The file may be in the middle of being rewritten, so just retry on errors
+                let current = match read_validators(&path) {
+                    Ok(validators) => by_index(validators),
+                    Err(err) => {
+                        warn!(?err, ?path, "Failed to read registry file");
+                        continue;
+                    }
+                };
 
-            let index = parts[0].parse()?;
-            let pubkey_str: String = parts[2].parse()?;
-            let bls_pub_key = BlsPublicKey::from_bytes(&alloy::hex::decode(pubkey_str)?)
-                .map_err(|e| eyre::eyre!("Invalid BLS public key: {:?}", e))?;
+                let removed = known
+                    .keys()
+                    .filter(|index| !current.contains_key(index))
+                    .map(|index| RegistryEvent::Removed(*index));
+                let updated = current
+                    .iter()
+                    .filter(|(index, validator)| known.get(index) != Some(validator))
+                    .map(|(_, validator)| RegistryEvent::Updated(validator.clone()));
 
-            // for now, we don't care about the stake
-            let stake = 0;
-            let socket = parts[3].parse()?;
+                for event in removed.chain(updated).collect::<Vec<_>>() {
+                    if events_tx.send(event).await.is_err() {
+                        // The watcher was dropped
+                        return;
+                    }
+                }
 
-            let val = ValidatorInfo { index, bls_pub_key, stake, socket, exists: true };
-            validators.push(val);
+                known = current;
+            }
+        });
+
+        Ok(ReceiverStream::new(events_rx))
+    }
+}
+
+/// Reads the validators from the given registry file.
+fn read_validators(path: &Path) -> eyre::Result<Vec<ValidatorInfo>> {
+    let file = BufReader::new(File::open(path)?);
+
+    let mut validators = Vec::new();
+    for line in file.lines() {
+        let line = line?;
+        let parts: Vec<&str> = line.split(',').collect();
+
+        if parts.len() < 5 {
+            eyre::bail!("Invalid registry line, expected 5 columns: {line}");
+        }
+
+        let index = parts[0].parse()?;
+        let pubkey = alloy::hex::decode(parts[2])?;
+        let proof = alloy::hex::decode(parts[4])?;
+        let bls_pub_key = parse_validator_key(index, &pubkey, &proof)?;
+
+        // the file doesn't contain stake, so all validators are weighed equally
+        let stake = 1;
+        let socket = parts[3].parse()?;
+
+        let val = ValidatorInfo { index, bls_pub_key, stake, socket, exists: true };
+        validators.push(val);
+    }
+
+    Ok(validators)
+}
+
+/// Reads the validators of each epoch from the given history file, if it exists. Lines that
+/// can't be read, e.g. partially written during a crash, are skipped, and don't count as
+/// epochs for any reader of the file.
+fn read_history(path: &Path) -> eyre::Result<Vec<Vec<ValidatorInfo>>> {
+    let mut file = match File::open(path) {
+        Ok(file) => BufReader::new(file),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(err.into()),
+    };
+
+    let mut epochs = Vec::new();
+    let mut line = Vec::new();
+    loop {
+        line.clear();
+
+        // The last line may still be being written
+        if file.read_until(b'\n', &mut line)? == 0 || line.last() != Some(&b'\n') {
+            break
+        }
+
+        match parse_history_line(&line) {
+            Ok(validators) => epochs.push(validators),
+            Err(err) => warn!(?err, ?path, "Skipping invalid line of the epoch history"),
         }
+    }
+
+    Ok(epochs)
+}
+
+/// Parses the validators of an epoch from a line of the history file.
+fn parse_history_line(line: &[u8]) -> eyre::Result<Vec<ValidatorInfo>> {
+    let entries: Vec<HistoryEntry> = serde_json::from_slice(line)?;
+
+    entries
+        .into_iter()
+        .map(|entry| {
+            let pubkey = BlsPublicKey::from_bytes(&alloy::hex::decode(&entry.pubkey)?)
+                .map_err(|err| eyre::eyre!("Invalid key of validator {}: {err:?}", entry.index))?;
+
+            Ok(ValidatorInfo {
+                index: entry.index,
+                bls_pub_key: pubkey,
+                stake: entry.stake,
+                socket: entry.socket,
+                exists: true,
+            })
+        })
+        .collect()
+}
+
+/// Appends the given validators to the history file as a new epoch.
+fn append_history(path: &Path, validators: &[ValidatorInfo]) -> eyre::Result<()> {
+    let entries = validators
+        .iter()
+        .map(|validator| HistoryEntry {
+            index: validator.index,
+            pubkey: alloy::hex::encode_prefixed(validator.bls_pub_key.compress()),
+            stake: validator.stake,
+            socket: validator.socket.clone(),
+        })
+        .collect::<Vec<_>>();
+
+    let mut file = OpenOptions::new().create(true).read(true).append(true).open(path)?;
+
+    // A line partially written during a crash is ended, so that it is skipped on its own
+    let mut line = Vec::new();
+    let len = file.metadata()?.len();
+    if len > 0 {
+        let mut last = [0];
+        file.seek(SeekFrom::Start(len - 1))?;
+        file.read_exact(&mut last)?;
+        if last[0] != b'\n' {
+            line.push(b'\n');
+        }
+    }
+
+    serde_json::to_writer(&mut line, &entries)?;
+    line.push(b'\n');
+
+    // A single write, so that concurrent appends don't interleave
+    file.write_all(&line)?;
+    file.sync_data()?;
+
+    Ok(())
+}
+
+/// Indexes the given validators by their index.
+fn by_index(validators: Vec<ValidatorInfo>) -> BTreeMap<u64, ValidatorInfo> {
+    validators.into_iter().map(|validator| (validator.index, validator)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use futures::StreamExt;
+
+    use super::*;
+    use crate::{
+        bls::{proof_of_possession, random_bls_secret, signature_to_eip2537},
+        Registry,
+    };
+
+    fn registry_line(index: u64, socket: &str) -> String {
+        let secret = random_bls_secret();
+        let pubkey = alloy::hex::encode(secret.sk_to_pk().compress());
+        let proof = alloy::hex::encode(proof_of_possession(&secret).compress());
+        format!("{index},0x00,0x{pubkey},{socket},0x{proof}\n")
+    }
+
+    /// Replaces the file contents atomically, so that the watcher never reads a partial file.
+    fn write_atomic(path: &PathBuf, contents: String) -> std::io::Result<()> {
+        let tmp = path.with_extension("tmp");
+        std::fs::write(&tmp, contents)?;
+        std::fs::rename(tmp, path)
+    }
+
+    #[tokio::test]
+    async fn test_watch_file() -> eyre::Result<()> {
+        let path = std::env::temp_dir().join(format!("dato-watch-{}.txt", std::process::id()));
+        let validator0 = registry_line(0, "127.0.0.1:8000");
+        std::fs::write(&path, validator0.clone() + &registry_line(1, "127.0.0.1:8001"))?;
+
+        let registry = FilesystemRegistry::read_from_file(path.clone())?;
+        let mut events = registry.watch_file(Duration::from_millis(10))?;
+
+        // Validator 1 leaves and validator 2 joins
+        write_atomic(&path, validator0 + &registry_line(2, "127.0.0.1:8002"))?;
+
+        let event = events.next().await.unwrap();
+        assert!(matches!(event, RegistryEvent::Removed(1)));
+        let event = events.next().await.unwrap();
+        assert!(matches!(event, RegistryEvent::Updated(ValidatorInfo { index: 2, .. })));
+
+        std::fs::remove_file(registry.history_path())?;
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_epochs() -> eyre::Result<()> {
+        let path = std::env::temp_dir().join(format!("dato-epochs-{}.txt", std::process::id()));
+        let validator0 = registry_line(0, "127.0.0.1:8000");
+        std::fs::write(&path, validator0.clone())?;
+
+        // A history left over by a previous run would number the epochs differently
+        let _ = std::fs::remove_file(format!("{}{HISTORY_SUFFIX}", path.display()));
+
+        let registry = FilesystemRegistry::read_from_file(path.clone())?;
+        let initial = registry.validators.clone();
+        let first = registry.epoch().await?;
+        assert_eq!(first, 0);
+
+        // Reading unchanged contents doesn't start a new epoch
+        assert_eq!(registry.epoch().await?, first);
+
+        write_atomic(&path, registry_line(1, "127.0.0.1:8001"))?;
+        let second = registry.epoch().await?;
+        assert_eq!(second, first + 1);
+
+        // Another registry reading the same file, e.g. in another process or after a restart,
+        // agrees on the epochs and their validators
+        let other = FilesystemRegistry::read_from_file(path.clone())?;
+        assert_eq!(other.epoch().await?, second);
+        assert_eq!(other.validators_at(second).await?, registry.validators_at(second).await?);
+        assert_eq!(other.validators_at(first).await?, initial);
+
+        // Past epochs keep their validators
+        assert_eq!(registry.validators_at(first).await?, initial);
+        let set = registry.validator_set(second).await?;
+        assert_eq!(set.epoch(), second);
+        assert_eq!(set.iter().map(|v| v.index).collect::<Vec<_>>(), vec![1]);
+        assert!(registry.validators_at(second + 1).await.is_err());
+
+        // Going back to a previous set still starts a new epoch
+        write_atomic(&path, validator0)?;
+        assert_eq!(other.epoch().await?, second + 1);
+        assert_eq!(registry.validators_at(second + 1).await?, initial);
+
+        std::fs::remove_file(registry.history_path())?;
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_eip2537_proof_of_possession() -> eyre::Result<()> {
+        let path = std::env::temp_dir().join(format!("dato-eip2537-{}.txt", std::process::id()));
+
+        // Proofs are also accepted in the uncompressed format checked by the registry contract
+        let secret = random_bls_secret();
+        let pubkey = alloy::hex::encode(secret.sk_to_pk().compress());
+        let proof = alloy::hex::encode(signature_to_eip2537(&proof_of_possession(&secret)));
+        std::fs::write(&path, format!("0,0x00,0x{pubkey},127.0.0.1:8000,0x{proof}\n"))?;
+
+        let registry = FilesystemRegistry::read_from_file(path.clone())?;
+        assert_eq!(registry.validators[0].bls_pub_key, secret.sk_to_pk());
+        std::fs::remove_file(registry.history_path())?;
+
+        // A proof with a non-zero padding is not a valid encoding
+        let mut proof = signature_to_eip2537(&proof_of_possession(&secret));
+        proof[0] = 1;
+        let proof = alloy::hex::encode(proof);
+        std::fs::write(&path, format!("0,0x00,0x{pubkey},127.0.0.1:8000,0x{proof}\n"))?;
+        assert!(FilesystemRegistry::read_from_file(path.clone()).is_err());
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_reject_invalid_proof_of_possession() -> eyre::Result<()> {
+        let path = std::env::temp_dir().join(format!("dato-pop-{}.txt", std::process::id()));
+
+        // The proof of possession of another key doesn't prove possession of this one
+        let secret = random_bls_secret();
+        let pubkey = alloy::hex::encode(secret.sk_to_pk().compress());
+        let proof = alloy::hex::encode(proof_of_possession(&random_bls_secret()).compress());
+        std::fs::write(&path, format!("0,0x00,0x{pubkey},127.0.0.1:8000,0x{proof}\n"))?;
+
+        let err = FilesystemRegistry::read_from_file(path.clone()).unwrap_err();
+        assert!(err.to_string().contains("Invalid proof of possession of validator 0"));
+
+        std::fs::remove_file(path)?;
 
-        Ok(Self { path, validators })
+        Ok(())
     }
 }
@@ -1,16 +1,25 @@
+use std::time::Duration;
+
 use async_trait::async_trait;
-use blst::min_pk::PublicKey as BlsPublicKey;
+use blst::min_pk::{PublicKey as BlsPublicKey, Signature as BlsSignature};
+use tokio_stream::wrappers::ReceiverStream;
 
-use crate::ValidatorIdentity;
+use crate::{
+    bls::{signature_from_eip2537, verify_proof_of_possession},
+    ValidatorIdentity, ValidatorSet,
+};
 
 mod contract;
-pub use contract::SmartContractRegistry;
+pub use contract::{RegistryOperator, SmartContractRegistry};
 
 mod filesystem;
 pub use filesystem::FilesystemRegistry;
 
 /// An interface for querying the set of validators in the DATO network.
 /// This is used by clients to discover the set of sockets to connect to.
+///
+/// Each version of the validator set is numbered by an epoch. The set of a past epoch never
+/// changes, so that certificates can be verified against the set they were produced under.
 #[async_trait]
 pub trait Registry {
     /// Returns the number of validators in the network.
@@ -18,20 +27,101 @@ pub trait Registry {
 
     /// Returns a list of all validators in the network.
     async fn all_validators(&self) -> eyre::Result<Vec<ValidatorInfo>>;
+
+    /// Returns the current epoch, i.e. the number of the latest version of the validator set.
+    async fn epoch(&self) -> eyre::Result<u64>;
+
+    /// Returns the list of validators in the set of the given epoch.
+    async fn validators_at(&self, epoch: u64) -> eyre::Result<Vec<ValidatorInfo>>;
+
+    /// Returns the validator set of the given epoch, e.g. to verify the certificates
+    /// produced under it.
+    async fn validator_set(&self, epoch: u64) -> eyre::Result<ValidatorSet> {
+        let validators = self.validators_at(epoch).await?;
+        let identities = validators.iter().filter(|v| v.exists).map(ValidatorInfo::identity);
+
+        Ok(ValidatorSet::new(identities).with_epoch(epoch))
+    }
+
+    /// Returns a stream of the changes to the validator set from now on. The registry is
+    /// checked for changes every `poll_interval`.
+    async fn watch(&self, poll_interval: Duration) -> eyre::Result<ReceiverStream<RegistryEvent>>;
 }
 
+/// A change to the validator set, as reported by [`Registry::watch`].
 #[derive(Debug, Clone)]
+pub enum RegistryEvent {
+    /// A validator joined the set, or its information (e.g. its stake) changed.
+    Updated(ValidatorInfo),
+    /// The validator with the given index left the set.
+    Removed(u64),
+}
+
+/// The information about a validator in the registry.
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ValidatorInfo {
+    /// The validator index.
     pub index: u64,
+    /// The BLS public key of the validator.
     pub bls_pub_key: BlsPublicKey,
-    pub stake: u64,
+    /// The stake deposited by the validator, in wei.
+    pub stake: u128,
+    /// The socket address at which the validator is listening.
     pub socket: String,
+    /// Whether the validator is registered.
     pub exists: bool,
 }
 
 impl ValidatorInfo {
+    /// Returns the identity of the validator, as used to verify its signatures.
     pub fn identity(&self) -> ValidatorIdentity {
-        ValidatorIdentity { index: self.index as usize, pubkey: self.bls_pub_key }
+        ValidatorIdentity::new(self.index as usize, self.bls_pub_key).with_stake(self.stake)
+    }
+}
+
+/// Parses the BLS public key of the validator with the given index, and checks the proof of
+/// possession of its secret key. Keys without a valid proof are rejected, as they could be
+/// used in a rogue-key attack on aggregate signatures.
+///
+/// The proof is either a compressed signature, or an uncompressed one in the EIP-2537 format
+/// checked by the registry contract.
+fn parse_validator_key(index: u64, pubkey: &[u8], proof: &[u8]) -> eyre::Result<BlsPublicKey> {
+    let pubkey = BlsPublicKey::from_bytes(pubkey)
+        .map_err(|e| eyre::eyre!("Invalid BLS public key of validator {index}: {e:?}"))?;
+    let proof = match proof.len() {
+        256 => signature_from_eip2537(proof)
+            .ok_or_else(|| eyre::eyre!("Invalid proof of possession of validator {index}"))?,
+        _ => BlsSignature::from_bytes(proof)
+            .map_err(|e| eyre::eyre!("Invalid proof of possession of validator {index}: {e:?}"))?,
+    };
+
+    if !verify_proof_of_possession(&pubkey, &proof) {
+        eyre::bail!("Invalid proof of possession of validator {index}");
+    }
+
+    Ok(pubkey)
+}
+
+#[async_trait]
+impl<R: Registry + Send + Sync + ?Sized> Registry for Box<R> {
+    async fn validator_count(&self) -> eyre::Result<u64> {
+        (**self).validator_count().await
+    }
+
+    async fn all_validators(&self) -> eyre::Result<Vec<ValidatorInfo>> {
+        (**self).all_validators().await
+    }
+
+    async fn epoch(&self) -> eyre::Result<u64> {
+        (**self).epoch().await
+    }
+
+    async fn validators_at(&self, epoch: u64) -> eyre::Result<Vec<ValidatorInfo>> {
+        (**self).validators_at(epoch).await
+    }
+
+    async fn watch(&self, poll_interval: Duration) -> eyre::Result<ReceiverStream<RegistryEvent>> {
+        (**self).watch(poll_interval).await
     }
 }
 
@@ -44,15 +134,41 @@ impl Registry for contract::SmartContractRegistry {
     async fn all_validators(&self) -> eyre::Result<Vec<ValidatorInfo>> {
         self.get_all_validators().await
     }
+
+    async fn epoch(&self) -> eyre::Result<u64> {
+        self.get_current_epoch().await
+    }
+
+    async fn validators_at(&self, epoch: u64) -> eyre::Result<Vec<ValidatorInfo>> {
+        self.get_validators_at_epoch(epoch).await
+    }
+
+    async fn watch(&self, poll_interval: Duration) -> eyre::Result<ReceiverStream<RegistryEvent>> {
+        self.watch_events(poll_interval).await
+    }
 }
 
 #[async_trait]
 impl Registry for filesystem::FilesystemRegistry {
     async fn validator_count(&self) -> eyre::Result<u64> {
-        Ok(self.validators.len() as u64)
+        Ok(self.all_validators().await?.len() as u64)
     }
 
     async fn all_validators(&self) -> eyre::Result<Vec<ValidatorInfo>> {
-        Ok(self.validators.clone())
+        // Re-read the file, so that changes are picked up without a restart
+        let epoch = self.refresh_epoch()?;
+        self.validators_at_epoch(epoch)
+    }
+
+    async fn epoch(&self) -> eyre::Result<u64> {
+        self.refresh_epoch()
+    }
+
+    async fn validators_at(&self, epoch: u64) -> eyre::Result<Vec<ValidatorInfo>> {
+        self.validators_at_epoch(epoch)
+    }
+
+    async fn watch(&self, poll_interval: Duration) -> eyre::Result<ReceiverStream<RegistryEvent>> {
+        self.watch_file(poll_interval)
     }
 }
@@ -0,0 +1,23 @@
+//! Helpers shared by the unit tests.
+
+use alloy::primitives::Bytes;
+
+use crate::{
+    bls::{random_bls_secret, sign_with_prefix},
+    Message, Namespace, Record, Timestamp,
+};
+
+/// Returns a record of the given message in the given namespace, timestamped and signed by a
+/// random validator key.
+pub(crate) fn signed_record(
+    namespace: &Namespace,
+    message: impl Into<Bytes>,
+    timestamp: impl Into<Timestamp>,
+) -> Record {
+    let message = Message(message.into());
+    let timestamp = timestamp.into();
+    let digest = message.record_digest(namespace, timestamp);
+    let signature = sign_with_prefix(&random_bls_secret(), digest);
+
+    Record { timestamp, message, signature, proof: Vec::new(), origin: None }
+}
@@ -0,0 +1,154 @@
+use crate::common::{Message, Namespace, ValidatorError};
+
+/// Limits on the messages and namespaces a validator accepts writes for, checked before
+/// signing. No limit is set by default.
+#[derive(Debug, Clone, Default)]
+pub struct AdmissionPolicy {
+    /// The maximum size of a message, in bytes.
+    pub max_message_bytes: Option<usize>,
+    /// The maximum length of a namespace, in bytes.
+    pub max_namespace_len: Option<usize>,
+    /// The patterns of the accepted namespaces, where `*` matches any sequence of bytes.
+    /// Any namespace is accepted if empty.
+    pub namespace_patterns: Vec<String>,
+    /// The maximum number of namespaces the validator holds records for or publishes to
+    /// subscribers of.
+    pub max_namespaces: Option<usize>,
+}
+
+impl AdmissionPolicy {
+    /// Sets the maximum size of a message, in bytes.
+    pub fn with_max_message_bytes(mut self, max_message_bytes: usize) -> Self {
+        self.max_message_bytes = Some(max_message_bytes);
+        self
+    }
+
+    /// Sets the maximum length of a namespace, in bytes.
+    pub fn with_max_namespace_len(mut self, max_namespace_len: usize) -> Self {
+        self.max_namespace_len = Some(max_namespace_len);
+        self
+    }
+
+    /// Adds a pattern of accepted namespaces, e.g. `commitments/*`. Once a pattern is added,
+    /// only the namespaces matching one of the patterns are accepted.
+    pub fn with_namespace_pattern(mut self, pattern: impl Into<String>) -> Self {
+        self.namespace_patterns.push(pattern.into());
+        self
+    }
+
+    /// Sets the maximum number of namespaces the validator holds records for or publishes to
+    /// subscribers of.
+    pub fn with_max_namespaces(mut self, max_namespaces: usize) -> Self {
+        self.max_namespaces = Some(max_namespaces);
+        self
+    }
+
+    /// Checks the length of the namespace and that it matches one of the patterns, if any.
+    /// The number of namespaces is checked by the validator, which knows them.
+    pub(crate) fn check_namespace(&self, namespace: &Namespace) -> Result<(), ValidatorError> {
+        if let Some(max) = self.max_namespace_len {
+            if namespace.len() > max {
+                return Err(ValidatorError::NamespaceTooLong {
+                    len: namespace.len() as u64,
+                    max: max as u64,
+                })
+            }
+        }
+
+        if !self.namespace_patterns.is_empty() &&
+            !self.namespace_patterns.iter().any(|pattern| matches(pattern.as_bytes(), namespace))
+        {
+            return Err(ValidatorError::NamespaceRejected)
+        }
+
+        Ok(())
+    }
+
+    /// Checks the size of the message.
+    pub(crate) fn check_message(&self, message: &Message) -> Result<(), ValidatorError> {
+        match self.max_message_bytes {
+            Some(max) if message.0.len() > max => Err(ValidatorError::MessageTooLarge {
+                size: message.0.len() as u64,
+                max: max as u64,
+            }),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Returns true if the namespace matches the pattern, where `*` matches any sequence of
+/// bytes. Runs in `O(pattern.len() * namespace.len())`, by backtracking to the last `*` only.
+pub(crate) fn matches(pattern: &[u8], namespace: &[u8]) -> bool {
+    let (mut p, mut n) = (0, 0);
+    // The position after the last `*` in the pattern, and the position in the namespace
+    // from which it matches
+    let mut star = None;
+
+    while n < namespace.len() {
+        if pattern.get(p) == Some(&b'*') {
+            star = Some((p + 1, n));
+            p += 1;
+        } else if pattern.get(p) == Some(&namespace[n]) {
+            p += 1;
+            n += 1;
+        } else if let Some((after_star, from)) = star {
+            // Let the last `*` match one more byte
+            star = Some((after_star, from + 1));
+            p = after_star;
+            n = from + 1;
+        } else {
+            return false
+        }
+    }
+
+    pattern[p..].iter().all(|&byte| byte == b'*')
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::Bytes;
+
+    use super::*;
+
+    #[test]
+    fn test_namespace_patterns() {
+        assert!(matches(b"commitments/*", b"commitments/"));
+        assert!(matches(b"commitments/*", b"commitments/block-1"));
+        assert!(!matches(b"commitments/*", b"telemetry/block-1"));
+        assert!(matches(b"*/block-*", b"commitments/block-1"));
+        assert!(!matches(b"*/block-*", b"commitments/slot-1"));
+        assert!(matches(b"a*b*c", b"aXbYbZc"));
+        assert!(!matches(b"a*b*c", b"aXbYbZ"));
+        assert!(matches(b"exact", b"exact"));
+        assert!(!matches(b"exact", b"exactly"));
+        assert!(matches(b"*", b""));
+
+        let policy = AdmissionPolicy::default()
+            .with_max_namespace_len(16)
+            .with_namespace_pattern("telemetry/*")
+            .with_namespace_pattern("commitments");
+        assert!(policy.check_namespace(&Bytes::from_static(b"telemetry/cpu")).is_ok());
+        assert!(policy.check_namespace(&Bytes::from_static(b"commitments")).is_ok());
+        assert_eq!(
+            policy.check_namespace(&Bytes::from_static(b"spam")),
+            Err(ValidatorError::NamespaceRejected)
+        );
+        assert_eq!(
+            policy.check_namespace(&Bytes::from_static(b"telemetry/cpu/core-1")),
+            Err(ValidatorError::NamespaceTooLong { len: 20, max: 16 })
+        );
+    }
+
+    #[test]
+    fn test_message_size() {
+        let policy = AdmissionPolicy::default().with_max_message_bytes(4);
+        assert!(policy.check_message(&Message(Bytes::from_static(b"1234"))).is_ok());
+        assert_eq!(
+            policy.check_message(&Message(Bytes::from_static(b"12345"))),
+            Err(ValidatorError::MessageTooLarge { size: 5, max: 4 })
+        );
+        assert!(AdmissionPolicy::default()
+            .check_message(&Message(vec![0; 1 << 20].into()))
+            .is_ok());
+    }
+}
@@ -2,32 +2,103 @@ use std::{
     future::Future,
     net::SocketAddr,
     pin::Pin,
+    slice,
+    sync::Arc,
     task::{Context, Poll},
+    time::{Duration, Instant},
 };
 
 use alloy::primitives::B256;
-use blst::min_pk::SecretKey as BlsSecretKey;
+use blst::min_pk::{
+    PublicKey as BlsPublicKey, SecretKey as BlsSecretKey, Signature as BlsSignature,
+};
 use bytes::Bytes;
-use futures::StreamExt;
-use hashbrown::HashSet;
-use msg::{tcp::Tcp, PubError, PubSocket, RepSocket};
-use tokio::sync::mpsc;
-use tracing::{debug, error, info};
+use futures::{future::BoxFuture, stream::FuturesUnordered, FutureExt, StreamExt};
+use hashbrown::{HashMap, HashSet};
+use msg::{tcp::Tcp, PubError, RepSocket, Request as RepRequest};
+use tokio::{
+    task::JoinHandle,
+    time::{sleep, Sleep},
+};
+use tracing::{debug, error, info, warn};
+
+mod admission;
+pub use admission::AdmissionPolicy;
 
 mod store;
-pub use store::{DataStore, InMemoryStore};
+pub use store::{DataStore, FilesystemStore, InMemoryStore};
 
 mod spec;
 pub use spec::ValidatorSpec;
 
+mod subscriptions;
+use subscriptions::Publishers;
+
+mod sync;
+use sync::{SyncedPage, SyncedRecords, Syncer};
+
+mod timelock;
+use timelock::{TimelockLimit, TimelockedRecords};
+
 use crate::{
     common::{
-        Log, Message, Namespace, ReadMessageResponse, Record, SubscribeResponse, Timestamp,
-        UnavailableMessage,
+        Log, Message, Namespace, ReadMessageResponse, Record, RetentionPolicy, SubscribeResponse,
+        SubscriptionToken, SyncResponse, Timestamp, UnavailableMessage, ValidatorError,
     },
-    primitives::{bls::sign_with_prefix, Request},
+    primitives::{
+        bloom::BloomFilter,
+        bls::{sign_with_prefix, verify_signature_with_dst, BLS_SUBSCRIBER_DST, BLS_SYNC_DST},
+        codec::{self, CodecError, WIRE_VERSION},
+        merkle, Request, Response,
+    },
+    Registry,
 };
 
+/// How long a subscription token can be used to connect to the publisher socket.
+/// Subscribers that need to reconnect after this must request a new token.
+const SUBSCRIPTION_TOKEN_TTL: Duration = Duration::from_secs(60 * 60);
+
+/// How far the time at which a request was signed can be from the time of the validator.
+/// Bounds how long a signed request can be replayed, while allowing for clock drift.
+const MAX_SIGNED_REQUEST_SKEW: Duration = Duration::from_secs(30);
+
+/// The maximum number of writes signed together at the end of a signing window. The batch is
+/// signed early if it fills up, to bound the response latency and the size of the proofs.
+const MAX_SIGNING_BATCH: usize = 4096;
+
+/// The maximum number of messages in a write batch. The batch is signed at once, over the root
+/// of its Merkle tree, so its size is bounded like the one of the writes of a signing window.
+pub const MAX_WRITE_BATCH: usize = 4096;
+
+/// The maximum delay before the release of a timelocked write. Timelocked records are kept
+/// in memory until their release, so the delay is bounded.
+const MAX_RELEASE_DELAY: Duration = Duration::from_secs(60 * 60);
+
+/// The maximum timelocked records withheld at once for a single namespace, so that a single
+/// writer can't take up all the space.
+const MAX_TIMELOCKED_PER_NAMESPACE: TimelockLimit =
+    TimelockLimit { records: 4096, bytes: 16 * 1024 * 1024 };
+
+/// The maximum timelocked records withheld at once across all namespaces, which bounds the
+/// memory they take up.
+const MAX_TIMELOCKED: TimelockLimit = TimelockLimit { records: 65536, bytes: 256 * 1024 * 1024 };
+
+/// How far back validators look for records to sync from each other, see
+/// [`Validator::with_sync`].
+const SYNC_HORIZON: Duration = Duration::from_secs(60 * 60);
+
+/// The maximum number of records in a page of a sync round, give or take records sharing a
+/// timestamp. A validator syncing more records pages through them over several rounds.
+const MAX_SYNC_RECORDS: usize = 4096;
+
+/// The minimum time between two sync requests from the same validator, including the rounds
+/// of a backfill. Faster requests are rejected as rate limited.
+const MIN_SYNC_REQUEST_INTERVAL: Duration = Duration::from_millis(100);
+
+/// A sync request whose signature is valid, along with whether the key that signed it belongs
+/// to another validator of the current epoch, once checked against the registry.
+type CheckedSyncRequest = (SyncRequest, eyre::Result<bool>);
+
 /// A validator instance that writes log records to a data store and
 /// communicates with clients over a TCP socket.
 ///
@@ -44,10 +115,78 @@ pub struct Validator<DS: DataStore> {
     secret_key: BlsSecretKey,
     /// Local address of the validator TCP socket
     local_addr: Option<SocketAddr>,
-    /// Set of namespaces that have active subscriptions from clients
-    active_subscriptions: HashSet<Namespace>,
-    /// Publisher socket for sending messages to all subscribers
-    pub_socket: PubSocket<Tcp>,
+    /// Publisher sockets for sending messages to the subscribers of each namespace
+    publishers: Publishers,
+    /// How long single writes are accumulated to be signed together, if batching is enabled
+    signing_window: Option<Duration>,
+    /// Writes waiting to be signed at the end of the current signing window
+    pending_writes: Vec<PendingWrite>,
+    /// Timer for the end of the current signing window, set while writes are pending
+    signing_timer: Option<Pin<Box<Sleep>>>,
+    /// Records withheld from readers and subscribers until their release time
+    timelocked: TimelockedRecords,
+    /// Timer for the next release of timelocked records, set while records are withheld
+    release_timer: Option<Pin<Box<Sleep>>>,
+    /// The syncer fetching missed records from the other validators, if syncing is enabled
+    syncer: Option<Syncer>,
+    /// How often to sync with the other validators
+    sync_interval: Duration,
+    /// Timer for the next sync round, set if syncing is enabled
+    sync_timer: Option<Pin<Box<Sleep>>>,
+    /// The sync round in progress, if any
+    sync_task: Option<JoinHandle<eyre::Result<SyncedPage>>>,
+    /// The range being synced, one page per round, if a sync pass is in progress
+    sync_pass: Option<SyncPass>,
+    /// The sync requests of other validators being checked against the registry
+    sync_requests: FuturesUnordered<BoxFuture<'static, CheckedSyncRequest>>,
+    /// The time of the last sync request served to each validator, by compressed public key
+    last_sync_requests: HashMap<[u8; 48], Instant>,
+    /// The time at which the validator joined, if it backfills the records written before
+    joined_at: Option<Timestamp>,
+    /// Whether the validator is still backfilling the records written before it joined
+    backfilling: bool,
+    /// The limits on the messages and namespaces accepted for writing
+    admission: AdmissionPolicy,
+    /// The namespaces written to or subscribed to so far, counted against the maximum number of
+    /// namespaces
+    namespaces: HashSet<Namespace>,
+}
+
+/// A write request waiting to be signed at the end of the signing window.
+struct PendingWrite {
+    /// The request to respond to with the signed record
+    req: RepRequest,
+    /// The namespace to write the message to
+    namespace: Namespace,
+    /// The message to write
+    message: Message,
+    /// The time at which the request was received, used as the record timestamp
+    timestamp: Timestamp,
+}
+
+/// A range of timestamps being synced from the other validators, one page per round.
+#[derive(Debug, Clone, Copy)]
+struct SyncPass {
+    /// The start of the next round
+    cursor: Timestamp,
+    /// The end of the page of the round in progress, inclusive
+    page_end: Timestamp,
+    /// The end of the range, inclusive
+    end: Timestamp,
+}
+
+/// A sync request from another validator, waiting to be checked against the registry.
+struct SyncRequest {
+    /// The request to respond to with the missing records
+    req: RepRequest,
+    /// The public key the request was signed with
+    syncer: BlsPublicKey,
+    /// The start of the time range to sync
+    start: Timestamp,
+    /// The end of the time range to sync
+    end: Timestamp,
+    /// The filters of the messages the syncing validator holds in each namespace
+    filters: Vec<(Namespace, BloomFilter)>,
 }
 
 impl Validator<InMemoryStore> {
@@ -58,35 +197,191 @@ impl Validator<InMemoryStore> {
 }
 
 impl<DS: DataStore + 'static> ValidatorSpec for Validator<DS> {
-    fn write(&mut self, namespace: Namespace, message: Message) -> Record {
+    fn write(&mut self, namespace: Namespace, message: Message) -> Result<Record, ValidatorError> {
+        self.admit(&namespace, slice::from_ref(&message))?;
+
+        let entries = [(namespace.clone(), message, Timestamp::now())];
+        let record = self.sign_records(&entries).pop().expect("One record per entry");
+
+        self.store_record(namespace, record.clone())?;
+
+        Ok(record)
+    }
+
+    fn write_batch(
+        &mut self,
+        namespace: Namespace,
+        messages: Vec<Message>,
+    ) -> Result<Vec<Record>, ValidatorError> {
+        if messages.is_empty() {
+            return Ok(Vec::new())
+        }
+
+        if messages.len() > MAX_WRITE_BATCH {
+            return Err(ValidatorError::BatchTooLarge {
+                len: messages.len() as u64,
+                max: MAX_WRITE_BATCH as u64,
+            })
+        }
+
+        self.admit(&namespace, &messages)?;
+
+        // The whole batch is signed at once, over the root of its Merkle tree
         let timestamp = Timestamp::now();
+        let entries = messages
+            .into_iter()
+            .map(|message| (namespace.clone(), message, timestamp))
+            .collect::<Vec<_>>();
+        let records = self.sign_records(&entries);
+
+        // None of the records are certified if the batch fails, so none of them are kept
+        self.store_batch(namespace, records.clone())?;
+
+        Ok(records)
+    }
+
+    fn write_delayed(
+        &mut self,
+        namespace: Namespace,
+        message: Message,
+        release_after: Timestamp,
+    ) -> Result<Record, ValidatorError> {
+        if release_after.time_until() > MAX_RELEASE_DELAY {
+            return Err(ValidatorError::MalformedRequest(format!(
+                "Release time is more than {MAX_RELEASE_DELAY:?} in the future"
+            )))
+        }
 
-        let record_digest = message.record_digest(&namespace, timestamp);
+        self.admit(&namespace, slice::from_ref(&message))?;
 
-        let signature = sign_with_prefix(&self.secret_key, record_digest);
-        let record = Record { message, timestamp, signature };
-        self.store.write_one(namespace, record.clone());
+        let entries = [(namespace.clone(), message, Timestamp::now())];
+        let record = self.sign_records(&entries).pop().expect("One record per entry");
 
-        record
+        // The record is stored along with its release time, so that it is still withheld after
+        // a restart, and stored again when it is released, see `release_timelocked`
+        self.timelocked.check(&namespace, &record)?;
+        self.store.write_timelocked(namespace.clone(), record.clone(), release_after).map_err(
+            |err| {
+                error!(?err, "Failed to write timelocked record to the store");
+                ValidatorError::Storage(err.to_string())
+            },
+        )?;
+        self.timelocked.insert(namespace.clone(), record.clone(), release_after)?;
+        self.count_namespace(&namespace);
+        self.schedule_release();
+
+        Ok(record)
     }
 
     fn read_range(&self, namespace: Namespace, start: Timestamp, end: Timestamp) -> Log {
         self.store.read_range(namespace, start, end)
     }
 
-    fn read_message(&self, namespace: Namespace, msg_id: B256) -> ReadMessageResponse {
-        let record = self.store.read_message(namespace, msg_id);
+    fn read_message(
+        &self,
+        namespace: Namespace,
+        msg_id: B256,
+        as_of: Option<Timestamp>,
+    ) -> Result<ReadMessageResponse, ValidatorError> {
+        // A message waiting to be signed has been seen and timestamped already, so we can't
+        // attest to its unavailability. Reads flush the pending writes first, see `poll`.
+        if self.is_pending(&namespace, msg_id) {
+            return Err(ValidatorError::Internal("Message is waiting to be signed".to_owned()))
+        }
+
+        // A timelocked message has been seen, so we can't attest to its unavailability either
+        if let Some(release_after) = self.timelocked.release_time(&namespace, msg_id) {
+            return Err(ValidatorError::Timelocked { release_after })
+        }
 
-        if let Some(record) = record {
-            ReadMessageResponse::Available(record)
-        } else {
-            let unavailable = UnavailableMessage::create_signed(msg_id, &self.secret_key);
-            ReadMessageResponse::Unavailable(unavailable)
+        // We can't attest to anything in the future, and clients expect the attestation to be
+        // as of the query time they asked for, so a future query time is rejected
+        let now = Timestamp::now();
+        if as_of.is_some_and(|as_of| as_of > now) {
+            return Err(ValidatorError::FutureQueryTime { now })
         }
+        let as_of = as_of.unwrap_or(now);
+
+        Ok(match self.store.read_message(namespace.clone(), msg_id) {
+            // A record seen after the query time was still unavailable as of that time.
+            Some(record) if record.timestamp <= as_of => ReadMessageResponse::Available(record),
+            record => {
+                // Without its record, the message may have been seen and pruned since: we never
+                // attest to the unavailability of pruned messages
+                if record.is_none() && self.store.may_have_pruned(&namespace, msg_id) {
+                    if let Some(low_water_mark) = self.store.low_water_mark(&namespace) {
+                        return Ok(ReadMessageResponse::OutsideRetention { low_water_mark })
+                    }
+                }
+
+                if let Some(joined_at) = self.joined_at {
+                    if as_of < joined_at {
+                        return Err(ValidatorError::BeforeJoin { joined_at })
+                    }
+
+                    if self.backfilling {
+                        return Err(ValidatorError::Backfilling)
+                    }
+                }
+
+                let unavailable =
+                    UnavailableMessage::create_signed(&namespace, msg_id, as_of, &self.secret_key);
+                ReadMessageResponse::Unavailable(unavailable)
+            }
+        })
     }
 
-    fn subscribe(&mut self, namespace: Namespace) {
-        self.active_subscriptions.insert(namespace);
+    fn sync(
+        &self,
+        start: Timestamp,
+        end: Timestamp,
+        filters: Vec<(Namespace, BloomFilter)>,
+    ) -> SyncResponse {
+        let filters = filters.into_iter().collect::<HashMap<_, _>>();
+        let (page, page_end) = self.read_sync_page(start, end);
+
+        let records = page
+            .into_iter()
+            .filter(|(namespace, record)| {
+                !filters
+                    .get(namespace)
+                    .is_some_and(|filter| filter.contains(record.message_digest(namespace)))
+            })
+            .collect();
+        let next = (page_end < end).then(|| Timestamp::from(u128::from(page_end) + 1));
+
+        SyncResponse { records, next }
+    }
+
+    fn retention(&self, namespace: Namespace) -> RetentionPolicy {
+        self.store.retention(&namespace)
+    }
+
+    fn subscribe(
+        &mut self,
+        namespace: Namespace,
+        subscriber: BlsPublicKey,
+        timestamp: Timestamp,
+        signature: BlsSignature,
+    ) -> Result<SubscriptionToken, ValidatorError> {
+        let now = Timestamp::now();
+        if u128::from(now).abs_diff(u128::from(timestamp)) > MAX_SIGNED_REQUEST_SKEW.as_millis() {
+            return Err(ValidatorError::Unauthorized)
+        }
+
+        let digest = Request::subscribe_digest(&namespace, &subscriber, timestamp);
+        if !verify_signature_with_dst(&signature, &subscriber, digest, BLS_SUBSCRIBER_DST) ||
+            !self.publishers.is_allowed(&namespace, &subscriber)
+        {
+            return Err(ValidatorError::Unauthorized)
+        }
+
+        // Each namespace subscribed to gets its own publisher socket
+        self.admit(&namespace, &[])?;
+        self.count_namespace(&namespace);
+
+        let expiry = Timestamp::from(u128::from(now) + SUBSCRIPTION_TOKEN_TTL.as_millis());
+        Ok(SubscriptionToken::create_signed(namespace, subscriber, expiry, &self.secret_key))
     }
 }
 
@@ -94,28 +389,473 @@ impl<DS: DataStore + 'static> Validator<DS> {
     /// Creates a new validator instance with the given data store backend,
     /// BLS secret key, and TCP port for the validator to listen on for new requests.
     ///
-    /// This method also tries to bind both the request and publisher sockets.
+    /// This method also tries to bind the request socket. The publisher socket of each
+    /// namespace is bound on its first subscription.
     pub async fn new(store: DS, secret_key: BlsSecretKey, port: u16) -> Result<Self, PubError> {
         let mut conn = RepSocket::new(Tcp::default());
         conn.bind(("0.0.0.0", port)).await?;
 
-        let mut pub_socket = PubSocket::new(Tcp::default());
-        pub_socket.bind(("0.0.0.0", 0)).await?;
+        let publishers = Publishers::new(secret_key.sk_to_pk());
+        let namespaces = store.namespaces().into_iter().collect();
+
+        // Keep withholding the records timelocked before a restart, whose release may be due
+        let mut timelocked = TimelockedRecords::new(MAX_TIMELOCKED_PER_NAMESPACE, MAX_TIMELOCKED);
+        for (namespace, record, release_after) in store.timelocked() {
+            timelocked.restore(namespace, record, release_after);
+        }
+        let release_timer = timelocked
+            .next_release()
+            .map(|release_after| Box::pin(sleep(release_after.time_until())));
 
         Ok(Self {
             store,
             secret_key,
             local_addr: conn.local_addr(),
-            active_subscriptions: HashSet::new(),
-            pub_socket,
+            publishers,
             conn,
+            signing_window: None,
+            pending_writes: Vec::new(),
+            signing_timer: None,
+            timelocked,
+            release_timer,
+            syncer: None,
+            sync_interval: Duration::ZERO,
+            sync_timer: None,
+            sync_task: None,
+            sync_pass: None,
+            sync_requests: FuturesUnordered::new(),
+            last_sync_requests: HashMap::new(),
+            joined_at: None,
+            backfilling: false,
+            admission: AdmissionPolicy::default(),
+            namespaces,
         })
     }
 
+    /// Accumulate single writes over the given window, and sign each batch of writes with a
+    /// single signature over the root of their Merkle tree, instead of one signature per
+    /// write. This trades write latency for throughput. A zero window disables batching.
+    pub fn with_signing_window(mut self, window: Duration) -> Self {
+        self.signing_window = Some(window).filter(|window| !window.is_zero());
+        self
+    }
+
+    /// Periodically sync with the other validators of the current epoch in the given registry,
+    /// to fetch the records written in the last hour that this validator missed. Synced
+    /// records keep the timestamp and signature of the validator that signed them, and are
+    /// served as such, but are not published to subscribers. The first round starts right away.
+    ///
+    /// The registry is also used to authenticate the sync requests of the other validators,
+    /// which are only served to the validators of the current epoch, and at most once every
+    /// 100ms to each. Validators without a registry reject all sync requests.
+    pub fn with_sync<R>(mut self, registry: R, interval: Duration) -> Self
+    where
+        R: Registry + Send + Sync + 'static,
+    {
+        self.syncer = Some(Syncer::new(Arc::new(registry), self.secret_key.sk_to_pk()));
+        self.sync_interval = interval.max(MIN_SYNC_REQUEST_INTERVAL);
+        self.sync_timer = Some(Box::pin(sleep(Duration::ZERO)));
+        self
+    }
+
+    /// Join the validator set now, and backfill the records written before from the other
+    /// validators when syncing, see [`Validator::with_sync`]. Sync rounds then page through
+    /// the whole history before the join, back to back, until the other validators have no
+    /// more records.
+    ///
+    /// The validator can't tell which messages were written before it joined, so it refuses
+    /// to attest to the unavailability of messages as of a time before it joined, and at all
+    /// until the backfill completes. Only records signed by validators of the current epoch
+    /// are backfilled.
+    pub fn with_backfill(mut self) -> Self {
+        self.joined_at = Some(Timestamp::now());
+        self.backfilling = true;
+        self
+    }
+
+    /// Reject the writes of messages and to namespaces beyond the limits of the given policy,
+    /// before signing them. Subscriptions are checked and counted against the maximum number
+    /// of namespaces like writes, and synced records beyond the limits are dropped.
+    pub fn with_admission(mut self, policy: AdmissionPolicy) -> Self {
+        self.admission = policy;
+        self
+    }
+
+    /// Only allow the given subscribers, identified by the keys they sign their subscribe
+    /// requests with, to subscribe to the namespaces matching the given pattern, where `*`
+    /// matches any sequence of bytes. Other namespaces can be subscribed to by anyone.
+    pub fn with_private_namespace(
+        mut self,
+        pattern: impl Into<String>,
+        subscribers: Vec<BlsPublicKey>,
+    ) -> Self {
+        self.publishers.add_private(pattern.into(), subscribers);
+        self
+    }
+
     /// Address of the TCP socket at which the validator is listening for incoming requests.
     pub fn local_addr(&self) -> Option<SocketAddr> {
         self.local_addr
     }
+
+    /// Signs the records of the given namespaces, messages and timestamps. A single record is
+    /// signed on its own, and several records are signed together over the root of their
+    /// Merkle tree, with an inclusion proof in each record. See [`merkle`].
+    fn sign_records(&self, entries: &[(Namespace, Message, Timestamp)]) -> Vec<Record> {
+        let digests = entries
+            .iter()
+            .map(|(namespace, message, timestamp)| message.record_digest(namespace, *timestamp))
+            .collect::<Vec<_>>();
+
+        let (_, proofs) = merkle::build_tree(&digests);
+        let signed_message = merkle::signed_message(digests[0], &proofs[0]);
+        let signature = sign_with_prefix(&self.secret_key, signed_message);
+
+        entries
+            .iter()
+            .zip(proofs)
+            .map(|((_, message, timestamp), proof)| Record {
+                timestamp: *timestamp,
+                message: message.clone(),
+                signature,
+                proof,
+                origin: None,
+            })
+            .collect()
+    }
+
+    /// Checks that the messages can be written, or subscribed to if none, to the namespace under
+    /// the admission policy. The namespace is only counted against the maximum number of
+    /// namespaces once a record is stored in it, see [`Self::count_namespace`].
+    fn admit(&self, namespace: &Namespace, messages: &[Message]) -> Result<(), ValidatorError> {
+        self.admission.check_namespace(namespace)?;
+        for message in messages {
+            self.admission.check_message(message)?;
+        }
+
+        if !self.namespaces.contains(namespace) {
+            if let Some(max) = self.admission.max_namespaces {
+                if self.namespaces.len() >= max {
+                    return Err(ValidatorError::TooManyNamespaces { max: max as u64 })
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Counts the namespace against the maximum number of namespaces, if it is new.
+    fn count_namespace(&mut self, namespace: &Namespace) {
+        if !self.namespaces.contains(namespace) {
+            self.namespaces.insert(namespace.clone());
+        }
+    }
+
+    /// Writes a signed record to the store, and counts its namespace once it is stored.
+    fn store_record(&mut self, namespace: Namespace, record: Record) -> Result<(), ValidatorError> {
+        self.store.write_one(namespace.clone(), record).map_err(|err| {
+            error!(?err, "Failed to write record to the store");
+            ValidatorError::Storage(err.to_string())
+        })?;
+        self.count_namespace(&namespace);
+
+        Ok(())
+    }
+
+    /// Writes a batch of signed records of the namespace to the store, either all of them or
+    /// none of them, and counts the namespace once they are stored.
+    fn store_batch(
+        &mut self,
+        namespace: Namespace,
+        records: Vec<Record>,
+    ) -> Result<(), ValidatorError> {
+        self.store.write_batch(namespace.clone(), records).map_err(|err| {
+            error!(?err, "Failed to write batch of records to the store");
+            ValidatorError::Storage(err.to_string())
+        })?;
+        self.count_namespace(&namespace);
+
+        Ok(())
+    }
+
+    /// Returns true if a write of the message with the given ID is waiting to be signed at the
+    /// end of the signing window.
+    fn is_pending(&self, namespace: &Namespace, msg_id: B256) -> bool {
+        self.pending_writes
+            .iter()
+            .any(|write| write.namespace == *namespace && write.message.digest(namespace) == msg_id)
+    }
+
+    /// Signs the writes accumulated during the current signing window as a single batch, and
+    /// responds to each of them with its record.
+    fn flush_pending_writes(&mut self) {
+        self.signing_timer = None;
+        let pending_writes = std::mem::take(&mut self.pending_writes);
+        if pending_writes.is_empty() {
+            return
+        }
+
+        debug!(len = pending_writes.len(), "Signing batch of pending writes");
+        let entries = pending_writes
+            .iter()
+            .map(|write| (write.namespace.clone(), write.message.clone(), write.timestamp))
+            .collect::<Vec<_>>();
+        let records = self.sign_records(&entries);
+
+        for (write, record) in pending_writes.into_iter().zip(records) {
+            // The namespaces of the writes are only counted once stored, so writes to new
+            // namespaces pending in the same window are checked against the limit again
+            let response = self
+                .admit(&write.namespace, &[])
+                .and_then(|()| self.store_record(write.namespace.clone(), record.clone()));
+            let response: Response<Record> = response.map(|_| record);
+
+            if let Err(err) = write.req.respond(Bytes::from(codec::encode(&response))) {
+                error!(?err, "Failed to respond to write request");
+            }
+
+            if let Ok(record) = response {
+                self.publish(write.namespace, &record);
+            }
+        }
+    }
+
+    /// Sets the release timer to the earliest release time of the timelocked records, if any.
+    fn schedule_release(&mut self) {
+        self.release_timer = self
+            .timelocked
+            .next_release()
+            .map(|release_after| Box::pin(sleep(release_after.time_until())));
+    }
+
+    /// Stores the timelocked records whose release time has passed, and publishes them to
+    /// the active subscribers.
+    fn release_timelocked(&mut self) {
+        for (namespace, record) in self.timelocked.release(Timestamp::now()) {
+            debug!(?namespace, "Releasing timelocked record");
+
+            // The writer already holds the record, so there is no one to report the error to
+            if self.store_record(namespace.clone(), record.clone()).is_ok() {
+                self.publish(namespace, &record);
+            }
+        }
+
+        self.schedule_release();
+    }
+
+    /// Reads the oldest records within the given timestamps across all namespaces, about
+    /// [`MAX_SYNC_RECORDS`] of them, without reading more than that from each namespace.
+    /// Returns them along with the end of the page, inclusive: every record up to it is read.
+    fn read_sync_page(&self, start: Timestamp, end: Timestamp) -> (SyncedRecords, Timestamp) {
+        // The page always covers its start, so that paging through the range progresses
+        let before = |timestamp: Timestamp| {
+            Timestamp::from(u128::from(timestamp).saturating_sub(1)).max(start)
+        };
+
+        let mut page = Vec::new();
+        let mut page_end = end;
+        for namespace in self.store.namespaces() {
+            let log = self.store.read_page(namespace.clone(), start, page_end, MAX_SYNC_RECORDS);
+
+            // The namespace may have more records sharing the timestamp of its last one
+            if log.len() == MAX_SYNC_RECORDS {
+                let last = log.records.last().expect("Records read").timestamp;
+                page_end = page_end.min(before(last));
+            }
+
+            page.extend(log.records.into_iter().map(|record| (namespace.clone(), record)));
+            if page.len() > MAX_SYNC_RECORDS {
+                page.sort_by_key(|(_, record)| record.timestamp);
+                page_end = page_end.min(before(page[MAX_SYNC_RECORDS].1.timestamp));
+            }
+
+            page.retain(|(_, record)| record.timestamp <= page_end);
+        }
+
+        page.sort_by_key(|(_, record)| record.timestamp);
+        (page, page_end)
+    }
+
+    /// Starts a sync round with the other validators, unless the previous one is still
+    /// running, and schedules the next one.
+    fn start_sync_round(&mut self) {
+        self.sync_timer = Some(Box::pin(sleep(self.sync_interval)));
+
+        let Some(syncer) = self.syncer.clone() else { return };
+        if self.sync_task.is_some() {
+            debug!("Previous sync round still running, skipping");
+            return
+        }
+
+        // A pass continues from where the previous round left off
+        let (start, end) = match self.sync_pass {
+            Some(SyncPass { cursor, end, .. }) => (cursor, end),
+            None => match self.joined_at {
+                Some(joined_at) if self.backfilling => (Timestamp::default(), joined_at),
+                _ => {
+                    let now = Timestamp::now();
+                    let start = u128::from(now).saturating_sub(SYNC_HORIZON.as_millis());
+                    (Timestamp::from(start), now)
+                }
+            },
+        };
+
+        // Summarize the records held in each namespace within a page of the range, with a fresh
+        // seed so that the false positives of the filters differ from round to round
+        let (page, page_end) = self.read_sync_page(start, end);
+        let mut digests = HashMap::<Namespace, Vec<B256>>::new();
+        for (namespace, record) in page {
+            let digest = record.message_digest(&namespace);
+            digests.entry(namespace).or_default().push(digest);
+        }
+
+        let seed = rand::random();
+        let filters = digests
+            .into_iter()
+            .map(|(namespace, digests)| {
+                let mut filter = BloomFilter::with_capacity(digests.len(), seed);
+                for digest in digests {
+                    filter.insert(digest);
+                }
+
+                (namespace, filter)
+            })
+            .collect();
+
+        self.sync_pass = Some(SyncPass { cursor: start, page_end, end });
+
+        let request = Request::sync(start, page_end, filters, &self.secret_key);
+        self.sync_task = Some(tokio::spawn(syncer.run(request.serialize())));
+    }
+
+    /// Stores the records fetched in a sync round that are still missing.
+    fn store_synced(&mut self, records: SyncedRecords) {
+        let mut stored = 0;
+
+        let now = Timestamp::now();
+        for (namespace, record) in records {
+            // Records that would be pruned right away aren't stored, so that they don't count as
+            // new in every round. Namespaces at their limit only take the records signed here.
+            let cutoff = self.store.retention(&namespace).cutoff(now);
+            if cutoff.is_some_and(|cutoff| record.timestamp < cutoff) ||
+                self.store.is_full(&namespace)
+            {
+                continue;
+            }
+
+            // The same record may be fetched from several validators
+            let msg_id = record.message_digest(&namespace);
+            if self.store.read_message(namespace.clone(), msg_id).is_some() ||
+                self.timelocked.release_time(&namespace, msg_id).is_some()
+            {
+                continue;
+            }
+
+            // The validator that signed the record may have looser limits
+            if let Err(err) = self.admit(&namespace, slice::from_ref(&record.message)) {
+                debug!(?namespace, %err, "Skipping synced record");
+                continue;
+            }
+
+            if self.store_record(namespace, record).is_ok() {
+                stored += 1;
+            }
+        }
+
+        if stored > 0 {
+            info!(stored, "Synced missed records from other validators");
+        }
+    }
+
+    /// Continues the sync pass with the next page, as soon as the other validators accept the
+    /// next round, given the earliest start of their next page. Ends the pass, and with it the
+    /// backfill, once they have no more records within its range.
+    fn continue_sync(&mut self, next: Option<Timestamp>) {
+        let Some(pass) = self.sync_pass.as_mut() else { return };
+
+        let next = next.or_else(|| {
+            (pass.page_end < pass.end).then(|| Timestamp::from(u128::from(pass.page_end) + 1))
+        });
+        if let Some(next) = next {
+            pass.cursor = next;
+            self.sync_timer = Some(Box::pin(sleep(MIN_SYNC_REQUEST_INTERVAL)));
+            return
+        }
+
+        self.sync_pass = None;
+        if self.backfilling {
+            info!(joined_at = ?self.joined_at, "Backfilled records written before joining");
+            self.backfilling = false;
+        }
+    }
+
+    /// Checks that the sync request was signed recently with the given key, before checking
+    /// that the key belongs to another validator of the current epoch in the registry. Without
+    /// a registry, no validator can sync from this one.
+    fn check_sync(&self, request: SyncRequest, timestamp: Timestamp, signature: BlsSignature) {
+        let SyncRequest { syncer, start, end, ref filters, .. } = request;
+
+        let now = u128::from(Timestamp::now());
+        let digest = Request::sync_digest(&syncer, start, end, filters, timestamp);
+        let authorized = now.abs_diff(u128::from(timestamp)) <= MAX_SIGNED_REQUEST_SKEW.as_millis() &&
+            verify_signature_with_dst(&signature, &syncer, digest, BLS_SYNC_DST);
+
+        match self.syncer.clone() {
+            Some(checker) if authorized => {
+                self.sync_requests.push(Box::pin(async move {
+                    let is_peer = checker.check_peer(syncer).await;
+                    (request, is_peer)
+                }));
+            }
+            _ => respond_sync(request.req, Err(ValidatorError::Unauthorized)),
+        }
+    }
+
+    /// Responds to a sync request once checked against the registry, unless the syncing
+    /// validator is not in the current epoch or synced too recently.
+    fn serve_sync(&mut self, request: SyncRequest, is_peer: eyre::Result<bool>) {
+        let SyncRequest { req, syncer, start, end, filters } = request;
+
+        let response = match is_peer {
+            Ok(true) => {
+                // Only served requests count, so that a validator polling too fast still gets
+                // served every interval
+                let now = Instant::now();
+                let last = self.last_sync_requests.get(&syncer.compress());
+                if last.is_some_and(|last| now - *last < MIN_SYNC_REQUEST_INTERVAL) {
+                    Err(ValidatorError::RateLimited)
+                } else {
+                    self.last_sync_requests.insert(syncer.compress(), now);
+                    Ok(self.sync(start, end, filters))
+                }
+            }
+            Ok(false) => Err(ValidatorError::Unauthorized),
+            Err(err) => {
+                warn!(?err, "Failed to check sync request against the registry");
+                Err(ValidatorError::Internal(format!("Failed to read registry: {err}")))
+            }
+        };
+
+        respond_sync(req, response);
+    }
+
+    /// Publishes the record to the subscribers of its namespace, if any. Records are handed to
+    /// the publisher socket right away, so that a large batch can't overflow an intermediate
+    /// queue.
+    fn publish(&self, namespace: Namespace, record: &Record) {
+        if !self.publishers.contains(&namespace) {
+            return
+        }
+
+        self.publishers.publish(&namespace, Bytes::from(codec::encode(record)));
+    }
+}
+
+/// Sends the response to a sync request.
+fn respond_sync(req: RepRequest, response: Response<SyncResponse>) {
+    if let Err(err) = req.respond(Bytes::from(codec::encode(&response))) {
+        error!(?err, "Failed to respond to sync request");
+    }
 }
 
 impl<DS: DataStore + 'static> Future for Validator<DS> {
@@ -124,15 +864,29 @@ impl<DS: DataStore + 'static> Future for Validator<DS> {
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let this = self.get_mut();
 
-        let (publisher_queue_tx, mut publisher_queue_rx) = mpsc::channel(512);
-
         loop {
             // process incoming requests from clients
             if let Poll::Ready(Some(req)) = this.conn.poll_next_unpin(cx) {
-                let request = match serde_json::from_slice::<Request>(req.msg()) {
+                let request = match codec::decode::<Request>(req.msg()) {
                     Ok(request) => request,
                     Err(err) => {
                         error!(?err, "Failed to parse request");
+
+                        // Let the client know why the request failed, instead of timing out
+                        let response: Response<()> = Err(match err {
+                            CodecError::UnsupportedVersion(version) => {
+                                ValidatorError::UnsupportedVersion {
+                                    version,
+                                    supported: WIRE_VERSION,
+                                }
+                            }
+                            err => ValidatorError::MalformedRequest(err.to_string()),
+                        });
+
+                        if let Err(err) = req.respond(Bytes::from(codec::encode(&response))) {
+                            error!(?err, "Failed to respond to malformed request");
+                        }
+
                         continue;
                     }
                 };
@@ -140,65 +894,123 @@ impl<DS: DataStore + 'static> Future for Validator<DS> {
                 match request {
                     Request::Write { namespace, message } => {
                         debug!(?namespace, "Received write request");
-                        let record = this.write(namespace.clone(), message);
-                        let Ok(response) = serde_json::to_vec(&record).map(Bytes::from) else {
-                            error!("Failed to serialize record");
+
+                        // Defer the write to the end of the signing window, if enabled
+                        if let Some(window) = this.signing_window {
+                            // Rejected writes are answered right away, not signed
+                            if let Err(err) = this.admit(&namespace, slice::from_ref(&message)) {
+                                let response: Response<Record> = Err(err);
+                                if let Err(err) = req.respond(Bytes::from(codec::encode(&response)))
+                                {
+                                    error!(?err, "Failed to respond to write request");
+                                }
+
+                                continue;
+                            }
+
+                            let timestamp = Timestamp::now();
+                            this.pending_writes.push(PendingWrite {
+                                req,
+                                namespace,
+                                message,
+                                timestamp,
+                            });
+
+                            if this.pending_writes.len() >= MAX_SIGNING_BATCH {
+                                this.flush_pending_writes();
+                            } else if this.signing_timer.is_none() {
+                                this.signing_timer = Some(Box::pin(sleep(window)));
+                            }
+
                             continue;
-                        };
+                        }
+
+                        let record = this.write(namespace.clone(), message);
+                        let response = Bytes::from(codec::encode(&record));
 
-                        if let Err(err) = req.respond(response.clone()) {
+                        if let Err(err) = req.respond(response) {
                             error!(?err, "Failed to respond to write request");
                         }
 
-                        // Send a request to publish the record to the active subscribers
-                        if this.active_subscriptions.contains(&namespace) {
-                            info!(?namespace, "Sending record to publish queue");
-                            if let Err(err) = publisher_queue_tx.try_send((namespace, response)) {
-                                error!(?err, "Failed to add record to the publish queue");
-                            }
+                        // Publish the record to the active subscribers
+                        if let Ok(record) = record {
+                            this.publish(namespace, &record);
+                        }
+                    }
+                    Request::WriteBatch { namespace, messages } => {
+                        debug!(?namespace, len = messages.len(), "Received write batch request");
+                        let records = this.write_batch(namespace.clone(), messages);
+                        let response = Bytes::from(codec::encode(&records));
+
+                        if let Err(err) = req.respond(response) {
+                            error!(?err, "Failed to respond to write batch request");
+                        }
+
+                        // Publish each record to the active subscribers
+                        for record in records.iter().flatten() {
+                            this.publish(namespace.clone(), record);
+                        }
+                    }
+                    Request::WriteDelayed { namespace, message, release_after } => {
+                        debug!(?namespace, %release_after, "Received delayed write request");
+                        let record = this.write_delayed(namespace, message, release_after);
+                        let response = Bytes::from(codec::encode(&record));
+
+                        if let Err(err) = req.respond(response) {
+                            error!(?err, "Failed to respond to delayed write request");
                         }
                     }
                     Request::ReadRange { namespace, start, end } => {
                         debug!(?namespace, "Received read request");
-                        let log = this.read_range(namespace, start, end);
-                        let Ok(response) = serde_json::to_vec(&log) else {
-                            error!("Failed to serialize log");
-                            continue;
-                        };
+                        let log: Response<Log> = Ok(this.read_range(namespace, start, end));
+                        let response = Bytes::from(codec::encode(&log));
 
-                        if let Err(err) = req.respond(Bytes::from(response)) {
+                        if let Err(err) = req.respond(response) {
                             error!(?err, "Failed to respond to read_range request");
                         }
                     }
-                    Request::ReadMessage { namespace, msg_id } => {
+                    Request::ReadMessage { namespace, msg_id, as_of } => {
                         debug!(?namespace, "Received read message request");
-                        let signature = this.read_message(namespace, msg_id);
-                        let Ok(response) = serde_json::to_vec(&signature).map(Bytes::from) else {
-                            error!("Failed to serialize signature");
-                            continue;
-                        };
+
+                        // The write of the message may have been timestamped already, so it is
+                        // signed and stored before answering, instead of attesting to its
+                        // unavailability as of a later time
+                        if this.is_pending(&namespace, msg_id) {
+                            this.flush_pending_writes();
+                        }
+
+                        let signature = this.read_message(namespace, msg_id, as_of);
+                        let response = Bytes::from(codec::encode(&signature));
 
                         if let Err(err) = req.respond(response) {
                             error!(?err, "Failed to respond to read_message request");
                         }
                     }
-                    Request::Subscribe { namespace } => {
-                        debug!(?namespace, "Received subscribe request");
-                        this.subscribe(namespace);
-
-                        let res = SubscribeResponse {
-                            port: this.pub_socket.local_addr().expect("Publisher not bound").port(),
-                            // TODO: impl auth
-                            auth_token: Bytes::from("noop").into(),
-                        };
-
-                        let Ok(response) = serde_json::to_vec(&res).map(Bytes::from) else {
-                            error!("Failed to serialize subscribe response");
-                            continue;
-                        };
+                    Request::Retention { namespace } => {
+                        debug!(?namespace, "Received retention request");
+                        let policy: Response<RetentionPolicy> = Ok(this.retention(namespace));
+                        let response = Bytes::from(codec::encode(&policy));
 
                         if let Err(err) = req.respond(response) {
-                            error!(?err, "Failed to respond to subscribe request");
+                            error!(?err, "Failed to respond to retention request");
+                        }
+                    }
+                    Request::Sync { syncer, start, end, filters, timestamp, signature } => {
+                        debug!(namespaces = filters.len(), "Received sync request");
+                        let request = SyncRequest { req, syncer, start, end, filters };
+                        this.check_sync(request, timestamp, signature);
+                    }
+                    Request::Subscribe { namespace, subscriber, timestamp, signature } => {
+                        debug!(?namespace, ?subscriber, "Received subscribe request");
+                        match this.subscribe(namespace, subscriber, timestamp, signature) {
+                            // Respond once the publisher socket of the namespace is bound
+                            Ok(token) => this.publishers.respond(req, token),
+                            Err(err) => {
+                                let res: Response<SubscribeResponse> = Err(err);
+                                if let Err(err) = req.respond(Bytes::from(codec::encode(&res))) {
+                                    error!(?err, "Failed to respond to subscribe request");
+                                }
+                            }
                         }
                     }
                 }
@@ -206,16 +1018,55 @@ impl<DS: DataStore + 'static> Future for Validator<DS> {
                 continue;
             }
 
-            // try to flush any pending messages to publish to active subscribers
-            if let Poll::Ready(Some((namespace, serialized_record))) =
-                publisher_queue_rx.poll_recv(cx)
-            {
-                info!(?namespace, "Publishing record to subscribers");
-                let topic_string = String::from_utf8_lossy(&namespace).to_string();
-                if let Err(err) = this.pub_socket.try_publish(topic_string, serialized_record) {
-                    error!(?err, "Failed to publish serialized record to subscriber");
+            // sign the pending writes at the end of the signing window
+            if let Some(timer) = this.signing_timer.as_mut() {
+                if timer.as_mut().poll(cx).is_ready() {
+                    this.flush_pending_writes();
+                    continue;
+                }
+            }
+
+            // release the timelocked records whose release time has passed
+            if let Some(timer) = this.release_timer.as_mut() {
+                if timer.as_mut().poll(cx).is_ready() {
+                    this.release_timelocked();
+                    continue;
+                }
+            }
+
+            // start a sync round with the other validators when it is due
+            if let Some(timer) = this.sync_timer.as_mut() {
+                if timer.as_mut().poll(cx).is_ready() {
+                    this.start_sync_round();
+                    continue;
                 }
+            }
+
+            // store the records fetched in the sync round once it completes
+            if let Some(task) = this.sync_task.as_mut() {
+                if let Poll::Ready(result) = task.poll_unpin(cx) {
+                    this.sync_task = None;
+                    match result {
+                        Ok(Ok((records, next))) => {
+                            this.store_synced(records);
+                            this.continue_sync(next);
+                        }
+                        Ok(Err(err)) => warn!(?err, "Failed to sync with other validators"),
+                        Err(err) => error!(?err, "Sync task failed"),
+                    }
+
+                    continue;
+                }
+            }
+
+            // respond to the sync requests once checked against the registry
+            if let Poll::Ready(Some((request, is_peer))) = this.sync_requests.poll_next_unpin(cx) {
+                this.serve_sync(request, is_peer);
+                continue;
+            }
 
+            // respond to the subscribe requests once the publisher of their namespace is bound
+            if this.publishers.poll_bound(cx).is_ready() {
                 continue;
             }
 
@@ -1,18 +1,72 @@
 use alloy::primitives::B256;
+use blst::min_pk::{PublicKey as BlsPublicKey, Signature as BlsSignature};
 
-use crate::{Log, Message, Namespace, ReadMessageResponse, Record, Timestamp};
+use crate::{
+    primitives::bloom::BloomFilter, Log, Message, Namespace, ReadMessageResponse, Record,
+    RetentionPolicy, SubscriptionToken, SyncResponse, Timestamp, ValidatorError,
+};
 
 /// A validator backend specification.
 pub trait ValidatorSpec {
-    /// Writes a message to the log.
-    fn write(&mut self, namespace: Namespace, message: Message) -> Record;
+    /// Writes a message to the log. Fails if the record couldn't be stored.
+    fn write(&mut self, namespace: Namespace, message: Message) -> Result<Record, ValidatorError>;
+
+    /// Writes a batch of messages to the log, in order, and returns their records.
+    /// Fails if the batch has more than [`MAX_WRITE_BATCH`](crate::MAX_WRITE_BATCH) messages,
+    /// or if any of the records couldn't be stored.
+    fn write_batch(
+        &mut self,
+        namespace: Namespace,
+        messages: Vec<Message>,
+    ) -> Result<Vec<Record>, ValidatorError>;
+
+    /// Writes a message to the log, timestamped now, but withholds it from readers and
+    /// subscribers until the given release time.
+    fn write_delayed(
+        &mut self,
+        namespace: Namespace,
+        message: Message,
+        release_after: Timestamp,
+    ) -> Result<Record, ValidatorError>;
 
     /// Reads a range of log records from the store within the given timestamps.
     fn read_range(&self, namespace: Namespace, start: Timestamp, end: Timestamp) -> Log;
 
-    /// Reads a single log record from the store by its message ID.
-    fn read_message(&self, namespace: Namespace, msg_id: B256) -> ReadMessageResponse;
+    /// Reads a single log record from the store by its message ID, or attests that it
+    /// had not been seen as of the given time (defaults to now). If the message is missing
+    /// but records of its namespace were pruned, answers that it is outside the retention
+    /// window instead. Fails if the message is timelocked or still waiting to be signed at
+    /// the end of the signing window, as it has been seen but can't be revealed yet.
+    fn read_message(
+        &self,
+        namespace: Namespace,
+        msg_id: B256,
+        as_of: Option<Timestamp>,
+    ) -> Result<ReadMessageResponse, ValidatorError>;
+
+    /// Returns the records within a page of the given timestamps that are missing from the
+    /// given filters of the messages another validator holds in each namespace, for it to
+    /// sync, along with the start of the next page if there are more records.
+    fn sync(
+        &self,
+        start: Timestamp,
+        end: Timestamp,
+        filters: Vec<(Namespace, BloomFilter)>,
+    ) -> SyncResponse;
+
+    /// Returns the retention policy of the given namespace, after which its records may be
+    /// pruned.
+    fn retention(&self, namespace: Namespace) -> RetentionPolicy;
 
-    /// Allow clients to subscribe to all messages in a namespace.
-    fn subscribe(&mut self, namespace: Namespace);
+    /// Allow a subscriber to subscribe to all messages in a namespace, given its request
+    /// signed at the given time, and returns the token to connect to the publisher with.
+    /// Fails if the request is not signed by the subscriber, was signed too long ago, or if
+    /// the subscriber is not allowed for the namespace.
+    fn subscribe(
+        &mut self,
+        namespace: Namespace,
+        subscriber: BlsPublicKey,
+        timestamp: Timestamp,
+        signature: BlsSignature,
+    ) -> Result<SubscriptionToken, ValidatorError>;
 }
@@ -0,0 +1,1012 @@
+use std::{
+    collections::BTreeMap,
+    fs::{self, File, OpenOptions},
+    io::{self, BufRead, BufReader, Read, Seek, SeekFrom, Write},
+    path::{Path, PathBuf},
+    slice,
+};
+
+use alloy::primitives::B256;
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+use tracing::{debug, error, warn};
+
+use crate::common::{Log, Namespace, Record, RetentionPolicy, Timestamp};
+
+use super::{DataStore, Retention, Tombstones};
+
+/// The default maximum size of a single segment file, in bytes.
+const DEFAULT_MAX_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+
+/// The prefix and extension of segment file names, e.g. `segment-00000001.log`.
+const SEGMENT_PREFIX: &str = "segment-";
+const SEGMENT_EXTENSION: &str = ".log";
+
+/// The name of the file keeping the low-water mark of each namespace, which is written
+/// before the segments of pruned records are deleted.
+const LOW_WATER_MARKS_FILE: &str = "low-water-marks.json";
+
+/// The name of the file keeping the IDs of the messages pruned from each namespace, which is
+/// written along with the low-water marks.
+const TOMBSTONES_FILE: &str = "tombstones.json";
+
+/// A persistent backend for the data store, backed by append-only segment files.
+///
+/// Every written record is appended as a newline-delimited JSON entry to the active
+/// segment file. Once the active segment grows past the maximum segment size, a new
+/// segment is started. An in-memory index by namespace, message digest and timestamp
+/// is rebuilt from the segments when the store is opened, so a restarted validator
+/// keeps serving the records it signed before.
+///
+/// Records beyond the limits of the retention policy of their namespace are removed from
+/// the index, oldest first by timestamp, and segments without any indexed record left are
+/// deleted. Setting a policy applies it to the records written before, e.g. by a previous
+/// run. Pruned records are noted in the active segment, so that they stay pruned after a
+/// restart even if their segment is kept for other records. The low-water mark of each
+/// namespace and the IDs of its pruned messages are also kept in separate files, so that they
+/// outlive the deleted segments.
+#[derive(Debug)]
+pub struct FilesystemStore {
+    /// The directory containing the segment files.
+    dir: PathBuf,
+    /// The maximum size of a segment file before rolling over to a new one.
+    max_segment_size: u64,
+    /// Whether to `fsync` the active segment after every write.
+    sync_writes: bool,
+    /// The ID of the segment currently being appended to.
+    active_id: u64,
+    /// The segment currently being appended to.
+    active: File,
+    /// The current length of the active segment, in bytes.
+    active_len: u64,
+    /// A map from namespace to the index of its records in the segments.
+    indexes: HashMap<Namespace, NamespaceIndex>,
+    /// The number of indexed records in each segment.
+    live: BTreeMap<u64, usize>,
+    /// The retention policies of the namespaces.
+    retention: Retention,
+    /// The timelocked records not released yet.
+    timelocked: TimelockIndex,
+}
+
+/// The location of a single entry in the segment files.
+#[derive(Debug, Clone, Copy)]
+struct Location {
+    /// The segment ID.
+    segment: u64,
+    /// The byte offset of the entry in the segment.
+    offset: u64,
+    /// The length of the entry, excluding the trailing newline.
+    len: u64,
+    /// The size of the message of the entry's record, in bytes.
+    size: u64,
+}
+
+/// The index of all records in a single namespace.
+#[derive(Debug, Default)]
+struct NamespaceIndex {
+    /// A map from message digest to the timestamp and location of its record.
+    by_digest: HashMap<B256, (Timestamp, Location)>,
+    /// The location of every record, ordered by timestamp.
+    by_time: BTreeMap<(Timestamp, B256), Location>,
+    /// The total size of the indexed messages, in bytes.
+    bytes: u64,
+    /// The timestamp of the latest pruned record, if any.
+    pruned_until: Option<Timestamp>,
+    /// The message digests of the pruned records.
+    tombstones: Tombstones,
+}
+
+impl NamespaceIndex {
+    /// Inserts a record location into the index, replacing any previous record
+    /// with the same message digest. Returns the location of the replaced record.
+    fn insert(
+        &mut self,
+        digest: B256,
+        timestamp: Timestamp,
+        location: Location,
+    ) -> Option<Location> {
+        self.bytes += location.size;
+        let old = self.by_digest.insert(digest, (timestamp, location));
+
+        if let Some((old_timestamp, old_location)) = old {
+            self.bytes -= old_location.size;
+            self.by_time.remove(&(old_timestamp, digest));
+        }
+
+        self.by_time.insert((timestamp, digest), location);
+        old.map(|(_, location)| location)
+    }
+
+    /// Removes the record with the given message digest from the index, if any, and marks it
+    /// as pruned.
+    fn remove(&mut self, digest: B256) {
+        if let Some((timestamp, location)) = self.by_digest.remove(&digest) {
+            self.by_time.remove(&(timestamp, digest));
+            self.bytes -= location.size;
+        }
+
+        if !self.tombstones.contains(digest) {
+            self.tombstones.insert(digest);
+        }
+    }
+
+    /// Returns whether a record with the given message digest and timestamp was pruned before.
+    /// A false positive of the tombstones only affects records older than the low-water mark,
+    /// which may have been pruned as far as readers know.
+    fn is_pruned(&self, digest: B256, timestamp: Timestamp) -> bool {
+        self.pruned_until.is_some_and(|until| timestamp <= until) &&
+            self.tombstones.contains(digest)
+    }
+
+    /// Removes the records that expired at the given time, then the oldest records until
+    /// the limits of the policy are no longer exceeded. Returns the message digests and
+    /// locations of the removed records.
+    fn prune(&mut self, policy: &RetentionPolicy, now: Timestamp) -> Vec<(B256, Location)> {
+        let cutoff = policy.cutoff(now);
+        let mut removed = Vec::new();
+
+        while let Some((&(timestamp, digest), &location)) = self.by_time.first_key_value() {
+            let expired = cutoff.is_some_and(|cutoff| timestamp < cutoff);
+            if !expired && !policy.is_exceeded(self.by_digest.len(), self.bytes) {
+                break
+            }
+
+            self.by_time.remove(&(timestamp, digest));
+            self.by_digest.remove(&digest);
+            self.bytes -= location.size;
+            self.pruned_until = self.pruned_until.max(Some(timestamp));
+            self.tombstones.insert(digest);
+            removed.push((digest, location));
+        }
+
+        removed
+    }
+}
+
+/// The locations of the timelocked records not released yet, along with their release time,
+/// keyed by namespace and message digest.
+type TimelockIndex = HashMap<(Namespace, B256), (Timestamp, Location)>;
+
+/// A single entry in a segment file.
+#[derive(Debug, Serialize, Deserialize)]
+struct Entry {
+    namespace: Namespace,
+    record: Record,
+    /// The release time of a timelocked record. The record is only indexed once an entry
+    /// without release time is written for it, on its release.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    release_after: Option<Timestamp>,
+}
+
+/// An entry in a segment file noting the records pruned from a namespace, which are removed
+/// from the index again when the segment is replayed.
+#[derive(Debug, Serialize, Deserialize)]
+struct Pruned {
+    namespace: Namespace,
+    /// The low-water mark of the namespace after pruning.
+    pruned_until: Timestamp,
+    /// The message digests of the pruned records.
+    digests: Vec<B256>,
+}
+
+/// A single line in a segment file.
+#[derive(Debug)]
+enum Line {
+    Entry(Box<Entry>),
+    Pruned(Pruned),
+}
+
+impl FilesystemStore {
+    /// Opens the store in the given directory, creating it if it doesn't exist.
+    ///
+    /// The low-water marks and pruned messages of the namespaces are restored, then all
+    /// existing segments are replayed to rebuild the index and the timelocked records not
+    /// released yet, skipping the records pruned before. A partially written entry at the end
+    /// of the last segment (e.g. after a crash) is truncated.
+    pub fn open(dir: impl AsRef<Path>) -> io::Result<Self> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let segments = list_segments(&dir)?;
+        let mut indexes: HashMap<Namespace, NamespaceIndex> = HashMap::new();
+        let mut timelocked = HashMap::new();
+
+        for (namespace, low_water_mark) in read_low_water_marks(&dir)? {
+            indexes.entry(namespace).or_default().pruned_until = Some(low_water_mark);
+        }
+
+        for (namespace, tombstones) in read_tombstones(&dir)? {
+            indexes.entry(namespace).or_default().tombstones = tombstones;
+        }
+
+        let mut active_id = 0;
+        let mut active_len = 0;
+        for (i, &id) in segments.iter().enumerate() {
+            let is_last = i == segments.len() - 1;
+            let path = segment_path(&dir, id);
+            let len = replay_segment(&path, id, is_last, &mut indexes, &mut timelocked)?;
+
+            active_id = id;
+            active_len = len;
+        }
+
+        let active =
+            OpenOptions::new().create(true).append(true).open(segment_path(&dir, active_id))?;
+
+        let mut live = BTreeMap::new();
+        for index in indexes.values() {
+            for (_, location) in index.by_digest.values() {
+                *live.entry(location.segment).or_default() += 1;
+            }
+        }
+        for (_, location) in timelocked.values() {
+            *live.entry(location.segment).or_default() += 1;
+        }
+
+        debug!(?dir, segments = segments.len(), namespaces = indexes.len(), "Opened store");
+
+        Ok(Self {
+            dir,
+            max_segment_size: DEFAULT_MAX_SEGMENT_SIZE,
+            sync_writes: false,
+            active_id,
+            active,
+            active_len,
+            indexes,
+            live,
+            retention: Retention::default(),
+            timelocked,
+        })
+    }
+
+    /// Sets the retention policy of namespaces without their own policy, and prunes the
+    /// records beyond it.
+    pub fn with_default_retention(mut self, policy: RetentionPolicy) -> Self {
+        self.retention.default = policy;
+        self.prune_all();
+        self
+    }
+
+    /// Sets the retention policy of the given namespace, and prunes the records beyond it.
+    pub fn with_retention(mut self, namespace: Namespace, policy: RetentionPolicy) -> Self {
+        self.retention.namespaces.insert(namespace, policy);
+        self.prune_all();
+        self
+    }
+
+    /// Sets the maximum size of a segment file before rolling over to a new one.
+    pub fn with_max_segment_size(mut self, max_segment_size: u64) -> Self {
+        self.max_segment_size = max_segment_size;
+        self
+    }
+
+    /// Sets whether to `fsync` the active segment after every write. Without it,
+    /// written records survive a process crash but not necessarily a power loss.
+    pub fn with_sync_writes(mut self, sync_writes: bool) -> Self {
+        self.sync_writes = sync_writes;
+        self
+    }
+
+    /// Appends an entry to the active segment, rolling over to a new segment if needed.
+    fn append(&mut self, entry: &Entry) -> io::Result<Location> {
+        let mut locations = self.append_all(slice::from_ref(entry))?;
+        Ok(locations.pop().expect("One location per entry"))
+    }
+
+    /// Appends entries to the active segment in a single write, rolling over to a new segment
+    /// first if needed. Either all the entries are appended, or none of them.
+    fn append_all(&mut self, entries: &[Entry]) -> io::Result<Vec<Location>> {
+        if self.active_len >= self.max_segment_size {
+            self.roll()?;
+        }
+
+        let lines = self.append_lines(entries)?;
+        let locations = entries
+            .iter()
+            .zip(lines)
+            .map(|(entry, (offset, len))| {
+                let size = entry.record.message.0.len() as u64;
+                Location { segment: self.active_id, offset, len, size }
+            })
+            .collect();
+
+        Ok(locations)
+    }
+
+    /// Appends lines to the active segment in a single write, without rolling over. Returns
+    /// the offset and the length of each, excluding the trailing newline.
+    fn append_lines<T: Serialize>(&mut self, lines: &[T]) -> io::Result<Vec<(u64, u64)>> {
+        let mut buf = Vec::new();
+        let mut spans = Vec::with_capacity(lines.len());
+        for line in lines {
+            let start = buf.len() as u64;
+            serde_json::to_writer(&mut buf, line)?;
+            spans.push((self.active_len + start, buf.len() as u64 - start));
+            buf.push(b'\n');
+        }
+
+        let written = self.active.write_all(&buf).and_then(|()| {
+            if self.sync_writes {
+                self.active.sync_data()
+            } else {
+                Ok(())
+            }
+        });
+
+        // The records are not acknowledged, so partially written entries are rolled back,
+        // which would otherwise corrupt the segment for the entries appended after them
+        if let Err(err) = written {
+            if let Err(err) = self.active.set_len(self.active_len) {
+                error!(?err, segment = self.active_id, "Failed to roll back partial entry");
+            }
+
+            return Err(err)
+        }
+
+        self.active_len += buf.len() as u64;
+
+        Ok(spans)
+    }
+
+    /// Prunes the records beyond the retention policy of the given namespace.
+    fn prune(&mut self, namespace: &Namespace) {
+        let policy = self.retention.policy(namespace);
+        let Some(index) = self.indexes.get_mut(namespace) else { return };
+
+        let (digests, removed): (Vec<_>, Vec<_>) =
+            index.prune(&policy, Timestamp::now()).into_iter().unzip();
+        if removed.is_empty() {
+            return
+        }
+
+        debug!(?namespace, len = removed.len(), "Pruned records");
+
+        // Otherwise the pruned records would be indexed again after a restart, as long as their
+        // segment is kept for other records
+        let pruned_until = index.pruned_until.expect("Set when pruning");
+        let pruned = Pruned { namespace: namespace.clone(), pruned_until, digests };
+        if let Err(err) = self.append_lines(slice::from_ref(&pruned)) {
+            warn!(?err, ?namespace, "Failed to note pruned records");
+        }
+
+        self.release(removed);
+    }
+
+    /// Prunes the records beyond the retention policy of every namespace, and deletes the
+    /// segments without any indexed record, e.g. whose records were all replaced.
+    fn prune_all(&mut self) {
+        for namespace in self.namespaces() {
+            self.prune(&namespace);
+        }
+
+        let Ok(segments) = list_segments(&self.dir) else { return };
+        for id in segments {
+            if id != self.active_id && !self.live.contains_key(&id) {
+                self.delete_segment(id);
+            }
+        }
+    }
+
+    /// Releases the given locations, which are no longer indexed, and deletes the segments
+    /// without any indexed record left, except the active one.
+    fn release(&mut self, locations: impl IntoIterator<Item = Location>) {
+        for location in locations {
+            let Some(count) = self.live.get_mut(&location.segment) else { continue };
+
+            *count -= 1;
+            if *count == 0 {
+                self.live.remove(&location.segment);
+                if location.segment != self.active_id {
+                    self.delete_segment(location.segment);
+                }
+            }
+        }
+    }
+
+    /// Deletes the segment with the given ID, once the low-water marks and pruned messages
+    /// are persisted.
+    fn delete_segment(&self, id: u64) {
+        // Otherwise the pruned records would be forgotten after a restart
+        if let Err(err) = self.persist_low_water_marks().and_then(|()| self.persist_tombstones()) {
+            warn!(?err, segment = id, "Failed to persist pruned records, keeping segment");
+            return
+        }
+
+        match fs::remove_file(segment_path(&self.dir, id)) {
+            Ok(()) => debug!(segment = id, "Deleted segment without any record left"),
+            Err(err) => warn!(?err, segment = id, "Failed to delete segment"),
+        }
+    }
+
+    /// Writes the low-water marks of the namespaces to their file, replacing it atomically.
+    fn persist_low_water_marks(&self) -> io::Result<()> {
+        let marks = self
+            .indexes
+            .iter()
+            .filter_map(|(namespace, index)| Some((namespace.clone(), index.pruned_until?)))
+            .collect::<Vec<_>>();
+
+        let path = self.dir.join(LOW_WATER_MARKS_FILE);
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec(&marks)?)?;
+        fs::rename(tmp_path, path)
+    }
+
+    /// Writes the IDs of the messages pruned from the namespaces to their file, replacing it
+    /// atomically.
+    fn persist_tombstones(&self) -> io::Result<()> {
+        let tombstones = self
+            .indexes
+            .iter()
+            .filter(|(_, index)| !index.tombstones.filters.is_empty())
+            .map(|(namespace, index)| (namespace, &index.tombstones))
+            .collect::<Vec<_>>();
+
+        let path = self.dir.join(TOMBSTONES_FILE);
+        let tmp_path = path.with_extension("tmp");
+        fs::write(&tmp_path, serde_json::to_vec(&tombstones)?)?;
+        fs::rename(tmp_path, path)
+    }
+
+    /// Starts a new active segment.
+    fn roll(&mut self) -> io::Result<()> {
+        self.active.sync_all()?;
+
+        // The records of the previous segment may all have been pruned while it was active
+        if !self.live.contains_key(&self.active_id) {
+            self.delete_segment(self.active_id);
+        }
+
+        let id = self.active_id + 1;
+        self.active =
+            OpenOptions::new().create(true).append(true).open(segment_path(&self.dir, id))?;
+        self.active_id = id;
+        self.active_len = 0;
+
+        debug!(segment = id, "Rolled over to new segment");
+
+        Ok(())
+    }
+
+    /// Reads the records at the given locations, in order. Segment files are reused
+    /// across consecutive locations in the same segment.
+    fn read_records<'a>(
+        &self,
+        locations: impl IntoIterator<Item = &'a Location>,
+    ) -> io::Result<Vec<Record>> {
+        let mut open: Option<(u64, File)> = None;
+        let mut records = Vec::new();
+
+        for location in locations {
+            if open.as_ref().map(|(id, _)| *id) != Some(location.segment) {
+                let file = File::open(segment_path(&self.dir, location.segment))?;
+                open = Some((location.segment, file));
+            }
+
+            let (_, file) = open.as_mut().expect("Segment opened");
+            records.push(read_entry(file, location)?.record);
+        }
+
+        Ok(records)
+    }
+
+    /// Indexes an entry appended to the given location, replacing the record of the same
+    /// message if any, and prunes its namespace.
+    fn index_entry(&mut self, entry: Entry, location: Location) {
+        // We need to deduplicate messages in the store by their message digest,
+        // which doesn't contain the committed timestamp.
+        let message_digest = entry.record.message_digest(&entry.namespace);
+        let timestamp = entry.record.timestamp;
+        *self.live.entry(location.segment).or_default() += 1;
+
+        let replaced = self.indexes.entry(entry.namespace.clone()).or_default().insert(
+            message_digest,
+            timestamp,
+            location,
+        );
+        self.release(replaced);
+
+        // A timelocked record is written again on its release
+        let released = self.timelocked.remove(&(entry.namespace.clone(), message_digest));
+        self.release(released.map(|(_, location)| location));
+        self.prune(&entry.namespace);
+    }
+}
+
+impl DataStore for FilesystemStore {
+    fn read_page(
+        &self,
+        namespace: Namespace,
+        start: Timestamp,
+        end: Timestamp,
+        limit: usize,
+    ) -> Log {
+        let Some(index) = self.indexes.get(&namespace) else { return Log { records: Vec::new() } };
+
+        // Expired records may not have been pruned yet
+        let start = self.retention.read_start(&namespace, start);
+        if start > end {
+            return Log { records: Vec::new() }
+        }
+
+        let range = (start, B256::ZERO)..=(end, B256::repeat_byte(0xff));
+        let locations = index.by_time.range(range).take(limit).map(|(_, location)| location);
+
+        match self.read_records(locations) {
+            Ok(records) => Log { records },
+            Err(err) => {
+                error!(?err, "Failed to read records from segments");
+                Log { records: Vec::new() }
+            }
+        }
+    }
+
+    fn read_message(&self, namespace: Namespace, msg_id: B256) -> Option<Record> {
+        let (timestamp, location) = self.indexes.get(&namespace)?.by_digest.get(&msg_id)?;
+        if self.retention.is_expired(&namespace, *timestamp) {
+            return None
+        }
+
+        match self.read_records([location]) {
+            Ok(mut records) => records.pop(),
+            Err(err) => {
+                error!(?err, "Failed to read record from segments");
+                None
+            }
+        }
+    }
+
+    fn namespaces(&self) -> Vec<Namespace> {
+        self.indexes.keys().cloned().collect()
+    }
+
+    fn retention(&self, namespace: &Namespace) -> RetentionPolicy {
+        self.retention.policy(namespace)
+    }
+
+    fn low_water_mark(&self, namespace: &Namespace) -> Option<Timestamp> {
+        let index = self.indexes.get(namespace)?;
+        let oldest = index.by_time.first_key_value().map(|((timestamp, _), _)| *timestamp);
+
+        self.retention.low_water_mark(namespace, index.pruned_until, oldest)
+    }
+
+    fn may_have_pruned(&self, namespace: &Namespace, msg_id: B256) -> bool {
+        let Some(index) = self.indexes.get(namespace) else { return false };
+
+        // Expired records may not have been pruned yet
+        match index.by_digest.get(&msg_id) {
+            Some((timestamp, _)) => self.retention.is_expired(namespace, *timestamp),
+            None => index.tombstones.contains(msg_id),
+        }
+    }
+
+    fn timelocked(&self) -> Vec<(Namespace, Record, Timestamp)> {
+        let mut timelocked = Vec::with_capacity(self.timelocked.len());
+        for ((namespace, _), (release_after, location)) in &self.timelocked {
+            match self.read_records([location]) {
+                Ok(mut records) => {
+                    let record = records.pop().expect("One record per location");
+                    timelocked.push((namespace.clone(), record, *release_after));
+                }
+                Err(err) => error!(?err, "Failed to read timelocked record from segments"),
+            }
+        }
+
+        timelocked
+    }
+
+    fn is_full(&self, namespace: &Namespace) -> bool {
+        self.indexes.get(namespace).is_some_and(|index| {
+            self.retention.policy(namespace).is_reached(index.by_digest.len(), index.bytes)
+        })
+    }
+
+    fn write_timelocked(
+        &mut self,
+        namespace: Namespace,
+        record: Record,
+        release_after: Timestamp,
+    ) -> io::Result<()> {
+        let message_digest = record.message_digest(&namespace);
+
+        let entry = Entry { namespace, record, release_after: Some(release_after) };
+        let location = self.append(&entry)?;
+        *self.live.entry(location.segment).or_default() += 1;
+
+        let replaced =
+            self.timelocked.insert((entry.namespace, message_digest), (release_after, location));
+        self.release(replaced.map(|(_, location)| location));
+
+        Ok(())
+    }
+
+    fn write_one(&mut self, namespace: Namespace, record: Record) -> io::Result<()> {
+        let entry = Entry { namespace, record, release_after: None };
+        let location = self.append(&entry)?;
+        self.index_entry(entry, location);
+
+        Ok(())
+    }
+
+    fn write_batch(&mut self, namespace: Namespace, records: Vec<Record>) -> io::Result<()> {
+        let entries = records
+            .into_iter()
+            .map(|record| Entry { namespace: namespace.clone(), record, release_after: None })
+            .collect::<Vec<_>>();
+
+        // The batch is appended in a single write, so that a failure doesn't leave part of it
+        // stored
+        let locations = self.append_all(&entries)?;
+        for (entry, location) in entries.into_iter().zip(locations) {
+            self.index_entry(entry, location);
+        }
+
+        Ok(())
+    }
+}
+
+/// Returns the path of the segment file with the given ID.
+fn segment_path(dir: &Path, id: u64) -> PathBuf {
+    dir.join(format!("{SEGMENT_PREFIX}{id:08}{SEGMENT_EXTENSION}"))
+}
+
+/// Returns the sorted IDs of all segment files in the given directory.
+fn list_segments(dir: &Path) -> io::Result<Vec<u64>> {
+    let mut segments = Vec::new();
+
+    for dir_entry in fs::read_dir(dir)? {
+        let file_name = dir_entry?.file_name();
+        let Some(id) = file_name
+            .to_str()
+            .and_then(|name| name.strip_prefix(SEGMENT_PREFIX))
+            .and_then(|name| name.strip_suffix(SEGMENT_EXTENSION))
+            .and_then(|id| id.parse().ok())
+        else {
+            continue
+        };
+
+        segments.push(id);
+    }
+
+    segments.sort_unstable();
+    Ok(segments)
+}
+
+/// Reads the low-water marks of the namespaces in the given directory, if any were written.
+fn read_low_water_marks(dir: &Path) -> io::Result<Vec<(Namespace, Timestamp)>> {
+    match fs::read(dir.join(LOW_WATER_MARKS_FILE)) {
+        Ok(marks) => Ok(serde_json::from_slice(&marks)?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Reads the IDs of the messages pruned from the namespaces in the given directory, if any
+/// were written.
+fn read_tombstones(dir: &Path) -> io::Result<Vec<(Namespace, Tombstones)>> {
+    match fs::read(dir.join(TOMBSTONES_FILE)) {
+        Ok(tombstones) => Ok(serde_json::from_slice(&tombstones)?),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Vec::new()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Replays all entries in a segment file into the given indexes and timelocked records, and
+/// returns the length of the valid part of the segment. Records pruned before are skipped.
+///
+/// If `truncate_tail` is set, a partially written entry at the end of the segment is
+/// removed. Otherwise, or if a complete entry cannot be decoded, an error is returned.
+fn replay_segment(
+    path: &Path,
+    id: u64,
+    truncate_tail: bool,
+    indexes: &mut HashMap<Namespace, NamespaceIndex>,
+    timelocked: &mut TimelockIndex,
+) -> io::Result<u64> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut buf = Vec::new();
+    let mut offset = 0;
+
+    loop {
+        buf.clear();
+        let read = reader.read_until(b'\n', &mut buf)?;
+        if read == 0 {
+            break
+        }
+
+        if buf.last() != Some(&b'\n') {
+            if !truncate_tail {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    format!("incomplete entry in segment {id} at offset {offset}"),
+                ))
+            }
+
+            warn!(segment = id, offset, "Truncating incomplete entry at the end of segment");
+            OpenOptions::new().write(true).open(path)?.set_len(offset)?;
+            break
+        }
+
+        let len = read as u64 - 1;
+        let entry = match decode_line(&buf[..len as usize])? {
+            Line::Entry(entry) => *entry,
+            Line::Pruned(pruned) => {
+                let index = indexes.entry(pruned.namespace).or_default();
+                index.pruned_until = index.pruned_until.max(Some(pruned.pruned_until));
+                for digest in pruned.digests {
+                    index.remove(digest);
+                }
+
+                offset += read as u64;
+                continue
+            }
+        };
+        let digest = entry.record.message_digest(&entry.namespace);
+        let size = entry.record.message.0.len() as u64;
+        let location = Location { segment: id, offset, len, size };
+
+        let key = (entry.namespace, digest);
+        if let Some(release_after) = entry.release_after {
+            timelocked.insert(key, (release_after, location));
+        } else {
+            timelocked.remove(&key);
+
+            let index = indexes.entry(key.0).or_default();
+            if !index.is_pruned(digest, entry.record.timestamp) {
+                index.insert(digest, entry.record.timestamp, location);
+            }
+        }
+
+        offset += read as u64;
+    }
+
+    Ok(offset)
+}
+
+/// Decodes a single line of a segment file, which is most likely an entry.
+fn decode_line(line: &[u8]) -> io::Result<Line> {
+    match serde_json::from_slice(line) {
+        Ok(entry) => Ok(Line::Entry(entry)),
+        Err(err) => match serde_json::from_slice(line) {
+            Ok(pruned) => Ok(Line::Pruned(pruned)),
+            Err(_) => Err(err.into()),
+        },
+    }
+}
+
+/// Reads a single entry from the given segment file at the given location.
+fn read_entry(file: &mut File, location: &Location) -> io::Result<Entry> {
+    file.seek(SeekFrom::Start(location.offset))?;
+
+    let mut buf = vec![0; location.len as usize];
+    file.read_exact(&mut buf)?;
+
+    Ok(serde_json::from_slice(&buf)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::signed_record;
+
+    fn temp_dir() -> PathBuf {
+        std::env::temp_dir().join(format!("dato-store-{}", rand::random::<u64>()))
+    }
+
+    #[test]
+    fn test_reopen_store() -> eyre::Result<()> {
+        let dir = temp_dir();
+        let namespace = Namespace::from_static(b"test");
+
+        let record = signed_record(&namespace, b"made with chatgpt", Timestamp::now());
+        let msg_id = record.message_digest(&namespace);
+
+        let mut store = FilesystemStore::open(&dir)?.with_max_segment_size(1);
+        store.write_one(namespace.clone(), record.clone())?;
+        store.write_one(
+            namespace.clone(),
+            signed_record(&namespace, b"made with chatgpt 2", Timestamp::now()),
+        )?;
+        drop(store);
+
+        let store = FilesystemStore::open(&dir)?;
+        let read = store.read_message(namespace.clone(), msg_id).expect("Record persisted");
+        assert_eq!(read.message, record.message);
+        assert_eq!(read.timestamp, record.timestamp);
+
+        let log = store.read_range(namespace, Timestamp::default(), Timestamp::now());
+        assert_eq!(log.len(), 2);
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_write_batch() -> eyre::Result<()> {
+        let dir = temp_dir();
+        let namespace = Namespace::from_static(b"test");
+
+        let timestamp = Timestamp::now();
+        let records = [b"one".as_slice(), b"two", b"three"]
+            .map(|message| signed_record(&namespace, message, timestamp))
+            .to_vec();
+
+        // The batch is appended at once, even past the maximum segment size
+        let mut store = FilesystemStore::open(&dir)?.with_max_segment_size(1);
+        store.write_batch(namespace.clone(), records.clone())?;
+        assert_eq!(list_segments(&dir)?, vec![0]);
+        drop(store);
+
+        let store = FilesystemStore::open(&dir)?;
+        for record in records {
+            let msg_id = record.message_digest(&namespace);
+            let read = store.read_message(namespace.clone(), msg_id).expect("Record persisted");
+            assert_eq!(read.message, record.message);
+        }
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_truncate_incomplete_entry() -> eyre::Result<()> {
+        let dir = temp_dir();
+        let namespace = Namespace::from_static(b"test");
+
+        let mut store = FilesystemStore::open(&dir)?;
+        store.write_one(
+            namespace.clone(),
+            signed_record(&namespace, b"made with chatgpt", Timestamp::now()),
+        )?;
+        drop(store);
+
+        // Simulate a crash in the middle of a write
+        let mut segment = OpenOptions::new().append(true).open(segment_path(&dir, 0))?;
+        segment.write_all(b"{\"namespace\":")?;
+        drop(segment);
+
+        let mut store = FilesystemStore::open(&dir)?;
+        store.write_one(
+            namespace.clone(),
+            signed_record(&namespace, b"made with chatgpt 2", Timestamp::now()),
+        )?;
+        drop(store);
+
+        let store = FilesystemStore::open(&dir)?;
+        let log = store.read_range(namespace, Timestamp::default(), Timestamp::now());
+        assert_eq!(log.len(), 2);
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_timelocked() -> eyre::Result<()> {
+        let dir = temp_dir();
+        let namespace = Namespace::from_static(b"test");
+
+        let record = signed_record(&namespace, b"made with chatgpt", Timestamp::now());
+        let msg_id = record.message_digest(&namespace);
+        let release_after = Timestamp::from(u128::from(Timestamp::now()) + 60_000);
+
+        let mut store = FilesystemStore::open(&dir)?.with_max_segment_size(1);
+        store.write_timelocked(namespace.clone(), record.clone(), release_after)?;
+        assert!(store.read_message(namespace.clone(), msg_id).is_none());
+        drop(store);
+
+        // The timelocked record outlives a restart, still withheld
+        let mut store = FilesystemStore::open(&dir)?.with_max_segment_size(1);
+        let timelocked = store.timelocked();
+        assert_eq!(timelocked.len(), 1);
+        assert_eq!(timelocked[0].1.message, record.message);
+        assert_eq!(timelocked[0].2, release_after);
+        assert!(store.read_message(namespace.clone(), msg_id).is_none());
+
+        // Once released, it is read like any other record, and no longer timelocked
+        store.write_one(namespace.clone(), record)?;
+        drop(store);
+
+        let store = FilesystemStore::open(&dir)?;
+        assert!(store.timelocked().is_empty());
+        assert!(store.read_message(namespace, msg_id).is_some());
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_retention() -> eyre::Result<()> {
+        let dir = temp_dir();
+        let namespace = Namespace::from_static(b"test");
+
+        let mut store = FilesystemStore::open(&dir)?.with_max_segment_size(1);
+        for message in [&b"made with chatgpt"[..], b"made with chatgpt 2", b"made with chatgpt 3"] {
+            store.write_one(
+                namespace.clone(),
+                signed_record(&namespace, message, Timestamp::now()),
+            )?;
+            // Distinct timestamps, so that the oldest records are pruned
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        drop(store);
+        assert_eq!(list_segments(&dir)?.len(), 3);
+
+        // Setting a policy prunes the records of the previous run, and deletes their segments
+        let policy = RetentionPolicy::default().with_max_records(1);
+        let mut store = FilesystemStore::open(&dir)?
+            .with_max_segment_size(1)
+            .with_retention(namespace.clone(), policy);
+        assert_eq!(store.retention(&namespace), policy);
+        assert!(store.is_full(&namespace));
+        assert_eq!(list_segments(&dir)?.len(), 1);
+
+        let kept = store.read_range(namespace.clone(), Timestamp::default(), Timestamp::now());
+        let low_water_mark = store.low_water_mark(&namespace).expect("Records pruned");
+        assert!(low_water_mark < kept.records[0].timestamp);
+
+        let pruned = signed_record(&namespace, b"made with chatgpt", Timestamp::now());
+        let pruned_id = pruned.message_digest(&namespace);
+        assert!(store.may_have_pruned(&namespace, pruned_id));
+        assert!(!store.may_have_pruned(&namespace, kept.records[0].message_digest(&namespace)));
+        assert!(!store.may_have_pruned(&namespace, B256::ZERO));
+
+        let record = signed_record(&namespace, b"made with chatgpt 4", Timestamp::now());
+        store.write_one(namespace.clone(), record.clone())?;
+        assert_eq!(list_segments(&dir)?.len(), 1);
+        assert_eq!(store.low_water_mark(&namespace), Some(kept.records[0].timestamp));
+        drop(store);
+
+        // The low-water mark and the pruned messages outlive the deleted segments, even
+        // without a policy
+        let store = FilesystemStore::open(&dir)?;
+        assert_eq!(store.low_water_mark(&namespace), Some(kept.records[0].timestamp));
+        assert!(store.may_have_pruned(&namespace, pruned_id));
+        assert!(!store.may_have_pruned(&namespace, B256::ZERO));
+
+        let log = store.read_range(namespace, Timestamp::default(), Timestamp::now());
+        assert_eq!(log.len(), 1);
+        assert_eq!(log.records[0].message, record.message);
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+
+    #[test]
+    fn test_reopen_without_retention() -> eyre::Result<()> {
+        let dir = temp_dir();
+        let namespace = Namespace::from_static(b"test");
+
+        let policy = RetentionPolicy::default().with_max_records(1);
+        let mut store = FilesystemStore::open(&dir)?.with_retention(namespace.clone(), policy);
+        let mut records = Vec::new();
+        for message in [&b"made with chatgpt"[..], b"made with chatgpt 2", b"made with chatgpt 3"] {
+            let record = signed_record(&namespace, message, Timestamp::now());
+            store.write_one(namespace.clone(), record.clone())?;
+            records.push(record);
+            // Distinct timestamps, so that the oldest records are pruned
+            std::thread::sleep(std::time::Duration::from_millis(2));
+        }
+        drop(store);
+
+        // The pruned records share their segment with the kept one, which is not deleted
+        assert_eq!(list_segments(&dir)?.len(), 1);
+
+        // The pruned records stay pruned, even without reapplying the policy
+        let store = FilesystemStore::open(&dir)?;
+        let log = store.read_range(namespace.clone(), Timestamp::default(), Timestamp::now());
+        assert_eq!(log.len(), 1);
+        assert_eq!(log.records[0].message, records[2].message);
+        assert_eq!(store.low_water_mark(&namespace), Some(records[1].timestamp));
+
+        for record in &records[..2] {
+            let msg_id = record.message_digest(&namespace);
+            assert!(store.read_message(namespace.clone(), msg_id).is_none());
+            assert!(store.may_have_pruned(&namespace, msg_id));
+        }
+
+        fs::remove_dir_all(dir)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,377 @@
+use std::{
+    collections::{BTreeSet, VecDeque},
+    io,
+};
+
+use alloy::primitives::B256;
+use hashbrown::HashMap;
+
+use crate::common::{Log, Namespace, Record, RetentionPolicy, Timestamp};
+
+use super::{DataStore, Retention, Tombstones};
+
+/// An in-memory backend for the data store.
+#[derive(Debug)]
+pub struct InMemoryStore {
+    /// The number of records to allocate for per namespace.
+    cap: usize,
+    /// The retention policies of the namespaces.
+    retention: Retention,
+    /// A map from namespace to a time-indexed FIFO map of records. The FIFO order is
+    /// used to evict old records when the limits of each namespace are reached.
+    record_maps: HashMap<Namespace, RecordMap>,
+    /// The timelocked records not released yet, along with their release time, keyed by
+    /// namespace and message digest.
+    timelocked: HashMap<(Namespace, B256), (Record, Timestamp)>,
+}
+
+impl InMemoryStore {
+    /// Creates a new in-memory store that keeps at most the given number of records per
+    /// namespace, unless a retention policy with another record limit is set.
+    pub fn with_capacity(cap: usize) -> Self {
+        let retention = Retention {
+            default: RetentionPolicy::default().with_max_records(cap as u64),
+            ..Default::default()
+        };
+
+        Self {
+            cap,
+            retention,
+            record_maps: HashMap::with_capacity(cap),
+            timelocked: HashMap::new(),
+        }
+    }
+
+    /// Sets the retention policy of namespaces without their own policy. The records are kept
+    /// in memory, so the capacity of the store still bounds them unless the policy sets
+    /// another record limit.
+    pub fn with_default_retention(mut self, policy: RetentionPolicy) -> Self {
+        self.retention.default = self.capped(policy);
+        self
+    }
+
+    /// Sets the retention policy of the given namespace, bounded by the capacity of the store
+    /// unless it sets another record limit.
+    pub fn with_retention(mut self, namespace: Namespace, policy: RetentionPolicy) -> Self {
+        let policy = self.capped(policy);
+        self.retention.namespaces.insert(namespace, policy);
+        self
+    }
+
+    /// Returns the given policy, limited to the capacity of the store if it has no record
+    /// limit of its own.
+    fn capped(&self, policy: RetentionPolicy) -> RetentionPolicy {
+        match policy.max_records {
+            Some(_) => policy,
+            None => policy.with_max_records(self.cap as u64),
+        }
+    }
+}
+
+impl DataStore for InMemoryStore {
+    fn read_page(
+        &self,
+        namespace: Namespace,
+        start: Timestamp,
+        end: Timestamp,
+        limit: usize,
+    ) -> Log {
+        let Some(existing) = self.record_maps.get(&namespace) else {
+            return Log { records: Vec::new() }
+        };
+
+        // Expired records may not have been pruned yet
+        let start = self.retention.read_start(&namespace, start);
+        if start > end {
+            return Log { records: Vec::new() }
+        }
+
+        Log { records: existing.range(start, end).take(limit).cloned().collect() }
+    }
+
+    fn read_message(&self, namespace: Namespace, msg_id: B256) -> Option<Record> {
+        self.record_maps
+            .get(&namespace)?
+            .get(&msg_id)
+            .filter(|record| !self.retention.is_expired(&namespace, record.timestamp))
+            .cloned()
+    }
+
+    fn namespaces(&self) -> Vec<Namespace> {
+        self.record_maps.keys().cloned().collect()
+    }
+
+    fn retention(&self, namespace: &Namespace) -> RetentionPolicy {
+        self.retention.policy(namespace)
+    }
+
+    fn low_water_mark(&self, namespace: &Namespace) -> Option<Timestamp> {
+        let record_map = self.record_maps.get(namespace)?;
+        let oldest = record_map.by_time.first().map(|(timestamp, _)| *timestamp);
+
+        self.retention.low_water_mark(namespace, record_map.pruned_until, oldest)
+    }
+
+    fn may_have_pruned(&self, namespace: &Namespace, msg_id: B256) -> bool {
+        let Some(record_map) = self.record_maps.get(namespace) else { return false };
+
+        // Expired records may not have been pruned yet
+        match record_map.get(&msg_id) {
+            Some(record) => self.retention.is_expired(namespace, record.timestamp),
+            None => record_map.tombstones.contains(msg_id),
+        }
+    }
+
+    fn is_full(&self, namespace: &Namespace) -> bool {
+        self.record_maps.get(namespace).is_some_and(|record_map| {
+            self.retention.policy(namespace).is_reached(record_map.len(), record_map.bytes)
+        })
+    }
+
+    fn write_one(&mut self, namespace: Namespace, record: Record) -> io::Result<()> {
+        // We need to deduplicate messages in the store by their message digest,
+        // which doesn't contain the committed timestamp.
+        let message_digest = record.message_digest(&namespace);
+        let policy = self.retention.policy(&namespace);
+
+        // A timelocked record is written again on its release
+        self.timelocked.remove(&(namespace.clone(), message_digest));
+
+        let record_map =
+            self.record_maps.entry(namespace).or_insert_with(|| RecordMap::with_capacity(self.cap));
+        record_map.insert(message_digest, record);
+        record_map.prune(&policy, Timestamp::now());
+
+        Ok(())
+    }
+
+    fn write_batch(&mut self, namespace: Namespace, records: Vec<Record>) -> io::Result<()> {
+        for record in records {
+            self.write_one(namespace.clone(), record)?;
+        }
+
+        Ok(())
+    }
+
+    fn write_timelocked(
+        &mut self,
+        namespace: Namespace,
+        record: Record,
+        release_after: Timestamp,
+    ) -> io::Result<()> {
+        let message_digest = record.message_digest(&namespace);
+        self.timelocked.insert((namespace, message_digest), (record, release_after));
+
+        Ok(())
+    }
+
+    fn timelocked(&self) -> Vec<(Namespace, Record, Timestamp)> {
+        self.timelocked
+            .iter()
+            .map(|((namespace, _), (record, release_after))| {
+                (namespace.clone(), record.clone(), *release_after)
+            })
+            .collect()
+    }
+}
+
+/// A map of records keyed by message digest, with a secondary index ordered by
+/// timestamp.
+///
+/// When the limits of the retention policy are exceeded, the record that was inserted
+/// first is evicted. Lookups by digest are O(1), and range queries by timestamp are
+/// O(log n + k).
+#[derive(Debug)]
+struct RecordMap {
+    /// The records, keyed by message digest.
+    records: HashMap<B256, Record>,
+    /// The message digests in insertion order.
+    insertion_order: VecDeque<B256>,
+    /// The message digests ordered by record timestamp.
+    by_time: BTreeSet<(Timestamp, B256)>,
+    /// The total size of the messages in the map, in bytes.
+    bytes: u64,
+    /// The timestamp of the latest record pruned from the map, if any.
+    pruned_until: Option<Timestamp>,
+    /// The message digests of the records pruned from the map.
+    tombstones: Tombstones,
+}
+
+impl RecordMap {
+    /// Creates a new record map with room for the given number of records.
+    fn with_capacity(cap: usize) -> Self {
+        Self {
+            records: HashMap::with_capacity(cap),
+            insertion_order: VecDeque::with_capacity(cap),
+            by_time: BTreeSet::new(),
+            bytes: 0,
+            pruned_until: None,
+            tombstones: Tombstones::default(),
+        }
+    }
+
+    /// Returns the number of records in the map.
+    fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    /// Returns the record with the given message digest.
+    fn get(&self, digest: &B256) -> Option<&Record> {
+        self.records.get(digest)
+    }
+
+    /// Returns an iterator over the records within the given timestamps (inclusive),
+    /// ordered by timestamp.
+    ///
+    /// Panics if `start > end`.
+    fn range(&self, start: Timestamp, end: Timestamp) -> impl Iterator<Item = &Record> {
+        let range = (start, B256::ZERO)..=(end, B256::repeat_byte(0xff));
+
+        self.by_time.range(range).filter_map(|(_, digest)| self.records.get(digest))
+    }
+
+    /// Inserts a record, replacing any existing record with the same message digest.
+    fn insert(&mut self, digest: B256, record: Record) {
+        let timestamp = record.timestamp;
+        self.bytes += record.message.0.len() as u64;
+
+        if let Some(old) = self.records.insert(digest, record) {
+            // Keep the original insertion order, only update the time index
+            self.bytes -= old.message.0.len() as u64;
+            self.by_time.remove(&(old.timestamp, digest));
+            self.by_time.insert((timestamp, digest));
+            return
+        }
+
+        self.insertion_order.push_back(digest);
+        self.by_time.insert((timestamp, digest));
+    }
+
+    /// Prunes the record with the given message digest from the map, except from the
+    /// insertion order.
+    fn remove(&mut self, digest: &B256) {
+        let Some(record) = self.records.remove(digest) else { return };
+        self.bytes -= record.message.0.len() as u64;
+        self.by_time.remove(&(record.timestamp, *digest));
+        self.pruned_until = self.pruned_until.max(Some(record.timestamp));
+        self.tombstones.insert(*digest);
+    }
+
+    /// Removes the records that expired at the given time, then evicts the oldest inserted
+    /// records until the limits of the policy are no longer exceeded.
+    fn prune(&mut self, policy: &RetentionPolicy, now: Timestamp) {
+        if let Some(cutoff) = policy.cutoff(now) {
+            let mut expired = false;
+            while let Some(&(timestamp, digest)) = self.by_time.first() {
+                if timestamp >= cutoff {
+                    break
+                }
+
+                self.remove(&digest);
+                expired = true;
+            }
+
+            if expired {
+                self.insertion_order.retain(|digest| self.records.contains_key(digest));
+            }
+        }
+
+        while policy.is_exceeded(self.records.len(), self.bytes) {
+            let Some(oldest) = self.insertion_order.pop_front() else { break };
+            self.remove(&oldest);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::test_utils::signed_record;
+
+    #[test]
+    fn test_range_and_eviction() {
+        let namespace = Namespace::from_static(b"test");
+        let mut store = InMemoryStore::with_capacity(3);
+
+        // Insert out of timestamp order to exercise the time index
+        for timestamp in [40u64, 10, 30, 20] {
+            let record = signed_record(&namespace, timestamp.to_string().into_bytes(), timestamp);
+            store.write_one(namespace.clone(), record).unwrap();
+        }
+
+        // The first inserted record (timestamp 40) was evicted
+        let log = store.read_range(namespace.clone(), 0u64.into(), 100u64.into());
+        let timestamps = log.records.iter().map(|r| r.timestamp).collect::<Vec<_>>();
+        assert_eq!(timestamps, vec![10u64.into(), 20u64.into(), 30u64.into()]);
+
+        let log = store.read_range(namespace.clone(), 15u64.into(), 30u64.into());
+        assert_eq!(log.len(), 2);
+
+        let log = store.read_range(namespace.clone(), 30u64.into(), 15u64.into());
+        assert!(log.is_empty());
+
+        let record = signed_record(&namespace, b"20", 20u64);
+        let msg_id = record.message_digest(&namespace);
+        assert!(store.read_message(namespace.clone(), msg_id).is_some());
+
+        let evicted = signed_record(&namespace, b"40", 40u64);
+        let msg_id = evicted.message_digest(&namespace);
+        assert!(store.read_message(namespace, msg_id).is_none());
+    }
+
+    #[test]
+    fn test_retention() {
+        let (logs, telemetry) = (Namespace::from_static(b"logs"), Namespace::from_static(b"tm"));
+        let mut store = InMemoryStore::with_capacity(3)
+            .with_retention(telemetry.clone(), RetentionPolicy::default().with_max_bytes(4));
+        assert_eq!(store.retention(&logs).max_records, Some(3));
+        assert_eq!(store.retention(&telemetry).max_records, Some(3));
+
+        // Policies without a record limit keep the capacity of the store as one
+        let max_age = RetentionPolicy::default().with_max_age(Duration::from_secs(60));
+        let capped = InMemoryStore::with_capacity(3).with_default_retention(max_age);
+        assert_eq!(capped.retention(&logs), max_age.with_max_records(3));
+        let uncapped = capped.with_default_retention(max_age.with_max_records(10));
+        assert_eq!(uncapped.retention(&logs).max_records, Some(10));
+
+        // Messages of 2 bytes each, so that the telemetry namespace holds 2 of them
+        for timestamp in [10u64, 20, 30] {
+            let record = signed_record(&telemetry, timestamp.to_string().into_bytes(), timestamp);
+            store.write_one(telemetry.clone(), record).unwrap();
+        }
+
+        let log = store.read_range(telemetry.clone(), 0u64.into(), 100u64.into());
+        let timestamps = log.records.iter().map(|r| r.timestamp).collect::<Vec<_>>();
+        assert_eq!(timestamps, vec![20u64.into(), 30u64.into()]);
+        assert!(store.is_full(&telemetry));
+        assert!(!store.is_full(&logs));
+        assert_eq!(store.low_water_mark(&telemetry), Some(10u64.into()));
+        assert_eq!(store.low_water_mark(&logs), None);
+
+        // Only the pruned messages may have been pruned, not the kept or unknown ones
+        let id = |timestamp: u64| {
+            signed_record(&telemetry, timestamp.to_string().into_bytes(), timestamp)
+                .message_digest(&telemetry)
+        };
+        assert!(store.may_have_pruned(&telemetry, id(10)));
+        assert!(!store.may_have_pruned(&telemetry, id(20)));
+        assert!(!store.may_have_pruned(&telemetry, B256::ZERO));
+
+        // Records older than the maximum age are neither read nor kept
+        let now = u128::from(Timestamp::now()) as u64;
+        let max_age = RetentionPolicy::default().with_max_age(Duration::from_secs(60));
+        let mut store = store.with_retention(logs.clone(), max_age);
+        let expired = signed_record(&logs, b"expired", now - 120_000);
+        let msg_id = expired.message_digest(&logs);
+        store.write_one(logs.clone(), expired).unwrap();
+        assert!(store.read_message(logs.clone(), msg_id).is_none());
+        assert!(store.may_have_pruned(&logs, msg_id));
+        assert_eq!(store.low_water_mark(&logs), Some((now - 120_000).into()));
+
+        store.write_one(logs.clone(), signed_record(&logs, b"fresh", now)).unwrap();
+        assert_eq!(store.read_range(logs.clone(), 0u64.into(), Timestamp::now()).len(), 1);
+        assert_eq!(store.record_maps[&logs].len(), 1);
+    }
+}
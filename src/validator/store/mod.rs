@@ -0,0 +1,193 @@
+use std::io;
+
+use alloy::primitives::B256;
+use hashbrown::HashMap;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    common::{Log, Namespace, Record, RetentionPolicy, Timestamp},
+    primitives::bloom::BloomFilter,
+};
+
+mod memory;
+pub use memory::InMemoryStore;
+
+mod filesystem;
+pub use filesystem::FilesystemStore;
+
+/// The number of message IDs the first tombstone filter of a namespace is sized for. Each next
+/// filter is sized for twice as many.
+const TOMBSTONES_CAPACITY: usize = 1024;
+
+/// A data store interface for reading and writing log records.
+///
+/// Every store enforces the retention policy of each namespace: records beyond its limits are
+/// pruned, and expired records are neither read nor kept.
+pub trait DataStore: Send + Sync + Unpin {
+    /// Reads a range of log records from the store within the given timestamps.
+    fn read_range(&self, namespace: Namespace, start: Timestamp, end: Timestamp) -> Log {
+        self.read_page(namespace, start, end, usize::MAX)
+    }
+
+    /// Reads at most `limit` log records from the store within the given timestamps, the
+    /// oldest first.
+    fn read_page(
+        &self,
+        namespace: Namespace,
+        start: Timestamp,
+        end: Timestamp,
+        limit: usize,
+    ) -> Log;
+
+    /// Reads a single log record from the store by its message ID.
+    fn read_message(&self, namespace: Namespace, msg_id: B256) -> Option<Record>;
+
+    /// Returns the namespaces with records in the store.
+    fn namespaces(&self) -> Vec<Namespace>;
+
+    /// Returns the retention policy of the given namespace.
+    fn retention(&self, namespace: &Namespace) -> RetentionPolicy;
+
+    /// Returns the time up to which records of the namespace may have been pruned, if any
+    /// were. A message missing from the store may have been written and pruned, unless it was
+    /// written after that time, which can't be told from its ID.
+    fn low_water_mark(&self, namespace: &Namespace) -> Option<Timestamp>;
+
+    /// Returns true if the record of the message with the given ID may have been pruned from
+    /// the namespace, including if it expired but wasn't pruned yet. False positives are
+    /// possible, but a pruned message is never reported as not pruned.
+    fn may_have_pruned(&self, namespace: &Namespace, msg_id: B256) -> bool;
+
+    /// Returns true if the namespace reached the record or size limit of its retention
+    /// policy, so that writing another record to it prunes one.
+    fn is_full(&self, namespace: &Namespace) -> bool;
+
+    /// Writes a single log record to the store. The record must not be acknowledged to the
+    /// client if this fails.
+    fn write_one(&mut self, namespace: Namespace, record: Record) -> io::Result<()>;
+
+    /// Writes a batch of log records of the namespace to the store, either all of them or none
+    /// of them. The records must not be acknowledged to the client if this fails.
+    fn write_batch(&mut self, namespace: Namespace, records: Vec<Record>) -> io::Result<()>;
+
+    /// Writes a timelocked record to the store, withheld until the given release time. It is
+    /// neither read nor counted against the retention policy until it is written again with
+    /// [`DataStore::write_one`] on its release. The record must not be acknowledged to the
+    /// client if this fails.
+    fn write_timelocked(
+        &mut self,
+        namespace: Namespace,
+        record: Record,
+        release_after: Timestamp,
+    ) -> io::Result<()>;
+
+    /// Returns the timelocked records not released yet, along with their release time, e.g. to
+    /// keep withholding them after a restart.
+    fn timelocked(&self) -> Vec<(Namespace, Record, Timestamp)>;
+}
+
+/// The retention policies of the namespaces of a store.
+#[derive(Debug, Clone, Default)]
+struct Retention {
+    /// The policy of namespaces without their own policy.
+    default: RetentionPolicy,
+    /// The policies of specific namespaces.
+    namespaces: HashMap<Namespace, RetentionPolicy>,
+}
+
+impl Retention {
+    /// Returns the retention policy of the given namespace.
+    fn policy(&self, namespace: &Namespace) -> RetentionPolicy {
+        self.namespaces.get(namespace).copied().unwrap_or(self.default)
+    }
+
+    /// Returns the timestamp before which the records of the given namespace are expired now,
+    /// if they expire.
+    fn cutoff(&self, namespace: &Namespace) -> Option<Timestamp> {
+        self.policy(namespace).cutoff(Timestamp::now())
+    }
+
+    /// Returns true if a record of the given namespace with the given timestamp is expired now.
+    /// Expired records may not have been pruned yet, but are no longer read.
+    fn is_expired(&self, namespace: &Namespace, timestamp: Timestamp) -> bool {
+        self.cutoff(namespace).is_some_and(|cutoff| timestamp < cutoff)
+    }
+
+    /// Returns the start of a read of the given namespace from the given time, past the
+    /// expired records.
+    fn read_start(&self, namespace: &Namespace, start: Timestamp) -> Timestamp {
+        self.cutoff(namespace).map_or(start, |cutoff| start.max(cutoff))
+    }
+
+    /// Returns the low-water mark of the given namespace, given the timestamp of its latest
+    /// pruned record and of its oldest record, if any. Expired records that weren't pruned
+    /// yet count as pruned.
+    fn low_water_mark(
+        &self,
+        namespace: &Namespace,
+        pruned_until: Option<Timestamp>,
+        oldest: Option<Timestamp>,
+    ) -> Option<Timestamp> {
+        let cutoff = self.cutoff(namespace);
+        let expired = oldest.is_some_and(|oldest| self.is_expired(namespace, oldest));
+
+        pruned_until.max(cutoff.filter(|_| expired))
+    }
+}
+
+/// The IDs of the messages pruned from a namespace, summarized in Bloom filters so that each
+/// takes about 10 bits. Once a filter is full, a new one twice as large is started, so that the
+/// false positive rate only grows with the log of the number of pruned messages. A false
+/// positive only keeps the validator from attesting to the unavailability of a message.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct Tombstones {
+    /// The filters, the last of which is being filled.
+    filters: Vec<BloomFilter>,
+    /// The number of IDs inserted into the last filter.
+    len: usize,
+}
+
+impl Tombstones {
+    /// Adds the ID of a pruned message.
+    fn insert(&mut self, msg_id: B256) {
+        let capacity = TOMBSTONES_CAPACITY << self.filters.len().saturating_sub(1);
+        if self.filters.is_empty() || self.len >= capacity {
+            let capacity = TOMBSTONES_CAPACITY << self.filters.len();
+            self.filters.push(BloomFilter::with_capacity(capacity, self.filters.len() as u64));
+            self.len = 0;
+        }
+
+        self.filters.last_mut().expect("Filter started").insert(msg_id);
+        self.len += 1;
+    }
+
+    /// Returns true if the message with the given ID may have been pruned, and false if it
+    /// definitely wasn't.
+    fn contains(&self, msg_id: B256) -> bool {
+        self.filters.iter().any(|filter| filter.contains(msg_id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tombstones() {
+        let ids = (0..3000u64).map(|i| B256::left_padding_from(&i.to_be_bytes()));
+
+        let mut tombstones = Tombstones::default();
+        assert!(!tombstones.contains(B256::ZERO));
+        for id in ids.clone() {
+            tombstones.insert(id);
+        }
+
+        // Filters sized for 1024, then 2048 IDs
+        assert_eq!(tombstones.filters.len(), 2);
+        assert!(ids.clone().all(|id| tombstones.contains(id)));
+
+        let others = (3000..13000u64).map(|i| B256::left_padding_from(&i.to_be_bytes()));
+        let false_positives = others.filter(|id| tombstones.contains(*id)).count();
+        assert!(false_positives < 500, "{false_positives} false positives");
+    }
+}
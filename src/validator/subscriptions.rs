@@ -0,0 +1,254 @@
+use std::task::{Context, Poll};
+
+use blst::min_pk::PublicKey as BlsPublicKey;
+use bytes::Bytes;
+use futures::{future::BoxFuture, stream::FuturesUnordered, StreamExt};
+use hashbrown::HashMap;
+use msg::{tcp::Tcp, Authenticator, PubError, PubSocket, Request as RepRequest};
+use tracing::{debug, error, info};
+
+use super::admission::matches;
+use crate::{
+    common::{Namespace, SubscribeResponse, SubscriptionToken, ValidatorError},
+    primitives::{codec, Response},
+};
+
+/// The namespace of a publisher socket along with the result of binding it.
+type BindResult = (Namespace, Result<PubSocket<Tcp>, PubError>);
+
+/// The publisher sockets of a validator, one per namespace with subscribers.
+///
+/// The publisher socket doesn't expose the topics a connection subscribes to, so each
+/// namespace gets its own socket, which only accepts the tokens issued for that namespace.
+/// Sockets are bound on the first subscription to their namespace.
+pub(crate) struct Publishers {
+    /// The public key of the validator, used to verify the token signatures
+    pubkey: BlsPublicKey,
+    /// The patterns of the private namespaces, along with the subscribers allowed for each
+    private: Vec<(String, Vec<BlsPublicKey>)>,
+    /// The bound publisher sockets, keyed by namespace
+    bound: HashMap<Namespace, PubSocket<Tcp>>,
+    /// The subscribe requests waiting for the socket of their namespace to be bound, along
+    /// with the tokens to respond with
+    waiting: HashMap<Namespace, Vec<(RepRequest, SubscriptionToken)>>,
+    /// The publisher sockets being bound
+    binding: FuturesUnordered<BoxFuture<'static, BindResult>>,
+}
+
+impl Publishers {
+    /// Creates the publishers of the validator with the given public key, with no namespace
+    /// bound yet.
+    pub(crate) fn new(pubkey: BlsPublicKey) -> Self {
+        Self {
+            pubkey,
+            private: Vec::new(),
+            bound: HashMap::new(),
+            waiting: HashMap::new(),
+            binding: FuturesUnordered::new(),
+        }
+    }
+
+    /// Restricts the namespaces matching the given pattern to the given subscribers.
+    pub(crate) fn add_private(&mut self, pattern: String, subscribers: Vec<BlsPublicKey>) {
+        self.private.push((pattern, subscribers));
+    }
+
+    /// Returns the subscribers allowed for the given namespace, or `None` if it is public,
+    /// i.e. it matches none of the private patterns.
+    fn allowed_subscribers(&self, namespace: &Namespace) -> Option<Vec<BlsPublicKey>> {
+        let mut private = self
+            .private
+            .iter()
+            .filter(|(pattern, _)| matches(pattern.as_bytes(), namespace))
+            .peekable();
+        private.peek()?;
+
+        Some(private.flat_map(|(_, subscribers)| subscribers.iter().copied()).collect())
+    }
+
+    /// Returns true if the given subscriber may subscribe to the given namespace.
+    pub(crate) fn is_allowed(&self, namespace: &Namespace, subscriber: &BlsPublicKey) -> bool {
+        self.allowed_subscribers(namespace)
+            .is_none_or(|subscribers| subscribers.contains(subscriber))
+    }
+
+    /// Returns true if the given namespace has a bound publisher socket.
+    pub(crate) fn contains(&self, namespace: &Namespace) -> bool {
+        self.bound.contains_key(namespace)
+    }
+
+    /// Responds to the subscribe request with the given token and the port of the publisher
+    /// socket of its namespace, once bound.
+    pub(crate) fn respond(&mut self, req: RepRequest, token: SubscriptionToken) {
+        if let Some(socket) = self.bound.get(&token.namespace) {
+            let port = socket.local_addr().expect("Publisher bound").port();
+            respond(req, Ok(SubscribeResponse { port, auth_token: token }));
+            return
+        }
+
+        // Bind the socket of the namespace on its first subscription
+        let namespace = token.namespace.clone();
+        if !self.waiting.contains_key(&namespace) {
+            let authenticator = SubscriptionAuthenticator {
+                pubkey: self.pubkey,
+                namespace: namespace.clone(),
+                subscribers: self.allowed_subscribers(&namespace),
+            };
+
+            let namespace = namespace.clone();
+            self.binding.push(Box::pin(async move {
+                let mut socket = PubSocket::new(Tcp::default()).with_auth(authenticator);
+                let result = socket.bind(("0.0.0.0", 0)).await.map(|_| socket);
+                (namespace, result)
+            }));
+        }
+
+        self.waiting.entry(namespace).or_default().push((req, token));
+    }
+
+    /// Publishes the encoded record to the subscribers of the given namespace, if any.
+    pub(crate) fn publish(&self, namespace: &Namespace, record: Bytes) {
+        let Some(socket) = self.bound.get(namespace) else { return };
+
+        info!(?namespace, "Publishing record to subscribers");
+        let topic_string = String::from_utf8_lossy(namespace).to_string();
+        if let Err(err) = socket.try_publish(topic_string, record) {
+            error!(?err, "Failed to publish serialized record to subscriber");
+        }
+    }
+
+    /// Polls the publisher sockets being bound, and responds to the subscribe requests
+    /// waiting for them. Ready once a socket has been bound, or failed to.
+    pub(crate) fn poll_bound(&mut self, cx: &mut Context<'_>) -> Poll<()> {
+        let Poll::Ready(Some((namespace, result))) = self.binding.poll_next_unpin(cx) else {
+            return Poll::Pending
+        };
+
+        let waiting = self.waiting.remove(&namespace).unwrap_or_default();
+        match result {
+            Ok(socket) => {
+                let port = socket.local_addr().expect("Publisher bound").port();
+                info!(?namespace, port, "Bound publisher");
+                self.bound.insert(namespace, socket);
+
+                for (req, auth_token) in waiting {
+                    respond(req, Ok(SubscribeResponse { port, auth_token }));
+                }
+            }
+            Err(err) => {
+                error!(?err, ?namespace, "Failed to bind publisher");
+                for (req, _) in waiting {
+                    respond(
+                        req,
+                        Err(ValidatorError::Internal(format!("Failed to bind publisher: {err}"))),
+                    );
+                }
+            }
+        }
+
+        Poll::Ready(())
+    }
+}
+
+/// Sends the response to a subscribe request.
+fn respond(req: RepRequest, response: Response<SubscribeResponse>) {
+    if let Err(err) = req.respond(Bytes::from(codec::encode(&response))) {
+        error!(?err, "Failed to respond to subscribe request");
+    }
+}
+
+/// Authenticates connections to the publisher socket of a namespace with the subscription
+/// tokens issued by this validator for that namespace. Tokens of subscribers that are not
+/// allowed for the namespace are rejected, even if they were issued before.
+#[derive(Debug)]
+struct SubscriptionAuthenticator {
+    /// The public key of the validator, used to verify the token signatures.
+    pubkey: BlsPublicKey,
+    /// The namespace of the publisher socket.
+    namespace: Namespace,
+    /// The subscribers allowed for the namespace, or `None` if it is public.
+    subscribers: Option<Vec<BlsPublicKey>>,
+}
+
+impl Authenticator for SubscriptionAuthenticator {
+    fn authenticate(&self, id: &Bytes) -> bool {
+        let Some(token) = SubscriptionToken::from_bytes(id) else {
+            debug!("Rejected malformed subscription token");
+            return false
+        };
+
+        if token.namespace != self.namespace {
+            debug!(namespace = ?token.namespace, "Rejected subscription token of another namespace");
+            return false
+        }
+
+        if self
+            .subscribers
+            .as_ref()
+            .is_some_and(|subscribers| !subscribers.contains(&token.subscriber))
+        {
+            debug!(namespace = ?token.namespace, "Rejected subscription token of a subscriber not allowed");
+            return false
+        }
+
+        let valid = token.verify(&self.pubkey);
+        if !valid {
+            debug!(namespace = ?token.namespace, "Rejected invalid or expired subscription token");
+        }
+
+        valid
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{bls::random_bls_secret, Timestamp};
+
+    #[test]
+    fn test_subscription_authenticator() {
+        let secret_key = random_bls_secret();
+        let subscriber = random_bls_secret().sk_to_pk();
+        let expiry = Timestamp::from(u128::from(Timestamp::now()) + 60_000);
+
+        let (a, b) = (Namespace::from_static(b"a"), Namespace::from_static(b"b"));
+        let token = SubscriptionToken::create_signed(a.clone(), subscriber, expiry, &secret_key);
+
+        let authenticator = |namespace: &Namespace, subscribers| SubscriptionAuthenticator {
+            pubkey: secret_key.sk_to_pk(),
+            namespace: namespace.clone(),
+            subscribers,
+        };
+
+        // A token for namespace A can't be used to receive namespace B
+        assert!(authenticator(&a, None).authenticate(&token.to_bytes()));
+        assert!(!authenticator(&b, None).authenticate(&token.to_bytes()));
+
+        // Nor by a subscriber that is not allowed for the namespace
+        assert!(authenticator(&a, Some(vec![subscriber])).authenticate(&token.to_bytes()));
+        let other = random_bls_secret().sk_to_pk();
+        assert!(!authenticator(&a, Some(vec![other])).authenticate(&token.to_bytes()));
+
+        assert!(!authenticator(&a, None).authenticate(&Bytes::from_static(b"malformed")));
+    }
+
+    #[test]
+    fn test_private_namespaces() {
+        let (alice, bob) = (random_bls_secret().sk_to_pk(), random_bls_secret().sk_to_pk());
+
+        let mut publishers = Publishers::new(random_bls_secret().sk_to_pk());
+        publishers.add_private("private/*".to_owned(), vec![alice]);
+        publishers.add_private("private/shared".to_owned(), vec![bob]);
+
+        let public = Namespace::from_static(b"public");
+        assert!(publishers.is_allowed(&public, &alice) && publishers.is_allowed(&public, &bob));
+
+        let private = Namespace::from_static(b"private/alice");
+        assert!(publishers.is_allowed(&private, &alice));
+        assert!(!publishers.is_allowed(&private, &bob));
+
+        // The subscribers of every matching pattern are allowed
+        let shared = Namespace::from_static(b"private/shared");
+        assert!(publishers.is_allowed(&shared, &alice) && publishers.is_allowed(&shared, &bob));
+    }
+}
@@ -0,0 +1,165 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use alloy::primitives::Bytes;
+use blst::min_pk::PublicKey as BlsPublicKey;
+use futures::StreamExt;
+use tracing::{debug, warn};
+
+use crate::{
+    common::{Namespace, Record, SyncResponse, Timestamp},
+    primitives::bls::verify_signature,
+    Client, Registry,
+};
+
+/// How long to wait for the response of another validator to a sync request.
+const SYNC_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// How long the validators of the current epoch are cached for, to authenticate the sync
+/// requests of the other validators without querying the registry for each of them.
+const PEERS_TTL: Duration = Duration::from_secs(1);
+
+/// The public keys of the other validators of the current epoch, along with the time they were
+/// fetched at.
+type CachedPeers = (Vec<BlsPublicKey>, Instant);
+
+/// Records synced from other validators, along with their namespace.
+pub(crate) type SyncedRecords = Vec<(Namespace, Record)>;
+
+/// The records synced in a round, along with the earliest start of the next page of the other
+/// validators, if any of them has more records.
+pub(crate) type SyncedPage = (SyncedRecords, Option<Timestamp>);
+
+/// Fetches the records a validator missed from the other validators of the current epoch in
+/// the registry, e.g. because it was down when they were written, or because the writer
+/// reached a quorum before contacting it.
+///
+/// Each round, the validator sends a filter of the records it holds in each namespace to the
+/// other validators, which respond with the records missing from it. The records keep the
+/// timestamp and signature of the validator that signed them, which is recorded as their
+/// origin.
+#[derive(Clone)]
+pub(crate) struct Syncer {
+    /// The registry to find the other validators in.
+    registry: Arc<dyn Registry + Send + Sync>,
+    /// The connections to the other validators.
+    client: Client,
+    /// The public key of the syncing validator, to recognize it in the registry.
+    pubkey: BlsPublicKey,
+    /// The public keys of the other validators of the current epoch, if fetched yet.
+    peers: Arc<Mutex<Option<CachedPeers>>>,
+}
+
+impl Syncer {
+    /// Creates a syncer for the validator with the given public key.
+    pub(crate) fn new(registry: Arc<dyn Registry + Send + Sync>, pubkey: BlsPublicKey) -> Self {
+        Self { registry, client: Client::new(), pubkey, peers: Arc::new(Mutex::new(None)) }
+    }
+
+    /// Checks whether the given public key is the one registered by another validator of the
+    /// current epoch, i.e. whether it may sync from this validator.
+    pub(crate) async fn check_peer(self, pubkey: BlsPublicKey) -> eyre::Result<bool> {
+        {
+            let peers = self.peers.lock().expect("Peers lock poisoned");
+            if let Some((peers, fetched_at)) = peers.as_ref() {
+                if fetched_at.elapsed() < PEERS_TTL {
+                    return Ok(peers.contains(&pubkey))
+                }
+            }
+        }
+
+        let epoch = self.registry.epoch().await?;
+        let peers = self
+            .registry
+            .validators_at(epoch)
+            .await?
+            .into_iter()
+            .filter(|validator| validator.exists && validator.bls_pub_key != self.pubkey)
+            .map(|validator| validator.bls_pub_key)
+            .collect::<Vec<_>>();
+
+        let is_peer = peers.contains(&pubkey);
+        *self.peers.lock().expect("Peers lock poisoned") = Some((peers, Instant::now()));
+
+        Ok(is_peer)
+    }
+
+    /// Sends the given encoded sync request to the other validators, and returns the records
+    /// they responded with whose signature is valid, attributed to their origin, along with
+    /// where the next page starts. Fails if none of the other validators responded, if there
+    /// are any.
+    pub(crate) async fn run(self, request: Bytes) -> eyre::Result<SyncedPage> {
+        let epoch = self.registry.epoch().await?;
+        let validators = self.registry.validators_at(epoch).await?;
+
+        let own_index = validators
+            .iter()
+            .find(|validator| validator.exists && validator.bls_pub_key == self.pubkey)
+            .map(|validator| validator.index as usize);
+        let peers = validators
+            .into_iter()
+            .filter(|validator| Some(validator.index as usize) != own_index)
+            .collect::<Vec<_>>();
+
+        // Only the connections to the validators whose socket changed are reopened
+        self.client.reconcile(epoch, &peers).await;
+        let validator_set = self.client.validator_set();
+
+        let mut synced = Vec::new();
+        let mut next: Option<Timestamp> = None;
+        let mut responses = 0;
+        let mut sync_responses = self.client.broadcast(request, SYNC_TIMEOUT);
+        while let Some((index, response)) = sync_responses.next().await {
+            let Ok(SyncResponse { records, next: peer_next }) = response else {
+                continue;
+            };
+
+            debug!(len = records.len(), "Got records to sync from validator {index}");
+            responses += 1;
+
+            // The pass goes on until every validator went through its records
+            if let Some(peer_next) = peer_next {
+                next = Some(next.map_or(peer_next, |next| next.min(peer_next)));
+            }
+
+            for (namespace, mut record) in records {
+                // Records the peer synced from elsewhere keep their origin, which must be one of
+                // the validators of the current epoch
+                let pubkey = match record.origin {
+                    Some(origin) if origin == self.pubkey => origin,
+                    Some(origin) => match validator_set.find(&origin) {
+                        Some(validator) => validator.pubkey,
+                        None => {
+                            warn!(
+                                ?origin,
+                                "Record to sync from unknown validator, via validator {index}"
+                            );
+                            continue;
+                        }
+                    },
+                    None => match validator_set.get(index) {
+                        Some(validator) => validator.pubkey,
+                        None => continue,
+                    },
+                };
+
+                if !verify_signature(&record.signature, &pubkey, record.signed_message(&namespace))
+                {
+                    warn!("Invalid signature of record to sync from validator {index}");
+                    continue;
+                }
+
+                record.origin = (pubkey != self.pubkey).then_some(pubkey);
+                synced.push((namespace, record));
+            }
+        }
+
+        if responses == 0 && !peers.is_empty() {
+            eyre::bail!("None of the {} other validators responded", peers.len())
+        }
+
+        Ok((synced, next))
+    }
+}
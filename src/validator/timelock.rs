@@ -0,0 +1,267 @@
+use std::collections::BTreeSet;
+
+use alloy::primitives::B256;
+use hashbrown::HashMap;
+
+use crate::common::{Namespace, Record, Timestamp, ValidatorError};
+
+/// A bound on the timelocked records withheld at once.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct TimelockLimit {
+    /// The maximum number of records.
+    pub(crate) records: usize,
+    /// The maximum total size of their messages, in bytes.
+    pub(crate) bytes: usize,
+}
+
+impl TimelockLimit {
+    /// Returns an error if adding a record of `size` bytes to the given usage exceeds the limit.
+    fn check(&self, usage: Usage, size: usize) -> Result<(), ValidatorError> {
+        if usage.records + 1 > self.records || usage.bytes + size > self.bytes {
+            return Err(ValidatorError::TimelockFull {
+                max_records: self.records as u64,
+                max_bytes: self.bytes as u64,
+            })
+        }
+
+        Ok(())
+    }
+}
+
+/// The number of withheld records and the total size of their messages.
+#[derive(Debug, Default, Clone, Copy)]
+struct Usage {
+    records: usize,
+    bytes: usize,
+}
+
+/// Records written with a release time, which are withheld from readers and subscribers
+/// until then.
+///
+/// Timelocked records are also written to the data store along with their release time, so
+/// that a validator that restarts before their release keeps withholding them, see
+/// [`TimelockedRecords::restore`]. They are written again to the data store when released.
+/// Their number and size are bounded per namespace and in total, so that writers can't
+/// exhaust the memory of the validator.
+#[derive(Debug)]
+pub(crate) struct TimelockedRecords {
+    /// The withheld records along with their release time, keyed by namespace and message ID.
+    records: HashMap<(Namespace, B256), (Timestamp, Record)>,
+    /// The keys of the withheld records, ordered by release time.
+    by_release: BTreeSet<(Timestamp, Namespace, B256)>,
+    /// The limit on the records withheld for each namespace.
+    namespace_limit: TimelockLimit,
+    /// The limit on all the records withheld.
+    total_limit: TimelockLimit,
+    /// The records withheld for each namespace.
+    namespace_usage: HashMap<Namespace, Usage>,
+    /// All the records withheld.
+    total_usage: Usage,
+}
+
+impl TimelockedRecords {
+    /// Creates an empty set of timelocked records, bounded by the given limits.
+    pub(crate) fn new(namespace_limit: TimelockLimit, total_limit: TimelockLimit) -> Self {
+        Self {
+            records: HashMap::new(),
+            by_release: BTreeSet::new(),
+            namespace_limit,
+            total_limit,
+            namespace_usage: HashMap::new(),
+            total_usage: Usage::default(),
+        }
+    }
+
+    /// Returns an error if withholding the record would exceed the limit of its namespace, or
+    /// the total one. A message already withheld is accounted for already.
+    pub(crate) fn check(
+        &self,
+        namespace: &Namespace,
+        record: &Record,
+    ) -> Result<(), ValidatorError> {
+        let msg_id = record.message_digest(namespace);
+        if self.records.contains_key(&(namespace.clone(), msg_id)) {
+            return Ok(())
+        }
+
+        let size = record.message.0.len();
+        let usage = self.namespace_usage.get(namespace).copied().unwrap_or_default();
+        self.namespace_limit.check(usage, size)?;
+        self.total_limit.check(self.total_usage, size)
+    }
+
+    /// Withholds the record until the given release time, replacing any withheld record of
+    /// the same message. Fails if withholding a new record would exceed the limit of its
+    /// namespace, or the total one.
+    pub(crate) fn insert(
+        &mut self,
+        namespace: Namespace,
+        record: Record,
+        release_after: Timestamp,
+    ) -> Result<(), ValidatorError> {
+        self.check(&namespace, &record)?;
+        self.restore(namespace, record, release_after);
+
+        Ok(())
+    }
+
+    /// Withholds a record read back from the data store until the given release time. It was
+    /// within the limits when written, and keeps being withheld even if they shrank since, as
+    /// the validator already signed it.
+    pub(crate) fn restore(
+        &mut self,
+        namespace: Namespace,
+        record: Record,
+        release_after: Timestamp,
+    ) {
+        let msg_id = record.message_digest(&namespace);
+
+        let key = (namespace.clone(), msg_id);
+        if let Some((old_release, _)) = self.records.get(&key) {
+            // The message is already withheld and accounted for, only its release time changes
+            self.by_release.remove(&(*old_release, namespace.clone(), msg_id));
+        } else {
+            self.add_usage(&namespace, record.message.0.len());
+        }
+
+        self.records.insert(key, (release_after, record));
+        self.by_release.insert((release_after, namespace, msg_id));
+    }
+
+    /// Returns the release time of the withheld message with the given ID, if any.
+    pub(crate) fn release_time(&self, namespace: &Namespace, msg_id: B256) -> Option<Timestamp> {
+        self.records.get(&(namespace.clone(), msg_id)).map(|(release_after, _)| *release_after)
+    }
+
+    /// Returns the earliest release time of the withheld records, if any.
+    pub(crate) fn next_release(&self) -> Option<Timestamp> {
+        self.by_release.first().map(|(release_after, _, _)| *release_after)
+    }
+
+    /// Removes and returns the records whose release time is not after `now`, in order of
+    /// release time.
+    pub(crate) fn release(&mut self, now: Timestamp) -> Vec<(Namespace, Record)> {
+        let mut released = Vec::new();
+
+        while let Some((release_after, _, _)) = self.by_release.first() {
+            if *release_after > now {
+                break
+            }
+
+            let (_, namespace, msg_id) = self.by_release.pop_first().expect("Not empty");
+            if let Some((_, record)) = self.records.remove(&(namespace.clone(), msg_id)) {
+                self.remove_usage(&namespace, record.message.0.len());
+                released.push((namespace, record));
+            }
+        }
+
+        released
+    }
+
+    /// Accounts for a new withheld record of `size` bytes in the given namespace.
+    fn add_usage(&mut self, namespace: &Namespace, size: usize) {
+        let usage = self.namespace_usage.entry(namespace.clone()).or_default();
+        usage.records += 1;
+        usage.bytes += size;
+
+        self.total_usage.records += 1;
+        self.total_usage.bytes += size;
+    }
+
+    /// Accounts for the release of a withheld record of `size` bytes in the given namespace.
+    fn remove_usage(&mut self, namespace: &Namespace, size: usize) {
+        if let Some(usage) = self.namespace_usage.get_mut(namespace) {
+            usage.records -= 1;
+            usage.bytes -= size;
+            if usage.records == 0 {
+                self.namespace_usage.remove(namespace);
+            }
+        }
+
+        self.total_usage.records -= 1;
+        self.total_usage.bytes -= size;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy::primitives::Bytes;
+
+    use super::*;
+    use crate::test_utils::signed_record;
+
+    /// A limit high enough not to be reached by the tests.
+    const UNLIMITED: TimelockLimit = TimelockLimit { records: usize::MAX, bytes: usize::MAX };
+
+    #[test]
+    fn test_release_in_order() {
+        let namespace = Namespace::from_static(b"test");
+        let mut timelocked = TimelockedRecords::new(UNLIMITED, UNLIMITED);
+
+        for (message, release_after) in [(&b"late"[..], 300u64), (b"early", 100), (b"mid", 200)] {
+            let record = signed_record(&namespace, message, 10u64);
+            timelocked.insert(namespace.clone(), record, release_after.into()).unwrap();
+        }
+        assert_eq!(timelocked.next_release(), Some(100u64.into()));
+
+        let msg_id = signed_record(&namespace, b"mid", 10u64).message_digest(&namespace);
+        assert_eq!(timelocked.release_time(&namespace, msg_id), Some(200u64.into()));
+
+        // Nothing is released before its time
+        assert!(timelocked.release(99u64.into()).is_empty());
+
+        let released = timelocked.release(200u64.into());
+        let messages = released.iter().map(|(_, r)| r.message.0.clone()).collect::<Vec<_>>();
+        assert_eq!(messages, vec![Bytes::from_static(b"early"), Bytes::from_static(b"mid")]);
+        assert_eq!(timelocked.release_time(&namespace, msg_id), None);
+        assert_eq!(timelocked.next_release(), Some(300u64.into()));
+
+        // Withholding a message again replaces its release time
+        let record = signed_record(&namespace, b"late", 10u64);
+        timelocked.insert(namespace.clone(), record, 400u64.into()).unwrap();
+        assert!(timelocked.release(300u64.into()).is_empty());
+        assert_eq!(timelocked.release(400u64.into()).len(), 1);
+        assert_eq!(timelocked.next_release(), None);
+    }
+
+    #[test]
+    fn test_limits() {
+        let (a, b, c) = (
+            Namespace::from_static(b"a"),
+            Namespace::from_static(b"b"),
+            Namespace::from_static(b"c"),
+        );
+        let namespace_limit = TimelockLimit { records: 2, bytes: 8 };
+        let total_limit = TimelockLimit { records: 3, bytes: 12 };
+        let mut timelocked = TimelockedRecords::new(namespace_limit, total_limit);
+
+        let namespace_full = Err(ValidatorError::TimelockFull { max_records: 2, max_bytes: 8 });
+        let total_full = Err(ValidatorError::TimelockFull { max_records: 3, max_bytes: 12 });
+
+        // The size of a namespace is bounded by count and by bytes
+        timelocked.insert(a.clone(), signed_record(&a, b"one", 10u64), 100u64.into()).unwrap();
+        timelocked.insert(a.clone(), signed_record(&a, b"two", 10u64), 100u64.into()).unwrap();
+        let result = timelocked.insert(a.clone(), signed_record(&a, b"3", 10u64), 100u64.into());
+        assert_eq!(result, namespace_full);
+        let result =
+            timelocked.insert(b.clone(), signed_record(&b, b"too large", 10u64), 100u64.into());
+        assert_eq!(result, namespace_full);
+
+        // Withholding a message again doesn't count twice
+        timelocked.insert(a.clone(), signed_record(&a, b"one", 10u64), 200u64.into()).unwrap();
+
+        // The total is bounded across namespaces
+        timelocked.insert(b.clone(), signed_record(&b, b"four", 10u64), 100u64.into()).unwrap();
+        let result = timelocked.insert(c.clone(), signed_record(&c, b"5", 10u64), 100u64.into());
+        assert_eq!(result, total_full);
+
+        // Released records free their space
+        assert_eq!(timelocked.release(100u64.into()).len(), 2);
+        timelocked.insert(c.clone(), signed_record(&c, b"five", 10u64), 300u64.into()).unwrap();
+        timelocked.insert(a.clone(), signed_record(&a, b"six", 10u64), 300u64.into()).unwrap();
+
+        // Records withheld before a restart are restored beyond the limits
+        timelocked.restore(c.clone(), signed_record(&c, b"seven", 10u64), 300u64.into());
+        assert_eq!(timelocked.release(300u64.into()).len(), 4);
+    }
+}
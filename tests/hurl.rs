@@ -19,7 +19,7 @@ async fn test_api_write_request() -> eyre::Result<()> {
     tokio::spawn(validator);
     info!("Validator listening on: {}", validator_addr);
 
-    let mut client = Client::new();
+    let client = Client::new();
     let identity = ValidatorIdentity::new(0, pubkey);
     client.connect_validator(identity, validator_addr).await?;
     info!("Client connected to validator");
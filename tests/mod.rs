@@ -1,19 +1,28 @@
-use std::time::Duration;
+use std::{collections::HashMap, time::Duration};
 
 use alloy::primitives::B256;
 use bytes::Bytes;
 use futures::StreamExt;
+use msg::{tcp::Tcp, ReqSocket};
 use tokio::time::sleep;
 use tracing::{debug, info};
 
 mod hurl;
 
 mod utils;
-use utils::spin_up_validator;
+use utils::{
+    remove_registry, spin_up_syncing_validators, spin_up_validator,
+    spin_up_validator_with_admission, spin_up_validator_with_key,
+    spin_up_validator_with_private_namespace, spin_up_validator_with_signing_window,
+    spin_up_validator_with_store, write_registry,
+};
 
 use dato::{
-    CertifiedReadMessageResponse, CertifiedUnavailableMessage, Client, ClientSpec, Message,
-    Namespace, Timestamp, ValidatorIdentity,
+    bls::{proof_of_possession, random_bls_secret},
+    codec::{self, WIRE_VERSION},
+    AdmissionPolicy, CertifiedReadMessageResponse, Client, ClientError, ClientSpec,
+    FilesystemRegistry, InMemoryStore, Log, Message, Namespace, ReadError, Registry,
+    RetentionPolicy, Timestamp, Validator, ValidatorError, ValidatorIdentity, MAX_WRITE_BATCH,
 };
 
 #[tokio::test]
@@ -23,20 +32,186 @@ async fn test_write_request() -> eyre::Result<()> {
     let (validator_addr, pubkey) = spin_up_validator().await?;
     info!("Validator listening on: {}", validator_addr);
 
-    let mut client = Client::new();
+    let client = Client::new();
     let identity = ValidatorIdentity::new(0, pubkey);
     client.connect_validator(identity, validator_addr).await?;
     info!("Client connected to validator");
 
-    let namespace = Bytes::from_static(b"test").into();
+    let namespace: Namespace = Bytes::from_static(b"test").into();
     let message = Message(Bytes::from_static(b"made with chatgpt").into());
 
-    let record = client.write(namespace, message.clone()).await?;
+    let record = client.write(namespace.clone(), message.clone()).await?;
     info!(?record, "Wrote record");
 
     assert_eq!(record.timestamps.len(), 1);
     assert_eq!(record.message, message);
 
+    // The certificate is self-describing and can be verified standalone
+    assert_eq!(record.namespace, namespace);
+    record.verify(&client.validator_set())?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_write_batch_request() -> eyre::Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let client = Client::new();
+    for index in 0..3 {
+        let (validator_addr, pubkey) = spin_up_validator().await?;
+        client.connect_validator(ValidatorIdentity::new(index, pubkey), validator_addr).await?;
+    }
+
+    let namespace: Namespace = Bytes::from_static(b"test").into();
+    let messages: Vec<_> = (0..10)
+        .map(|i| Message(Bytes::from(format!("made with chatgpt {i}").into_bytes()).into()))
+        .collect();
+
+    let records = client.write_batch(namespace.clone(), messages.clone()).await?;
+    assert_eq!(records.len(), messages.len());
+
+    // Each record is certified on its own, and the batch is written in order
+    for (record, message) in records.iter().zip(&messages) {
+        assert_eq!(record.message, *message);
+        assert_eq!(record.signers.count(), 2);
+        record.verify(&client.validator_set())?;
+    }
+
+    assert!(records
+        .windows(2)
+        .all(|pair| { pair[0].certified_timestamp() <= pair[1].certified_timestamp() }));
+
+    // Batches larger than validators sign at once are rejected
+    let messages = vec![Message(Bytes::from_static(b"spam").into()); MAX_WRITE_BATCH + 1];
+    let result = client.write_batch(namespace, messages).await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Validator(ValidatorError::BatchTooLarge { len, max }))
+            if len == max + 1 && max == MAX_WRITE_BATCH as u64
+    ));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_write_with_signing_window() -> eyre::Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let client = Client::new();
+    for index in 0..3 {
+        let sk = random_bls_secret();
+        let pubkey = sk.sk_to_pk();
+        let validator_addr =
+            spin_up_validator_with_signing_window(sk, Duration::from_millis(50)).await?;
+        client.connect_validator(ValidatorIdentity::new(index, pubkey), validator_addr).await?;
+    }
+
+    let namespace: Namespace = Bytes::from_static(b"test").into();
+
+    // Concurrent writes are signed together by each validator
+    let writes = (0..10).map(|i| {
+        let client = client.clone();
+        let message = Message(Bytes::from(format!("made with chatgpt {i}").into_bytes()).into());
+        let namespace = namespace.clone();
+        async move { client.write(namespace, message).await }
+    });
+    let records = futures::future::try_join_all(writes).await?;
+
+    for record in &records {
+        record.verify(&client.validator_set())?;
+    }
+    assert!(records.iter().any(|record| record.proofs.iter().any(|proof| !proof.is_empty())));
+
+    // The inclusion proofs are kept with the stored records
+    let msg_id = records[0].msg_id();
+    let CertifiedReadMessageResponse::Available(record) =
+        client.read_message(namespace, msg_id).await?
+    else {
+        panic!("Message should be available");
+    };
+    record.verify(&client.validator_set())?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_read_message_in_signing_window() -> eyre::Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let sk = random_bls_secret();
+    let pubkey = sk.sk_to_pk();
+    let validator_addr = spin_up_validator_with_signing_window(sk, Duration::from_secs(5)).await?;
+
+    let client = Client::new();
+    client.connect_validator(ValidatorIdentity::new(0, pubkey), validator_addr).await?;
+
+    let namespace: Namespace = Bytes::from_static(b"test").into();
+    let message = Message(Bytes::from_static(b"made with chatgpt").into());
+    let write = tokio::spawn({
+        let (client, namespace, message) = (client.clone(), namespace.clone(), message.clone());
+        async move { client.write(namespace, message).await }
+    });
+
+    // The message is read while its write waits for the end of the signing window
+    sleep(Duration::from_millis(100)).await;
+    let msg_id = message.digest(&namespace);
+    let CertifiedReadMessageResponse::Available(read) =
+        client.read_message(namespace, msg_id).await?
+    else {
+        panic!("Pending message should not be attested to be unavailable");
+    };
+
+    // The read signed the pending write early, with the timestamp of its arrival
+    let record = write.await??;
+    assert_eq!(read.timestamps, record.timestamps);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_write_delayed() -> eyre::Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let client = Client::new();
+    for index in 0..3 {
+        let (validator_addr, pubkey) = spin_up_validator().await?;
+        client.connect_validator(ValidatorIdentity::new(index, pubkey), validator_addr).await?;
+    }
+
+    let namespace: Namespace = Bytes::from_static(b"test").into();
+    let mut stream = client.subscribe(namespace.clone()).await?;
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    // The record is certified right away, but withheld until its release time
+    let release_after = Timestamp::from(u128::from(Timestamp::now()) + 500);
+    let message = Message(Bytes::from_static(b"made with chatgpt").into());
+    let record = client.write_delayed(namespace.clone(), message.clone(), release_after).await?;
+    record.verify(&client.validator_set())?;
+    assert!(record.certified_timestamp() < release_after);
+
+    let result = client.read_message(namespace.clone(), record.msg_id()).await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Validator(ValidatorError::Timelocked { release_after: at }))
+            if at == release_after
+    ));
+
+    let log = client.read(namespace.clone(), 0u64.into(), release_after).await?;
+    assert!(log.is_empty());
+
+    // Subscribers receive the record from each validator once it is released
+    let received = stream.next().await.expect("Received message");
+    assert_eq!(received.message, message);
+    assert!(Timestamp::now() >= release_after);
+
+    let CertifiedReadMessageResponse::Available(released) =
+        client.read_message(namespace, record.msg_id()).await?
+    else {
+        panic!("Message should be available after its release");
+    };
+    released.verify(&client.validator_set())?;
+
     Ok(())
 }
 
@@ -47,7 +222,7 @@ async fn test_read_request_single_validator() -> eyre::Result<()> {
     let (validator_addr, pubkey) = spin_up_validator().await?;
     info!("Validator listening on: {}", validator_addr);
 
-    let mut client = Client::new();
+    let client = Client::new();
     let identity = ValidatorIdentity::new(0, pubkey);
     client.connect_validator(identity, validator_addr).await?;
     info!("Client connected to validator");
@@ -85,7 +260,7 @@ async fn test_read_request_multiple_validators() -> eyre::Result<()> {
     let (validator_addr3, pubkey3) = spin_up_validator().await?;
     info!("Validator 3 listening on: {}", validator_addr3);
 
-    let mut client = Client::new();
+    let client = Client::new();
     client.connect_validator(ValidatorIdentity::new(0, pubkey1), validator_addr1).await?;
     client.connect_validator(ValidatorIdentity::new(1, pubkey2), validator_addr2).await?;
     client.connect_validator(ValidatorIdentity::new(2, pubkey3), validator_addr3).await?;
@@ -99,7 +274,8 @@ async fn test_read_request_multiple_validators() -> eyre::Result<()> {
     info!(?record, "Wrote record");
 
     // we expect 2 instead of 3 because the quorum is 2/3
-    assert_eq!(record.timestamps.len(), 2);
+    assert_eq!(record.signers.count(), 2);
+    record.verify(&client.validator_set())?;
 
     sleep(Duration::from_millis(300)).await;
     let end = Timestamp::now();
@@ -119,7 +295,7 @@ async fn test_read_unavailable_message() -> eyre::Result<()> {
     let (validator_addr, pubkey) = spin_up_validator().await?;
     info!("Validator listening on: {}", validator_addr);
 
-    let mut client = Client::new();
+    let client = Client::new();
     client.connect_validator(ValidatorIdentity::new(0, pubkey), validator_addr).await?;
     info!("Client connected to validators");
 
@@ -130,13 +306,62 @@ async fn test_read_unavailable_message() -> eyre::Result<()> {
     info!(?log, "Read log");
 
     match log {
-        CertifiedReadMessageResponse::Unavailable(CertifiedUnavailableMessage { .. }) => {}
+        CertifiedReadMessageResponse::Unavailable(certificate) => {
+            certificate.verify(&client.validator_set())?;
+        }
         _ => eyre::bail!("Expected UnavailableMessage"),
     }
 
     Ok(())
 }
 
+#[tokio::test]
+async fn test_read_unavailable_message_as_of() -> eyre::Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let (validator_addr, pubkey) = spin_up_validator().await?;
+    info!("Validator listening on: {}", validator_addr);
+
+    let client = Client::new();
+    client.connect_validator(ValidatorIdentity::new(0, pubkey), validator_addr).await?;
+    info!("Client connected to validators");
+
+    let namespace: Namespace = Bytes::from_static(b"test").into();
+    let message = Message(Bytes::from_static(b"made with chatgpt").into());
+    let msg_id = message.digest(&namespace);
+
+    let before_write = Timestamp::now();
+    sleep(Duration::from_millis(10)).await;
+    client.write(namespace.clone(), message).await?;
+
+    // The message was written after the query time, so it was unavailable as of then
+    match client.read_message_as_of(namespace.clone(), msg_id, before_write).await? {
+        CertifiedReadMessageResponse::Unavailable(certificate) => {
+            assert_eq!(certificate.certified_timestamp(), before_write);
+            certificate.verify(&client.validator_set())?;
+        }
+        _ => eyre::bail!("Expected UnavailableMessage"),
+    }
+
+    match client.read_message(namespace.clone(), msg_id).await? {
+        CertifiedReadMessageResponse::Available(certificate) => {
+            certificate.verify(&client.validator_set())?;
+        }
+        _ => eyre::bail!("Expected available message"),
+    }
+
+    // Validators can't attest to the unavailability of a message as of a future time
+    let future = Timestamp::from(u128::from(Timestamp::now()) + 60_000);
+    let result = client.read_message_as_of(namespace, B256::ZERO, future).await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Validator(ValidatorError::FutureQueryTime { now }))
+            if now < future
+    ));
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_read_certified() -> eyre::Result<()> {
     let _ = tracing_subscriber::fmt::try_init();
@@ -144,7 +369,7 @@ async fn test_read_certified() -> eyre::Result<()> {
     let (validator_addr, pubkey) = spin_up_validator().await?;
     info!("Validator listening on: {}", validator_addr);
 
-    let mut client = Client::new();
+    let client = Client::new();
     client.connect_validator(ValidatorIdentity::new(0, pubkey), validator_addr).await?;
     info!("Client connected to validators");
 
@@ -168,6 +393,32 @@ async fn test_read_certified() -> eyre::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_error_responses() -> eyre::Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let (validator_addr, _) = spin_up_validator().await?;
+
+    let mut socket = ReqSocket::new(Tcp::default());
+    socket.connect(validator_addr).await?;
+
+    // A request from a newer client is answered with the version the validator supports
+    let response = socket.request(Bytes::from(vec![WIRE_VERSION + 1, 0])).await?;
+    assert_eq!(
+        codec::decode_response::<Log>(&response)?.unwrap_err(),
+        ValidatorError::UnsupportedVersion { version: WIRE_VERSION + 1, supported: WIRE_VERSION }
+    );
+
+    // A request with an unknown tag is malformed
+    let response = socket.request(Bytes::from(vec![WIRE_VERSION, 42])).await?;
+    assert!(matches!(
+        codec::decode_response::<Log>(&response)?,
+        Err(ValidatorError::MalformedRequest(_))
+    ));
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_subscribe() -> eyre::Result<()> {
     let _ = tracing_subscriber::fmt::try_init();
@@ -175,7 +426,7 @@ async fn test_subscribe() -> eyre::Result<()> {
     let (validator_addr, pubkey) = spin_up_validator().await?;
     info!("Validator listening on: {}", validator_addr);
 
-    let mut client = Client::new();
+    let client = Client::new();
     client.connect_validator(ValidatorIdentity::new(0, pubkey), validator_addr).await?;
 
     let namespace: Namespace = Bytes::from_static(b"test").into();
@@ -203,6 +454,39 @@ async fn test_subscribe() -> eyre::Result<()> {
     Ok(())
 }
 
+#[tokio::test]
+async fn test_subscribe_private_namespace() -> eyre::Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let subscriber_key = random_bls_secret();
+    let (validator_addr, pubkey) =
+        spin_up_validator_with_private_namespace("private/*", vec![subscriber_key.sk_to_pk()])
+            .await?;
+
+    let client = Client::new();
+    client.connect_validator(ValidatorIdentity::new(0, pubkey), validator_addr).await?;
+
+    let private: Namespace = Bytes::from_static(b"private/feed").into();
+    let result = client.subscribe(private.clone()).await;
+    assert!(matches!(result, Err(ClientError::Validator(ValidatorError::Unauthorized))));
+
+    // Other namespaces can be subscribed to by anyone
+    client.subscribe(Bytes::from_static(b"public").into()).await?;
+
+    let client = client.with_subscriber_key(subscriber_key);
+    let mut stream = client.subscribe(private.clone()).await?;
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let message = Message(Bytes::from_static(b"private message").into());
+    let record = client.write(private, message).await?;
+
+    let received = stream.next().await.expect("Received message");
+    assert_eq!(received.message, record.message);
+
+    Ok(())
+}
+
 #[tokio::test]
 async fn test_subscribe_certified() -> eyre::Result<()> {
     let _ = tracing_subscriber::fmt::try_init();
@@ -210,7 +494,7 @@ async fn test_subscribe_certified() -> eyre::Result<()> {
     let (validator_addr, pubkey) = spin_up_validator().await?;
     info!("Validator listening on: {}", validator_addr);
 
-    let mut client = Client::new();
+    let client = Client::new();
     client.connect_validator(ValidatorIdentity::new(0, pubkey), validator_addr).await?;
 
     let namespace: Namespace = Bytes::from_static(b"test").into();
@@ -228,3 +512,403 @@ async fn test_subscribe_certified() -> eyre::Result<()> {
 
     Ok(())
 }
+
+#[tokio::test]
+async fn test_subscribe_certified_multiple_validators() -> eyre::Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let client = Client::new();
+    for index in 0..3 {
+        let (validator_addr, pubkey) = spin_up_validator().await?;
+        client.connect_validator(ValidatorIdentity::new(index, pubkey), validator_addr).await?;
+    }
+
+    let namespace: Namespace = Bytes::from_static(b"test").into();
+    let mut stream = client.subscribe_certified(namespace.clone()).await?;
+    info!("Subscribed to certified namespace");
+
+    tokio::time::sleep(Duration::from_millis(300)).await;
+
+    let message = Message(Bytes::from_static(b"made with chatgpt").into());
+    let record = client.write(namespace.clone(), message).await?;
+
+    // The certificate has the same shape as the one returned by the write
+    let received = stream.next().await.expect("Received message");
+    assert_eq!(received.message, record.message);
+    assert_eq!(received.timestamps.len(), record.timestamps.len());
+    assert!(received.signers.count() >= 2);
+    received.verify(&client.validator_set())?;
+
+    // The record from the last validator doesn't produce another certificate
+    let next = tokio::time::timeout(Duration::from_millis(500), stream.next()).await;
+    assert!(next.is_err(), "Message was certified more than once");
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_reconcile_validators_from_registry() -> eyre::Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let (sk1, sk2) = (random_bls_secret(), random_bls_secret());
+    let validator_addr1 = spin_up_validator_with_key(sk1.clone()).await?;
+    let validator_addr2 = spin_up_validator_with_key(sk2.clone()).await?;
+
+    let path = std::env::temp_dir().join(format!("dato-registry-{}.txt", std::process::id()));
+    let line = |index: usize, sk: &blst::min_pk::SecretKey, addr: std::net::SocketAddr| {
+        let pubkey = alloy::hex::encode(sk.sk_to_pk().compress());
+        let proof = alloy::hex::encode(proof_of_possession(sk).compress());
+        format!("{index},0x00,0x{pubkey},{addr},0x{proof}\n")
+    };
+
+    std::fs::write(&path, line(0, &sk1, validator_addr1) + &line(1, &sk2, validator_addr2))?;
+    let registry = FilesystemRegistry::read_from_file(path.clone())?;
+
+    let client = Client::new();
+    let first = registry.epoch().await?;
+    client.reconcile(first, &registry.validators_at(first).await?).await;
+    assert_eq!(client.validator_set().len(), 2);
+    assert_eq!(client.validator_set().epoch(), first);
+
+    let namespace: Namespace = Bytes::from_static(b"test").into();
+    let message = Message(Bytes::from_static(b"made with chatgpt").into());
+    let old_record = client.write(namespace.clone(), message.clone()).await?;
+    assert_eq!(old_record.epoch, first);
+
+    // Validator 1 leaves the set, which starts a new epoch when the file is read again
+    std::fs::write(&path, line(0, &sk1, validator_addr1))?;
+    let epoch = registry.epoch().await?;
+    assert_ne!(epoch, first);
+    client.reconcile(epoch, &registry.validators_at(epoch).await?).await;
+    assert_eq!(client.validator_set().len(), 1);
+    assert_eq!(client.validator_set().epoch(), epoch);
+    assert!(client.validator_addr(1).is_none());
+
+    let record = client.write(namespace, message).await?;
+    assert_eq!(record.epoch, epoch);
+    assert_eq!(record.signers.iter().collect::<Vec<_>>(), vec![0]);
+    record.verify(&client.validator_set())?;
+
+    // The certificate of the previous epoch is verified against the validator set of its epoch
+    assert!(old_record.verify(&client.validator_set()).is_err());
+    old_record.verify(&registry.validator_set(old_record.epoch).await?)?;
+
+    remove_registry(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sync_missed_writes() -> eyre::Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let sks = (0..3).map(|_| random_bls_secret()).collect::<Vec<_>>();
+    let path = std::env::temp_dir().join(format!("dato-sync-{}.txt", std::process::id()));
+    let addrs = spin_up_syncing_validators(&sks, path.clone(), Duration::from_millis(200)).await?;
+
+    // The writer only reaches validators 0 and 1
+    let writer = Client::new();
+    for index in 0..2 {
+        let identity = ValidatorIdentity::new(index, sks[index].sk_to_pk());
+        writer.connect_validator(identity, addrs[index]).await?;
+    }
+
+    let namespace: Namespace = Bytes::from_static(b"test").into();
+    let message = Message(Bytes::from_static(b"made with chatgpt").into());
+    let start = Timestamp::now();
+    let record = writer.write(namespace.clone(), message.clone()).await?;
+    assert_eq!(record.signers.iter().collect::<Vec<_>>(), vec![0, 1]);
+
+    sleep(Duration::from_millis(600)).await;
+
+    // Validator 2 serves the record synced from the others, signed by its origin
+    let registry = FilesystemRegistry::read_from_file(path.clone())?;
+    let reader = Client::new();
+    let validators = registry.validator_set(registry.epoch().await?).await?;
+    reader.set_validators(validators, &HashMap::from([(2, addrs[2])])).await;
+
+    let log = reader.read(namespace.clone(), start, Timestamp::now()).await?;
+    assert_eq!(log.records.len(), 1);
+    assert_eq!(log.records[0].message, message);
+    let origin = log.records[0].origin.expect("Synced record");
+    assert!(origin == sks[0].sk_to_pk() || origin == sks[1].sk_to_pk());
+
+    // Each signer counts once in the certificate of the message
+    let client = Client::new();
+    let epoch = registry.epoch().await?;
+    client.reconcile(epoch, &registry.validators_at(epoch).await?).await;
+
+    let CertifiedReadMessageResponse::Available(certified) =
+        client.read_message(namespace, record.msg_id()).await?
+    else {
+        panic!("Message should be available");
+    };
+    assert_eq!(certified.signers.iter().collect::<Vec<_>>(), vec![0, 1]);
+    certified.verify(&client.validator_set())?;
+
+    remove_registry(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_backfill_late_joiner() -> eyre::Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let sks = (0..3).map(|_| random_bls_secret()).collect::<Vec<_>>();
+    let mut validators = Vec::with_capacity(sks.len());
+    for sk in &sks {
+        validators.push(Validator::new_in_memory(sk.clone(), 0).await?);
+    }
+
+    let addrs = validators
+        .iter()
+        .map(|validator| validator.local_addr().expect("Listening"))
+        .collect::<Vec<_>>();
+
+    // Validators 0 and 1 only serve the sync requests of the validators in their registry
+    let path = std::env::temp_dir().join(format!("dato-backfill-{}.txt", std::process::id()));
+    write_registry(&path, sks.iter().zip(addrs.iter().copied()))?;
+    let registry = FilesystemRegistry::read_from_file(path.clone())?;
+
+    let late_joiner = validators.pop().expect("Three validators");
+    for validator in validators {
+        let registry = FilesystemRegistry::read_from_file(path.clone())?;
+        tokio::spawn(validator.with_sync(registry, Duration::from_secs(60)));
+    }
+
+    let writer = Client::new();
+    for index in 0..2 {
+        let identity = ValidatorIdentity::new(index, sks[index].sk_to_pk());
+        writer.connect_validator(identity, addrs[index]).await?;
+    }
+
+    let namespace: Namespace = Bytes::from_static(b"test").into();
+    let message = Message(Bytes::from_static(b"made with chatgpt").into());
+    let record = writer.write(namespace.clone(), message.clone()).await?;
+    let before_join = Timestamp::now();
+
+    // Validator 2 joins after the write
+    tokio::spawn(
+        late_joiner
+            .with_sync(FilesystemRegistry::read_from_file(path.clone())?, Duration::from_secs(60))
+            .with_backfill(),
+    );
+
+    let reader = Client::new();
+    let validators = registry.validator_set(registry.epoch().await?).await?;
+    reader.set_validators(validators, &HashMap::from([(2, addrs[2])])).await;
+
+    // It never attests to unavailability before it joined
+    let result = reader.read_message_as_of(namespace.clone(), B256::ZERO, before_join).await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Validator(ValidatorError::BeforeJoin { joined_at }))
+            if joined_at > before_join
+    ));
+
+    sleep(Duration::from_millis(300)).await;
+
+    // It serves the record written before it joined, signed by its origin
+    let log = reader.read(namespace.clone(), 0u64.into(), Timestamp::now()).await?;
+    assert_eq!(log.records.len(), 1);
+    assert_eq!(log.records[0].message, message);
+    let origin = log.records[0].origin.expect("Synced record");
+    assert!(origin == sks[0].sk_to_pk() || origin == sks[1].sk_to_pk());
+
+    // Once backfilled, it attests to unavailability as of later times, short of a quorum alone
+    let result = reader.read_message(namespace.clone(), B256::ZERO).await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Read(ReadError::NoQuorum { available: 0, unavailable })) if unavailable > 0
+    ));
+
+    let client = Client::new();
+    let epoch = registry.epoch().await?;
+    client.reconcile(epoch, &registry.validators_at(epoch).await?).await;
+
+    let CertifiedReadMessageResponse::Available(certified) =
+        client.read_message(namespace, record.msg_id()).await?
+    else {
+        panic!("Message should be available");
+    };
+    certified.verify(&client.validator_set())?;
+
+    remove_registry(&path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_sync_rejected_outside_epoch() -> eyre::Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let sks = (0..3).map(|_| random_bls_secret()).collect::<Vec<_>>();
+    let mut validators = Vec::with_capacity(sks.len());
+    for sk in &sks {
+        validators.push(Validator::new_in_memory(sk.clone(), 0).await?);
+    }
+
+    let addrs = validators
+        .iter()
+        .map(|validator| validator.local_addr().expect("Listening"))
+        .collect::<Vec<_>>();
+
+    // Validator 2 considers itself part of the set, but validators 0 and 1 don't
+    let id = std::process::id();
+    let members_path = std::env::temp_dir().join(format!("dato-sync-members-{id}.txt"));
+    write_registry(&members_path, sks.iter().zip(addrs.iter().copied()).take(2))?;
+    let outsider_path = std::env::temp_dir().join(format!("dato-sync-outsider-{id}.txt"));
+    write_registry(&outsider_path, sks.iter().zip(addrs.iter().copied()))?;
+
+    let outsider = validators.pop().expect("Three validators");
+    tokio::spawn(outsider.with_sync(
+        FilesystemRegistry::read_from_file(outsider_path.clone())?,
+        Duration::from_millis(200),
+    ));
+    for validator in validators {
+        let registry = FilesystemRegistry::read_from_file(members_path.clone())?;
+        tokio::spawn(validator.with_sync(registry, Duration::from_secs(60)));
+    }
+
+    let writer = Client::new();
+    for index in 0..2 {
+        let identity = ValidatorIdentity::new(index, sks[index].sk_to_pk());
+        writer.connect_validator(identity, addrs[index]).await?;
+    }
+
+    let namespace: Namespace = Bytes::from_static(b"test").into();
+    let message = Message(Bytes::from_static(b"made with chatgpt").into());
+    let start = Timestamp::now();
+    writer.write(namespace.clone(), message).await?;
+
+    sleep(Duration::from_millis(600)).await;
+
+    // The sync requests of validator 2 are rejected, so it never gets the record
+    let reader = Client::new();
+    reader.connect_validator(ValidatorIdentity::new(2, sks[2].sk_to_pk()), addrs[2]).await?;
+    let log = reader.read(namespace, start, Timestamp::now()).await?;
+    assert!(log.records.is_empty());
+
+    remove_registry(&members_path)?;
+    remove_registry(&outsider_path)?;
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_retention_advertised() -> eyre::Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let namespace: Namespace = Bytes::from_static(b"telemetry").into();
+    let policy = RetentionPolicy::default().with_max_age(Duration::from_secs(600));
+
+    let store = InMemoryStore::with_capacity(4096).with_retention(namespace.clone(), policy);
+    let (validator_addr, pubkey) = spin_up_validator_with_store(store).await?;
+
+    let client = Client::new();
+    client.connect_validator(ValidatorIdentity::new(0, pubkey), validator_addr).await?;
+
+    assert_eq!(client.retention(namespace).await?, vec![(0, policy)]);
+
+    // Other namespaces have the default policy of the store
+    let other = client.retention(Bytes::from_static(b"other").into()).await?;
+    assert_eq!(other, vec![(0, RetentionPolicy::default().with_max_records(4096))]);
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_read_message_outside_retention() -> eyre::Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let (validator_addr, pubkey) =
+        spin_up_validator_with_store(InMemoryStore::with_capacity(1)).await?;
+
+    let client = Client::new();
+    client.connect_validator(ValidatorIdentity::new(0, pubkey), validator_addr).await?;
+
+    // Nothing was pruned yet, so the validator attests to unavailability
+    let namespace: Namespace = Bytes::from_static(b"test").into();
+    let response = client.read_message(namespace.clone(), B256::ZERO).await?;
+    assert!(matches!(response, CertifiedReadMessageResponse::Unavailable(_)));
+
+    let pruned =
+        client.write(namespace.clone(), Message(Bytes::from_static(b"first").into())).await?;
+    client.write(namespace.clone(), Message(Bytes::from_static(b"second").into())).await?;
+
+    // The pruned message is not claimed to be unavailable
+    let result = client.read_message(namespace.clone(), pruned.msg_id()).await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Read(ReadError::OutsideRetention { low_water_mark }))
+            if low_water_mark == pruned.certified_timestamp()
+    ));
+
+    // But a message that was never seen still is
+    let response = client.read_message(namespace.clone(), B256::ZERO).await?;
+    assert!(matches!(response, CertifiedReadMessageResponse::Unavailable(_)));
+
+    Ok(())
+}
+
+#[tokio::test]
+async fn test_write_admission() -> eyre::Result<()> {
+    let _ = tracing_subscriber::fmt::try_init();
+
+    let policy = AdmissionPolicy::default()
+        .with_max_message_bytes(8)
+        .with_max_namespace_len(16)
+        .with_namespace_pattern("telemetry/*")
+        .with_max_namespaces(2);
+    let (validator_addr, pubkey) = spin_up_validator_with_admission(policy).await?;
+
+    let client = Client::new();
+    client.connect_validator(ValidatorIdentity::new(0, pubkey), validator_addr).await?;
+
+    let message = Message(Bytes::from_static(b"hello").into());
+    let cpu: Namespace = Bytes::from_static(b"telemetry/cpu").into();
+    client.write(cpu.clone(), message.clone()).await?;
+
+    let result = client.write(cpu.clone(), Message(Bytes::from_static(b"too large").into())).await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Validator(ValidatorError::MessageTooLarge { size: 9, max: 8 }))
+    ));
+
+    let result = client.write(Bytes::from_static(b"spam").into(), message.clone()).await;
+    assert!(matches!(result, Err(ClientError::Validator(ValidatorError::NamespaceRejected))));
+
+    let result =
+        client.write(Bytes::from_static(b"telemetry/cpu/core-1").into(), message.clone()).await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Validator(ValidatorError::NamespaceTooLong { len: 20, max: 16 }))
+    ));
+
+    // A batch is rejected as a whole if any of its messages is too large
+    let batch = vec![message.clone(), Message(Bytes::from_static(b"too large").into())];
+    let result = client.write_batch(cpu.clone(), batch).await;
+    assert!(matches!(result, Err(ClientError::Validator(ValidatorError::MessageTooLarge { .. }))));
+
+    // Only the namespaces already written to are accepted once the maximum is reached
+    client.write(Bytes::from_static(b"telemetry/mem").into(), message.clone()).await?;
+    let result = client.write(Bytes::from_static(b"telemetry/disk").into(), message.clone()).await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Validator(ValidatorError::TooManyNamespaces { max: 2 }))
+    ));
+    client.write(cpu, message).await?;
+
+    let result = client.subscribe(Bytes::from_static(b"spam").into()).await;
+    assert!(matches!(result, Err(ClientError::Validator(ValidatorError::NamespaceRejected))));
+
+    // Subscribing to a new namespace counts against the maximum as well
+    let result = client.subscribe(Bytes::from_static(b"telemetry/disk").into()).await;
+    assert!(matches!(
+        result,
+        Err(ClientError::Validator(ValidatorError::TooManyNamespaces { max: 2 }))
+    ));
+
+    Ok(())
+}
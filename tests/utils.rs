@@ -1,14 +1,127 @@
-use std::net::SocketAddr;
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
-use blst::min_pk::PublicKey as BlsPublicKey;
-use dato::{bls::random_bls_secret, Validator};
+use blst::min_pk::{PublicKey as BlsPublicKey, SecretKey as BlsSecretKey};
+use dato::{
+    bls::{proof_of_possession, random_bls_secret},
+    AdmissionPolicy, DataStore, FilesystemRegistry, Validator,
+};
 
 pub async fn spin_up_validator() -> eyre::Result<(SocketAddr, BlsPublicKey)> {
     let dummy_sk = random_bls_secret();
     let pubkey = dummy_sk.sk_to_pk();
-    let validator = Validator::new_in_memory(dummy_sk, 0).await?;
+    let validator_addr = spin_up_validator_with_key(dummy_sk).await?;
+
+    Ok((validator_addr, pubkey))
+}
+
+pub async fn spin_up_validator_with_key(sk: BlsSecretKey) -> eyre::Result<SocketAddr> {
+    spin_up_validator_with_signing_window(sk, Duration::ZERO).await
+}
+
+pub async fn spin_up_validator_with_store<DS: DataStore + 'static>(
+    store: DS,
+) -> eyre::Result<(SocketAddr, BlsPublicKey)> {
+    let sk = random_bls_secret();
+    let pubkey = sk.sk_to_pk();
+    let validator = Validator::new(store, sk, 0).await?;
+    let validator_addr = validator.local_addr().expect("Listening");
+    tokio::spawn(validator);
+
+    Ok((validator_addr, pubkey))
+}
+
+pub async fn spin_up_validator_with_admission(
+    policy: AdmissionPolicy,
+) -> eyre::Result<(SocketAddr, BlsPublicKey)> {
+    let sk = random_bls_secret();
+    let pubkey = sk.sk_to_pk();
+    let validator = Validator::new_in_memory(sk, 0).await?.with_admission(policy);
+    let validator_addr = validator.local_addr().expect("Listening");
+    tokio::spawn(validator);
+
+    Ok((validator_addr, pubkey))
+}
+
+pub async fn spin_up_validator_with_private_namespace(
+    pattern: &str,
+    subscribers: Vec<BlsPublicKey>,
+) -> eyre::Result<(SocketAddr, BlsPublicKey)> {
+    let sk = random_bls_secret();
+    let pubkey = sk.sk_to_pk();
+    let validator =
+        Validator::new_in_memory(sk, 0).await?.with_private_namespace(pattern, subscribers);
     let validator_addr = validator.local_addr().expect("Listening");
     tokio::spawn(validator);
 
     Ok((validator_addr, pubkey))
 }
+
+pub async fn spin_up_validator_with_signing_window(
+    sk: BlsSecretKey,
+    window: Duration,
+) -> eyre::Result<SocketAddr> {
+    let validator = Validator::new_in_memory(sk, 0).await?.with_signing_window(window);
+    let validator_addr = validator.local_addr().expect("Listening");
+    tokio::spawn(validator);
+
+    Ok(validator_addr)
+}
+
+/// Spins up validators with the given keys, which sync missed records with each other at the
+/// given interval, using a registry file at the given path.
+pub async fn spin_up_syncing_validators(
+    sks: &[BlsSecretKey],
+    path: PathBuf,
+    interval: Duration,
+) -> eyre::Result<Vec<SocketAddr>> {
+    let mut validators = Vec::with_capacity(sks.len());
+    for sk in sks {
+        validators.push(Validator::new_in_memory(sk.clone(), 0).await?);
+    }
+
+    let addrs = validators
+        .iter()
+        .map(|validator| validator.local_addr().expect("Listening"))
+        .collect::<Vec<_>>();
+
+    write_registry(&path, sks.iter().zip(addrs.iter().copied()))?;
+
+    for validator in validators {
+        let registry = FilesystemRegistry::read_from_file(path.clone())?;
+        tokio::spawn(validator.with_sync(registry, interval));
+    }
+
+    Ok(addrs)
+}
+
+/// Writes a registry file at the given path, with the validators of the given keys listening
+/// at the given addresses, indexed in order.
+pub fn write_registry<'a>(
+    path: &Path,
+    validators: impl IntoIterator<Item = (&'a BlsSecretKey, SocketAddr)>,
+) -> eyre::Result<()> {
+    let lines = validators.into_iter().enumerate().map(|(index, (sk, addr))| {
+        let pubkey = alloy::hex::encode(sk.sk_to_pk().compress());
+        let proof = alloy::hex::encode(proof_of_possession(sk).compress());
+        format!("{index},0x00,0x{pubkey},{addr},0x{proof}\n")
+    });
+    std::fs::write(path, lines.collect::<String>())?;
+
+    Ok(())
+}
+
+/// Removes the registry file at the given path, along with the history of its epochs written
+/// next to it.
+pub fn remove_registry(path: &Path) -> eyre::Result<()> {
+    let mut history = path.as_os_str().to_owned();
+    history.push(".epochs");
+
+    std::fs::remove_file(history)?;
+    std::fs::remove_file(path)?;
+
+    Ok(())
+}